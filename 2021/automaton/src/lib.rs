@@ -0,0 +1,291 @@
+// A small cellular-automaton engine: a grid, how its cells see each other,
+// and rule sets that advance it one step at a time.
+
+use anyhow::{bail, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edges {
+    Bounded,
+    Toroidal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Neighbourhood {
+    VonNeumann,
+    Moore,
+    Custom(Vec<(i32, i32)>),
+}
+
+impl Neighbourhood {
+    pub fn offsets(&self) -> Vec<(i32, i32)> {
+        match self {
+            Neighbourhood::VonNeumann => vec![(-1, 0), (0, -1), (0, 1), (1, 0)],
+            Neighbourhood::Moore => vec![
+                (-1, -1), (-1, 0), (-1, 1),
+                (0, -1), (0, 1),
+                (1, -1), (1, 0), (1, 1),
+            ],
+            Neighbourhood::Custom(offsets) => offsets.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<T> {
+    pub cells: Vec<T>,
+    pub rows: usize,
+    pub cols: usize,
+    pub edges: Edges,
+}
+
+impl<T: Clone> Grid<T> {
+    pub fn new(rows: Vec<Vec<T>>, edges: Edges) -> Result<Self> {
+        let Some(cols) = rows.first().map(|r| r.len()) else {
+            bail!("the grid has no rows");
+        };
+        if let Some(i) = rows.iter().position(|r| r.is_empty()) {
+            bail!("row {} has no cells", i + 1);
+        }
+        if let Some(i) = rows.iter().position(|r| r.len() != cols) {
+            bail!("row {} is {} wide, the first is {}", i + 1, rows[i].len(), cols);
+        }
+        Ok(Grid {
+            rows: rows.len(),
+            cols,
+            cells: rows.into_iter().flatten().collect(),
+            edges,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn get(&self, i: usize, j: usize) -> &T {
+        &self.cells[i * self.cols + j]
+    }
+
+    // the cell at `offset` from `idx`, if it is on the grid
+    pub fn offset(&self, idx: usize, (di, dj): (i32, i32)) -> Option<usize> {
+        let (i, j) = ((idx / self.cols) as i64, (idx % self.cols) as i64);
+        let (rows, cols) = (self.rows as i64, self.cols as i64);
+        let (mut ni, mut nj) = (i + di as i64, j + dj as i64);
+        match self.edges {
+            Edges::Toroidal => {
+                ni = ni.rem_euclid(rows);
+                nj = nj.rem_euclid(cols);
+            }
+            Edges::Bounded => {
+                if ni < 0 || nj < 0 || ni >= rows || nj >= cols {
+                    return None;
+                }
+            }
+        }
+        Some((ni * cols + nj) as usize)
+    }
+
+    pub fn row(&self, i: usize) -> &[T] {
+        &self.cells[i * self.cols..(i + 1) * self.cols]
+    }
+}
+
+pub trait Rules {
+    type Cell: Clone;
+
+    // advances the grid one step and returns how many cells were active
+    // (flashed, moved, ...) during it
+    fn step(&self, grid: &mut Grid<Self::Cell>) -> usize;
+
+    // whether a step with this much activity is the synchronised state
+    // the simulation is waiting for; by default every cell acted at once
+    fn is_synchronised(&self, grid: &Grid<Self::Cell>, activity: usize) -> bool {
+        activity == grid.len()
+    }
+}
+
+// every cell gains `increment`; cells above `threshold` flash, giving `spread`
+// to their neighbours (who may flash in turn), and end the step at `reset`
+#[derive(Debug, Clone)]
+pub struct FlashRules {
+    pub threshold: i32,
+    pub increment: i32,
+    pub spread: i32,
+    pub reset: i32,
+    pub neighbourhood: Neighbourhood,
+}
+
+impl FlashRules {
+    pub fn dumbo_octopus() -> Self {
+        FlashRules {
+            threshold: 9,
+            increment: 1,
+            spread: 1,
+            reset: 0,
+            neighbourhood: Neighbourhood::Moore,
+        }
+    }
+}
+
+impl Rules for FlashRules {
+    type Cell = i32;
+
+    fn step(&self, grid: &mut Grid<i32>) -> usize {
+        let offsets = self.neighbourhood.offsets();
+        let mut flashed = vec![false; grid.len()];
+        let mut queue = Vec::new();
+
+        for (idx, cell) in grid.cells.iter_mut().enumerate() {
+            *cell += self.increment;
+            if *cell > self.threshold {
+                flashed[idx] = true;
+                queue.push(idx);
+            }
+        }
+
+        let mut flashes = 0;
+        while let Some(idx) = queue.pop() {
+            flashes += 1;
+            for &offset in &offsets {
+                let Some(n) = grid.offset(idx, offset) else {
+                    continue;
+                };
+                if flashed[n] {
+                    continue;
+                }
+                grid.cells[n] += self.spread;
+                if grid.cells[n] > self.threshold {
+                    flashed[n] = true;
+                    queue.push(n);
+                }
+            }
+        }
+
+        for (cell, _) in grid.cells.iter_mut().zip(&flashed).filter(|(_, f)| **f) {
+            *cell = self.reset;
+        }
+        flashes
+    }
+}
+
+// each species, in order, moves all its members one `offset` at once
+// into cells that were `empty` when its turn started
+#[derive(Debug, Clone)]
+pub struct HerdRules {
+    pub species: Vec<(char, (i32, i32))>,
+    pub empty: char,
+}
+
+impl HerdRules {
+    pub fn sea_cucumbers() -> Self {
+        HerdRules {
+            species: vec![('>', (0, 1)), ('v', (1, 0))],
+            empty: '.',
+        }
+    }
+}
+
+impl Rules for HerdRules {
+    type Cell = char;
+
+    fn step(&self, grid: &mut Grid<char>) -> usize {
+        let mut moves = 0;
+        for &(kind, offset) in &self.species {
+            let movers: Vec<(usize, usize)> = (0..grid.len())
+                .filter(|&idx| grid.cells[idx] == kind)
+                .filter_map(|idx| grid.offset(idx, offset).map(|to| (idx, to)))
+                .filter(|&(_, to)| grid.cells[to] == self.empty)
+                .collect();
+            for &(from, to) in &movers {
+                grid.cells[from] = self.empty;
+                grid.cells[to] = kind;
+            }
+            moves += movers.len();
+        }
+        moves
+    }
+
+    // the herd is settled once nothing moves
+    fn is_synchronised(&self, _grid: &Grid<char>, activity: usize) -> bool {
+        activity == 0
+    }
+}
+
+pub struct Automaton<R: Rules> {
+    pub grid: Grid<R::Cell>,
+    pub rules: R,
+    pub steps: usize,
+}
+
+impl<R: Rules> Automaton<R> {
+    pub fn new(grid: Grid<R::Cell>, rules: R) -> Self {
+        Automaton { grid, rules, steps: 0 }
+    }
+
+    pub fn step(&mut self) -> usize {
+        self.steps += 1;
+        self.rules.step(&mut self.grid)
+    }
+
+    // activity of each of the next `steps` steps
+    pub fn run(&mut self, steps: usize) -> Vec<usize> {
+        (0..steps).map(|_| self.step()).collect()
+    }
+
+    // keeps stepping until a synchronised step, giving its 1-based number
+    pub fn first_synchronised(&mut self, max_steps: usize) -> Option<usize> {
+        while self.steps < max_steps {
+            let activity = self.step();
+            if self.rules.is_synchronised(&self.grid, activity) {
+                return Some(self.steps);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn octopuses(text: &str, edges: Edges) -> Grid<i32> {
+        Grid::new(text.lines().map(|l| l.chars().map(|c| c.to_digit(10).unwrap() as i32).collect()).collect(), edges).unwrap()
+    }
+
+    #[test]
+    fn rejects_ragged_and_empty_rows() {
+        assert_eq!(Grid::new(vec![vec![1, 2], vec![3]], Edges::Bounded).unwrap_err().to_string(), "row 2 is 1 wide, the first is 2");
+        assert_eq!(Grid::new(vec![vec![1], vec![]], Edges::Bounded).unwrap_err().to_string(), "row 2 has no cells");
+        assert_eq!(Grid::<i32>::new(vec![vec![]], Edges::Toroidal).unwrap_err().to_string(), "row 1 has no cells");
+        assert_eq!(Grid::<i32>::new(vec![], Edges::Bounded).unwrap_err().to_string(), "the grid has no rows");
+    }
+
+    #[test]
+    fn offsets_follow_the_edges() {
+        let bounded = octopuses("123\n456", Edges::Bounded);
+        assert_eq!(bounded.offset(0, (-1, 0)), None);
+        assert_eq!(bounded.offset(0, (1, 1)), Some(4));
+        let toroidal = Grid { edges: Edges::Toroidal, ..bounded };
+        assert_eq!(toroidal.offset(0, (-1, -1)), Some(5));
+        assert_eq!(toroidal.row(1), &[4, 5, 6]);
+    }
+
+    #[test]
+    fn small_flash_example() {
+        let mut octopuses = Automaton::new(octopuses("11111\n19991\n19191\n19991\n11111", Edges::Bounded), FlashRules::dumbo_octopus());
+        assert_eq!(octopuses.run(2), vec![9, 0]);
+        assert_eq!(octopuses.grid, self::octopuses("45654\n51115\n61116\n51115\n45654", Edges::Bounded));
+    }
+
+    #[test]
+    fn sea_cucumbers_wrap_round() {
+        let row = |s: &str| s.chars().collect::<Vec<char>>();
+        let grid = Grid::new(vec![row("...>>>>>...")], Edges::Toroidal).unwrap();
+        let mut herd = Automaton::new(grid, HerdRules::sea_cucumbers());
+        assert_eq!(herd.run(2), vec![1, 2]);
+        assert_eq!(herd.grid.row(0), row("...>>>.>.>.").as_slice());
+    }
+}
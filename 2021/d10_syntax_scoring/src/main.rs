@@ -1,7 +1,5 @@
-use anyhow::Result;
-use std::collections::{HashMap, VecDeque};
+use anyhow::{anyhow, bail, Result};
 use std::fs::File;
-use std::hash::Hash;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 
@@ -12,83 +10,325 @@ fn parse(path: &Path) -> Result<Vec<String>> {
     reader.lines().collect::<Result<Vec<_>, _>>().map_err(Into::into)
 }
 
-enum Error {
-    Expected(char),
-    Incomplete(String)
+#[derive(Debug, Clone, Copy)]
+struct BracketPair {
+    open: char,
+    close: char,
+    error_score: i64,
+    completion_score: i64,
 }
 
-fn get_mistake(s: &str) -> Error {
-    let com = HashMap::from([
-        (')', '('),
-        ('>', '<'),
-        ('}', '{'),
-        (']', '['),
-    ]);
-
-    let mut stack = VecDeque::<char>::new();
-    for chr in s.chars() {
-        if let Some(v) = com.get(&chr) {
-            if v != stack.back().unwrap() {
-                return Error::Expected(chr);
+#[derive(Debug, Clone)]
+struct Language {
+    pairs: Vec<BracketPair>,
+    completion_base: i64,
+}
+
+impl Default for Language {
+    fn default() -> Self {
+        Language::from_spec("()=3/1,[]=57/2,{}=1197/3,<>=25137/4").unwrap()
+    }
+}
+
+impl Language {
+    // "()=3/1,[]=57/2": pairs with their error and completion scores
+    fn from_spec(spec: &str) -> Result<Self> {
+        let mut pairs = Vec::new();
+        for item in spec.split(',') {
+            let (brackets, scores) = item.split_once('=').ok_or_else(|| anyhow!("missing '=' in {:?}", item))?;
+            let (error, completion) = scores.split_once('/').ok_or_else(|| anyhow!("missing '/' in {:?}", item))?;
+            let brackets: Vec<char> = brackets.chars().collect();
+            if brackets.len() != 2 || brackets[0] == brackets[1] {
+                bail!("{:?} is not an open/close pair", item);
+            }
+            pairs.push(BracketPair {
+                open: brackets[0],
+                close: brackets[1],
+                error_score: error.parse()?,
+                completion_score: completion.parse()?,
+            });
+        }
+        Ok(Language { pairs, completion_base: 5 })
+    }
+
+    fn opening(&self, c: char) -> Option<&BracketPair> {
+        self.pairs.iter().find(|p| p.open == c)
+    }
+
+    fn closing(&self, c: char) -> Option<&BracketPair> {
+        self.pairs.iter().find(|p| p.close == c)
+    }
+
+    fn matches(&self, open: char, close: char) -> bool {
+        self.opening(open).is_some_and(|p| p.close == close)
+    }
+
+    fn check(&self, s: &str) -> Diagnostic {
+        let mut stack: Vec<&BracketPair> = Vec::new();
+        for (column, chr) in s.chars().enumerate() {
+            if let Some(pair) = self.opening(chr) {
+                stack.push(pair);
+            } else if self.closing(chr).is_some() {
+                match stack.pop() {
+                    Some(open) if open.close == chr => {}
+                    open => {
+                        return Diagnostic::Corrupted {
+                            column,
+                            expected: open.map(|p| p.close),
+                            found: chr,
+                        }
+                    }
+                }
+            } else {
+                return Diagnostic::Unknown { column, found: chr };
             }
+        }
 
-            stack.pop_back();
+        if stack.is_empty() {
+            Diagnostic::Valid
         } else {
-            stack.push_back(chr);
+            Diagnostic::Incomplete { completion: stack.iter().rev().map(|p| p.close).collect() }
+        }
+    }
+
+    fn error_score(&self, diagnostic: &Diagnostic) -> Option<i64> {
+        match diagnostic {
+            Diagnostic::Corrupted { found, .. } => self.closing(*found).map(|p| p.error_score),
+            _ => None,
+        }
+    }
+
+    fn completion_score(&self, diagnostic: &Diagnostic) -> Option<i64> {
+        match diagnostic {
+            Diagnostic::Incomplete { completion } => Some(completion.chars().fold(0, |acc, c| {
+                acc * self.completion_base + self.closing(c).unwrap().completion_score
+            })),
+            _ => None,
+        }
+    }
+
+    // fewest insertions and deletions that make the line valid;
+    // cost[i][j] is that number for the chars in i..j
+    fn repair(&self, s: &str) -> Repair {
+        let chars: Vec<char> = s.chars().collect();
+        let n = chars.len();
+        let mut cost = vec![vec![0usize; n + 1]; n + 1];
+        for len in 1..=n {
+            for i in 0..=n - len {
+                let j = i + len;
+                let mut best = 1 + cost[i + 1][j];
+                for k in i + 1..j {
+                    if self.matches(chars[i], chars[k]) {
+                        best = best.min(cost[i + 1][k] + cost[k + 1][j]);
+                    }
+                }
+                cost[i][j] = best;
+            }
+        }
+
+        let mut edits = Vec::new();
+        self.rebuild(&chars, &cost, 0, n, &mut edits);
+        edits.sort_by_key(|e| match e {
+            Edit::Insert { column, .. } => (*column, 0),
+            Edit::Delete { column, .. } => (*column, 1),
+        });
+
+        let mut repaired = String::new();
+        let mut next = edits.iter().peekable();
+        for (column, chr) in chars.iter().map(Some).chain([None]).enumerate() {
+            let mut deleted = false;
+            while let Some(edit) = next.next_if(|e| e.column() == column) {
+                match edit {
+                    Edit::Insert { ch, .. } => repaired.push(*ch),
+                    Edit::Delete { .. } => deleted = true,
+                }
+            }
+            if let (Some(chr), false) = (chr, deleted) {
+                repaired.push(*chr);
+            }
         }
+        Repair { edits, repaired }
     }
 
-    Error::Incomplete(stack.iter().collect())
+    // an unmatched opener gets its closer at the end of its span,
+    // an unmatched closer (or unknown char) is dropped
+    fn rebuild(&self, chars: &[char], cost: &[Vec<usize>], i: usize, j: usize, edits: &mut Vec<Edit>) {
+        if i >= j {
+            return;
+        }
+        for k in i + 1..j {
+            if self.matches(chars[i], chars[k]) && cost[i][j] == cost[i + 1][k] + cost[k + 1][j] {
+                self.rebuild(chars, cost, i + 1, k, edits);
+                self.rebuild(chars, cost, k + 1, j, edits);
+                return;
+            }
+        }
+        // inner spans first, so closers inserted at the same column nest properly
+        self.rebuild(chars, cost, i + 1, j, edits);
+        match self.opening(chars[i]) {
+            Some(pair) => edits.push(Edit::Insert { column: j, ch: pair.close }),
+            None => edits.push(Edit::Delete { column: i, ch: chars[i] }),
+        }
+    }
 }
-fn part_1(lines: &Vec<String>) -> i32 {
-    let scores = HashMap::from([
-        (')', 3),
-        (']', 57),
-        ('}', 1197),
-        ('>', 25137),
-    ]);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Diagnostic {
+    Valid,
+    Corrupted { column: usize, expected: Option<char>, found: char },
+    Unknown { column: usize, found: char },
+    Incomplete { completion: String },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Edit {
+    // insert before the char at `column`
+    Insert { column: usize, ch: char },
+    Delete { column: usize, ch: char },
+}
+
+impl Edit {
+    fn column(&self) -> usize {
+        match self {
+            Edit::Insert { column, .. } | Edit::Delete { column, .. } => *column,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Repair {
+    edits: Vec<Edit>,
+    repaired: String,
+}
+
+fn part_1(language: &Language, lines: &[String]) -> i64 {
     lines
         .iter()
-        .filter_map(|x|match get_mistake(x) {
-            Error::Expected(c) => Some(c),
-            Error::Incomplete(_) => None
-        })
-        .filter_map(|x|scores.get(&x))
+        .filter_map(|x| language.error_score(&language.check(x)))
         .sum()
 }
 
-fn part_2(lines: &Vec<String>) -> i64 {
-    let scores = HashMap::from([
-        ('(', 1),
-        ('[', 2),
-        ('{', 3),
-        ('<', 4),
-    ]);
-
-    let mut scores: Vec<i64> =
-    lines
+fn part_2(language: &Language, lines: &[String]) -> Option<i64> {
+    let mut scores: Vec<i64> = lines
         .iter()
-        .filter_map(|x|match get_mistake(x) {
-            Error::Expected(_) => None,
-            Error::Incomplete(s) => Some(s)
-        })
-        .map(|s|
-            s.chars()
-                .rev()
-                .fold(0, |acc, chr| acc * 5 + scores.get(&chr).unwrap()))
+        .filter_map(|x| language.completion_score(&language.check(x)))
         .collect();
 
     scores.sort();
-    scores.get(scores.len() / 2).unwrap().clone()
+    scores.get(scores.len() / 2).copied()
 }
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let language = match args.iter().position(|a| a == "--language") {
+        Some(i) => Language::from_spec(args.get(i + 1).ok_or_else(|| anyhow!("--language needs a spec"))?)?,
+        None => Language::default(),
+    };
 
-    let lines = parse(&Path::new("input.txt"))?;
-    let p1 = part_1(&lines);
-    println!("Part 1 {}", p1);
+    let lines = parse(Path::new("input.txt"))?;
+    if args.iter().any(|a| a == "--diagnose") {
+        for (nr, line) in lines.iter().enumerate() {
+            match language.check(line) {
+                Diagnostic::Valid => println!("{}: valid", nr + 1),
+                Diagnostic::Corrupted { column, expected: Some(e), found } => {
+                    println!("{}:{}: expected {}, found {}", nr + 1, column + 1, e, found)
+                }
+                Diagnostic::Corrupted { column, expected: None, found } => {
+                    println!("{}:{}: unexpected {}", nr + 1, column + 1, found)
+                }
+                Diagnostic::Unknown { column, found } => {
+                    println!("{}:{}: unknown char {:?}", nr + 1, column + 1, found)
+                }
+                Diagnostic::Incomplete { completion } => println!("{}: incomplete, add {}", nr + 1, completion),
+            }
+        }
+    }
+    if args.iter().any(|a| a == "--repair") {
+        for (nr, line) in lines.iter().enumerate() {
+            let repair = language.repair(line);
+            println!("{}: {} edit(s) -> {}", nr + 1, repair.edits.len(), repair.repaired);
+        }
+    }
 
-    let p2 = part_2(&lines);
-    println!("Part 2 {}", p2);
+    println!("Part 1 {}", part_1(&language, &lines));
+    match part_2(&language, &lines) {
+        Some(p2) => println!("Part 2 {}", p2),
+        None => println!("Part 2: no incomplete lines"),
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "[({(<(())[]>[[{[]{<()<>>
+[(()[<>])]({[<{<<[]>>(
+{([(<{}[<>[]}>{[]{[(<()>
+(((({<>}<{<{<>}{[]{[]{}
+[[<[([]))<([[{}[[()]]]
+[{[{({}]{}}([{[{{{}}([]
+{<[[]]>}<{[{[{[]{()[[[]
+[<(<(<(<{}))><([]([]()
+<{([([[(<>()){}]>(<<{{
+<{([{{}}[<[[[<>{}]]]>[]]";
+
+    fn lines() -> Vec<String> {
+        EXAMPLE.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn example_scores() {
+        let language = Language::default();
+        assert_eq!(part_1(&language, &lines()), 26397);
+        assert_eq!(part_2(&language, &lines()), Some(288957));
+    }
+
+    #[test]
+    fn diagnostics() {
+        let language = Language::default();
+        let lines = lines();
+        assert_eq!(language.check(&lines[2]), Diagnostic::Corrupted { column: 12, expected: Some(']'), found: '}' });
+        assert_eq!(language.check(&lines[0]), Diagnostic::Incomplete { completion: "}}]])})]".to_string() });
+        assert_eq!(language.check("(x)"), Diagnostic::Unknown { column: 1, found: 'x' });
+        assert_eq!(language.check(")"), Diagnostic::Corrupted { column: 0, expected: None, found: ')' });
+        assert_eq!(language.check("([]<>)"), Diagnostic::Valid);
+    }
+
+    #[test]
+    fn custom_language() {
+        let language = Language::from_spec("ab=10/1,xy=20/2").unwrap();
+        assert_eq!(part_1(&language, &["axb".to_string(), "ay".to_string()]), 30);
+        // completed by "byb", scoring (1 * 5 + 2) * 5 + 1
+        assert_eq!(language.completion_score(&language.check("axa")), Some(36));
+        assert!(Language::from_spec("aa=1/1").is_err());
+        assert!(Language::from_spec("()=1").is_err());
+    }
+
+    #[test]
+    fn repairs_are_minimal_and_valid() {
+        let language = Language::default();
+        let repair = language.repair("(]");
+        assert_eq!(repair.edits, vec![Edit::Delete { column: 1, ch: ']' }, Edit::Insert { column: 2, ch: ')' }]);
+        assert_eq!(repair.repaired, "()");
+        assert_eq!(language.repair("[(])").edits.len(), 2);
+
+        let mut seed = 3u64;
+        let alphabet: Vec<char> = "()[]{}<>".chars().collect();
+        for _ in 0..300 {
+            let line: String = (0..8)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    alphabet[(seed >> 33) as usize % alphabet.len()]
+                })
+                .collect();
+            let repair = language.repair(&line);
+            assert_eq!(language.check(&repair.repaired), Diagnostic::Valid, "{} -> {}", line, repair.repaired);
+            // an edit per char is always enough; a valid line needs none
+            assert!(repair.edits.len() <= line.len());
+            if language.check(&line) == Diagnostic::Valid {
+                assert!(repair.edits.is_empty());
+            }
+        }
+    }
+}
@@ -1,112 +1,14 @@
-use std::collections::{HashSet, VecDeque};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use anyhow::Result;
+use anyhow::{bail, Result};
+use automaton::{Automaton, Edges, FlashRules, Grid};
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
-struct GridElement {
-    value: i32,
-    i: usize,
-    j: usize,
+fn parse(path: &Path, edges: Edges) -> Result<Grid<i32>> {
+    parse_reader(BufReader::new(File::open(path)?), edges)
 }
 
-#[derive(Debug, Clone)]
-struct Grid {
-    g: Vec<Vec<i32>>,
-    rows: usize,
-    cols: usize,
-}
-
-impl Grid {
-    fn new(g: Vec<Vec<i32>>) -> Self {
-        let rows = g.len();
-        let cols = g.get(0).unwrap().len();
-
-        Self {
-            g,
-            rows,
-            cols
-        }
-    }
-
-    fn in_bounds(&self, i: usize, j: usize) -> bool {
-        i < self.rows &&
-            j < self.cols
-    }
-
-    fn get(&self, i: usize, j: usize) -> Option<GridElement> {
-        if !self.in_bounds(i, j) {
-            return None;
-        }
-
-        Some(GridElement {
-            value: self.g[i][j],
-            i,
-            j
-        })
-    }
-
-    fn neighbours(&self, i: usize, j: usize) -> Vec<GridElement> {
-        let mut neighbours = Vec::new();
-        let dpos: [(i32, i32); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
-
-        for (di, dj) in dpos {
-            let ni = i as i32 + di;
-            let nj = j as i32 + dj;
-            if let Some(ge) = self.get(ni as usize, nj as usize) {
-                neighbours.push(ge);
-            }
-        }
-
-        neighbours
-    }
-
-    // returns the new state and the number of booms
-    fn step(&self) -> (Grid, usize) {
-        let mut grid = self.clone();
-
-        let mut seen = HashSet::<(usize, usize)>::new();
-        let mut q = VecDeque::new();
-
-        // increase all
-        for (row_nr, row) in &mut grid.g.iter_mut().enumerate() {
-            for (col_nr, col) in row.iter_mut().enumerate() {
-                *col = *col + 1;
-                if *col > 9 {
-                    q.push_back((row_nr, col_nr));
-                    seen.insert((row_nr, col_nr));
-                }
-            }
-        }
-
-        while !q.is_empty() {
-            let (i, j) = q.pop_back().unwrap();
-            grid.g[i][j] = 0;
-            for n in grid.neighbours(i, j) {
-                let i_n = n.i;
-                let j_n = n.j;
-
-                if seen.contains(&(i_n, j_n)) {
-                    continue;
-                }
-
-                grid.g[i_n][j_n] += 1;
-
-                if grid.g[i_n][j_n] > 9 {
-                    q.push_back((i_n, j_n));
-                    seen.insert((i_n, j_n));
-                }
-            }
-        }
-
-        (grid, seen.len())
-    }
-}
-
-fn parse(path: &Path) -> Result<Grid> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+fn parse_reader(reader: impl BufRead, edges: Edges) -> Result<Grid<i32>> {
     let mut grid = Vec::new();
 
     for line in reader.lines() {
@@ -114,50 +16,74 @@ fn parse(path: &Path) -> Result<Grid> {
         let mut row = Vec::new();
 
         for chr in line.chars() {
-            row.push((chr as i32) - ('0' as i32));
+            match chr.to_digit(10) {
+                Some(d) => row.push(d as i32),
+                None => bail!("invalid energy level {:?}", chr),
+            }
         }
 
         grid.push(row);
     }
 
-    Ok(Grid::new(grid))
+    Grid::new(grid, edges)
 }
 
-
-fn part_1(grid: &Grid) -> usize {
-    let mut g = grid.clone();
-    let mut booms = 0;
-    for i in 0..100 {
-        let (g_next, b_next) = g.step();
-        g = g_next;
-        booms += b_next;
-    }
-
-    booms
+fn part_1(grid: &Grid<i32>) -> usize {
+    let mut octopuses = Automaton::new(grid.clone(), FlashRules::dumbo_octopus());
+    octopuses.run(100).iter().sum()
 }
 
-fn part_2(grid: &Grid) -> usize {
-    let mut g = grid.clone();
-    let mut steps = 0;
-
-    loop {
-        let (g_next, b_next) = g.step();
-        steps += 1;
-        if b_next == g.rows * g.cols {
-            break;
-        }
-        g = g_next;
-    }
-
-    steps
+fn part_2(grid: &Grid<i32>) -> Option<usize> {
+    let mut octopuses = Automaton::new(grid.clone(), FlashRules::dumbo_octopus());
+    octopuses.first_synchronised(1_000_000)
 }
 
-
 fn main() -> Result<()> {
-    let grid = parse(&Path::new("input.txt"))?;
+    let edges = if std::env::args().any(|a| a == "--toroidal") {
+        Edges::Toroidal
+    } else {
+        Edges::Bounded
+    };
+    let grid = parse(Path::new("input.txt"), edges)?;
     let p1 = part_1(&grid);
     println!("Part 1 {}", p1);
-    let p2 = part_2(&grid);
-    println!("Part 2 {}", p2);
+    match part_2(&grid) {
+        Some(p2) => println!("Part 2 {}", p2),
+        None => println!("Part 2: never synchronised"),
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "5483143223
+2745854711
+5264556173
+6141336146
+6357385478
+4167524645
+2176841721
+6882881134
+4846848554
+5283751526";
+
+    fn example() -> Grid<i32> {
+        let rows = EXAMPLE.lines().map(|l| l.chars().map(|c| c.to_digit(10).unwrap() as i32).collect()).collect();
+        Grid::new(rows, Edges::Bounded).unwrap()
+    }
+
+    #[test]
+    fn flashes_and_synchronisation() {
+        let grid = example();
+        assert_eq!(part_1(&grid), 1656);
+        assert_eq!(part_2(&grid), Some(195));
+    }
+
+    #[test]
+    fn rejects_ragged_input() {
+        let err = parse_reader("123\n45\n".as_bytes(), Edges::Bounded).unwrap_err();
+        assert_eq!(err.to_string(), "row 2 is 2 wide, the first is 3");
+    }
+}
@@ -1,8 +1,8 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 
 #[derive(Debug, Clone, Hash, Eq, PartialEq)]
 struct Node {
@@ -31,25 +31,34 @@ impl Node {
 
 #[derive(Debug, Clone, Default)]
 struct Graph {
-    neighbours: HashMap<Node, Vec<Node>>,
+    nodes: Vec<Node>,
+    ids: HashMap<Node, usize>,
+    neighbours: Vec<Vec<usize>>,
 }
 
 impl Graph {
-    fn add_edge(&mut self, a: Node, b: Node) {
-        self.neighbours.entry(a.clone()).or_default().push(b.clone());
-        self.neighbours.entry(b.clone()).or_default().push(a.clone());
+    fn id(&mut self, n: Node) -> usize {
+        if let Some(id) = self.ids.get(&n) {
+            return *id;
+        }
+        self.nodes.push(n.clone());
+        self.neighbours.push(Vec::new());
+        self.ids.insert(n, self.nodes.len() - 1);
+        self.nodes.len() - 1
     }
 
-    fn get_start(&self) -> Node {
-        self.neighbours.keys().find(|k|k.is_start()).unwrap().clone()
+    fn add_edge(&mut self, a: Node, b: Node) {
+        let (a, b) = (self.id(a), self.id(b));
+        self.neighbours[a].push(b);
+        self.neighbours[b].push(a);
     }
 
-    fn get_end(&self) -> Node {
-        self.neighbours.keys().find(|k|k.is_end()).unwrap().clone()
+    fn get_start(&self) -> Result<usize> {
+        self.nodes.iter().position(|k| k.is_start()).ok_or_else(|| anyhow!("no start cave"))
     }
 
-    fn get_neighbours<'a>(&'a self, n: &'a Node) -> impl Iterator<Item = &'a Node> {
-        self.neighbours.get(n).into_iter().flat_map(|v| v.iter())
+    fn get_end(&self) -> Result<usize> {
+        self.nodes.iter().position(|k| k.is_end()).ok_or_else(|| anyhow!("no end cave"))
     }
 }
 
@@ -60,99 +69,235 @@ fn parse(path: &Path) -> Result<Graph> {
     let mut graph = Graph::default();
     for line in reader.lines() {
         let line = line?;
-        let mut splt = line.split("-");
-        let n1 = Node::create(splt.next().unwrap().to_string());
-        let n2 = Node::create(splt.next().unwrap().to_string());
+        let Some((n1, n2)) = line.split_once('-') else {
+            bail!("not an edge: {:?}", line);
+        };
 
-        graph.add_edge(n1, n2);
+        graph.add_edge(Node::create(n1.to_string()), Node::create(n2.to_string()));
     }
 
     Ok(graph)
 }
 
-fn dfs(node: &Node, graph: &Graph, seen: &mut HashSet<Node>) -> i32 {
-    if node.is_end() {
-        return 1;
-    }
-
-    let mut count = 0;
+// how often each small cave may be entered; big caves are unlimited.
+// `spare` extra visits may be spent on any small cave but start and end
+#[derive(Debug, Clone)]
+struct VisitBudget {
+    small: u32,
+    overrides: HashMap<String, u32>,
+    spare: u32,
+}
 
-    if node.is_small() {
-        seen.insert(node.clone());
+impl VisitBudget {
+    fn once() -> Self {
+        VisitBudget { small: 1, overrides: HashMap::new(), spare: 0 }
     }
 
-    for neighbour in graph.get_neighbours(node) {
-        if seen.contains(neighbour) {
-            continue;
-        }
-
-        count += dfs(neighbour, graph, seen);
+    fn one_twice() -> Self {
+        VisitBudget { spare: 1, ..VisitBudget::once() }
     }
 
-    if node.is_small() {
-        seen.remove(&node);
+    fn of(&self, node: &Node) -> u32 {
+        if node.is_start() || node.is_end() {
+            return 1;
+        }
+        *self.overrides.get(&node.name).unwrap_or(&self.small)
     }
+}
 
-    count
+// visit counts of the small caves packed into one word, `bits` per cave
+struct PathCounter<'a> {
+    graph: &'a Graph,
+    budget: &'a VisitBudget,
+    slot: Vec<Option<usize>>,
+    bits: usize,
+    end: usize,
+    memo: HashMap<(usize, u64, u32), u64>,
 }
 
-fn dfs2(node: &Node, graph: &Graph, seen: &mut HashSet<Node>, twice: Option<Node>) -> i32 {
-    if node.is_end() {
-        return 1;
+impl<'a> PathCounter<'a> {
+    fn new(graph: &'a Graph, budget: &'a VisitBudget) -> Result<Self> {
+        for (a, n) in graph.nodes.iter().enumerate() {
+            if n.is_small() {
+                continue;
+            }
+            if let Some(&b) = graph.neighbours[a].iter().find(|&&b| !graph.nodes[b].is_small()) {
+                bail!("big caves {} and {} are connected, there are infinitely many paths", n.name, graph.nodes[b].name);
+            }
+        }
+
+        let mut slot = vec![None; graph.nodes.len()];
+        let mut small = 0;
+        for (id, n) in graph.nodes.iter().enumerate() {
+            if n.is_small() {
+                slot[id] = Some(small);
+                small += 1;
+            }
+        }
+        let max_visits = graph
+            .nodes
+            .iter()
+            .filter(|n| n.is_small())
+            .map(|n| budget.of(n) + budget.spare)
+            .max()
+            .unwrap_or(1);
+        let bits = (u32::BITS - max_visits.leading_zeros()) as usize;
+        if small * bits > 64 {
+            bail!("{} small caves with up to {} visits do not fit the visit state", small, max_visits);
+        }
+
+        Ok(PathCounter { graph, budget, slot, bits, end: graph.get_end()?, memo: HashMap::new() })
     }
 
-    let mut count = 0;
+    fn visits(&self, state: u64, node: usize) -> Option<u32> {
+        self.slot[node].map(|s| ((state >> (s * self.bits)) & ((1 << self.bits) - 1)) as u32)
+    }
 
-    if node.is_small() {
-        seen.insert(node.clone());
+    fn visit(&self, state: u64, node: usize) -> u64 {
+        match self.slot[node] {
+            Some(s) => state + (1 << (s * self.bits)),
+            None => state,
+        }
     }
 
-    for neighbour in graph.get_neighbours(node) {
-        if seen.contains(neighbour) && !neighbour.is_start() && twice.is_none() {
-            count += dfs2(neighbour, graph, seen, Some(neighbour.clone()));
+    // the (state, spare) after entering `next`, if the budget allows it
+    fn enter(&self, next: usize, state: u64, spare: u32) -> Option<(u64, u32)> {
+        let node = &self.graph.nodes[next];
+        match self.visits(state, next) {
+            None => Some((state, spare)),
+            Some(v) if v < self.budget.of(node) => Some((self.visit(state, next), spare)),
+            // a cave closed off with budget 0 stays closed, spare or not
+            Some(_) if spare > 0 && self.budget.of(node) > 0 && !node.is_start() && !node.is_end() => {
+                Some((self.visit(state, next), spare - 1))
+            }
+            Some(_) => None,
         }
+    }
 
-        if seen.contains(neighbour) {
-            continue;
+    fn count(&mut self, node: usize, state: u64, spare: u32) -> u64 {
+        if node == self.end {
+            return 1;
+        }
+        if let Some(c) = self.memo.get(&(node, state, spare)) {
+            return *c;
         }
 
-        count += dfs2(neighbour, graph, seen, twice.clone());
-    }
+        let mut count = 0;
+        for idx in 0..self.graph.neighbours[node].len() {
+            let next = self.graph.neighbours[node][idx];
+            if let Some((state, spare)) = self.enter(next, state, spare) {
+                count += self.count(next, state, spare);
+            }
+        }
 
-    if node.is_small() {
-        seen.remove(&node);
+        self.memo.insert((node, state, spare), count);
+        count
     }
 
-    if let Some(x) = twice {
-        if x.name == node.name {
-            seen.insert(node.clone());
+    fn walk(&self, node: usize, state: u64, spare: u32, path: &mut Vec<usize>, emit: &mut dyn FnMut(&str)) {
+        path.push(node);
+        if node == self.end {
+            let names: Vec<&str> = path.iter().map(|&n| self.graph.nodes[n].name.as_str()).collect();
+            emit(&names.join(","));
+        } else {
+            for &next in &self.graph.neighbours[node] {
+                if let Some((state, spare)) = self.enter(next, state, spare) {
+                    self.walk(next, state, spare, path, emit);
+                }
+            }
         }
+        path.pop();
     }
-
-    count
 }
 
-fn part_1(graph: &Graph) -> i32 {
-    let start = graph.get_start();
-    let mut seen = HashSet::new();
-
-    dfs(&start, graph, &mut seen)
+fn count_paths(graph: &Graph, budget: &VisitBudget) -> Result<u64> {
+    let start = graph.get_start()?;
+    let mut counter = PathCounter::new(graph, budget)?;
+    let state = counter.visit(0, start);
+    Ok(counter.count(start, state, budget.spare))
 }
 
-fn part_2(graph: &Graph) -> i32 {
-    let start = graph.get_start();
-    let mut seen = HashSet::new();
-
-    dfs2(&start, graph, &mut seen, None)
+fn stream_paths(graph: &Graph, budget: &VisitBudget, emit: &mut dyn FnMut(&str)) -> Result<()> {
+    let start = graph.get_start()?;
+    let counter = PathCounter::new(graph, budget)?;
+    counter.walk(start, counter.visit(0, start), budget.spare, &mut Vec::new(), emit);
+    Ok(())
 }
 
 fn main() -> Result<()> {
-    let graph = parse(&Path::new("input.txt"))?;
-    let p1 = part_1(&graph);
+    let graph = parse(Path::new("input.txt"))?;
+    let p1 = count_paths(&graph, &VisitBudget::once())?;
     println!("Part 1 {}", p1);
 
-    let p2 = part_2(&graph);
+    let p2 = count_paths(&graph, &VisitBudget::one_twice())?;
     println!("Part 2 {}", p2);
 
+    if std::env::args().any(|a| a == "--paths") {
+        stream_paths(&graph, &VisitBudget::one_twice(), &mut |p| println!("{}", p))?;
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL: &str = "start-A\nstart-b\nA-c\nA-b\nb-d\nA-end\nb-end";
+    const MEDIUM: &str = "dc-end\nHN-start\nstart-kj\ndc-start\ndc-HN\nLN-dc\nHN-end\nkj-sa\nkj-HN\nkj-dc";
+    const LARGE: &str = "fs-end\nhe-DX\nfs-he\nstart-DX\npj-DX\nend-zg\nzg-sl\nzg-pj\npj-he\nRW-he\nfs-DX\npj-RW\nzg-RW\nstart-pj\nhe-WI\nzg-he\npj-fs\nstart-RW";
+
+    fn graph(text: &str) -> Graph {
+        let mut graph = Graph::default();
+        for line in text.lines() {
+            let (a, b) = line.split_once('-').unwrap();
+            graph.add_edge(Node::create(a.to_string()), Node::create(b.to_string()));
+        }
+        graph
+    }
+
+    fn streamed(graph: &Graph, budget: &VisitBudget) -> Vec<String> {
+        let mut paths = Vec::new();
+        stream_paths(graph, budget, &mut |p| paths.push(p.to_string())).unwrap();
+        paths
+    }
+
+    #[test]
+    fn example_caves() {
+        for (text, once, one_twice) in [(SMALL, 10, 36), (MEDIUM, 19, 103), (LARGE, 226, 3509)] {
+            let graph = graph(text);
+            assert_eq!(count_paths(&graph, &VisitBudget::once()).unwrap(), once);
+            assert_eq!(count_paths(&graph, &VisitBudget::one_twice()).unwrap(), one_twice);
+            assert_eq!(streamed(&graph, &VisitBudget::one_twice()).len() as u64, one_twice);
+        }
+    }
+
+    #[test]
+    fn streamed_paths_are_distinct() {
+        let mut paths = streamed(&graph(SMALL), &VisitBudget::once());
+        assert!(paths.contains(&"start,A,b,A,c,A,end".to_string()));
+        paths.sort();
+        paths.dedup();
+        assert_eq!(paths.len(), 10);
+    }
+
+    #[test]
+    fn overridden_budgets() {
+        let graph = graph(SMALL);
+        let budget = VisitBudget { overrides: HashMap::from([("c".to_string(), 0)]), ..VisitBudget::once() };
+        assert!(streamed(&graph, &budget).iter().all(|p| !p.contains('c')));
+        let budget = VisitBudget { overrides: HashMap::from([("c".to_string(), 0)]), ..VisitBudget::one_twice() };
+        let paths = streamed(&graph, &budget);
+        assert!(paths.iter().all(|p| !p.contains('c')), "{:?}", paths);
+        assert_eq!(count_paths(&graph, &budget).unwrap(), paths.len() as u64);
+        let budget = VisitBudget { small: 3, ..VisitBudget::once() };
+        assert_eq!(count_paths(&graph, &budget).unwrap(), streamed(&graph, &budget).len() as u64);
+    }
+
+    #[test]
+    fn rejects_connected_big_caves() {
+        let err = count_paths(&graph("start-A\nA-B\nB-end"), &VisitBudget::once()).unwrap_err();
+        assert_eq!(err.to_string(), "big caves A and B are connected, there are infinitely many paths");
+        assert_eq!(count_paths(&graph("a-end"), &VisitBudget::once()).unwrap_err().to_string(), "no start cave");
+    }
+}
@@ -1,5 +1,6 @@
 use std::collections::{BTreeMap, HashSet};
 use std::collections::Bound::Excluded;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
@@ -27,7 +28,7 @@ impl Origami {
             by_y.entry(*y).or_default().push(*x);
         }
 
-        let paper = HashSet::from_iter(points.into_iter());
+        let paper = HashSet::from_iter(points);
 
         Self { by_x, by_y, paper }
     }
@@ -73,8 +74,10 @@ impl Origami {
 }
 
 fn parse(path: &Path) -> Result<(Origami, Vec<Fold>)> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+    parse_reader(BufReader::new(File::open(path)?))
+}
+
+fn parse_reader(reader: impl BufRead) -> Result<(Origami, Vec<Fold>)> {
     let mut lines = reader.lines();
 
     let mut points = vec![];
@@ -107,7 +110,7 @@ fn part1(origami: &Origami, fold: &Fold) -> usize {
     origami.count()
 }
 
-fn part2(origami: &Origami, fold: &Vec<Fold>) -> Origami {
+fn part2(origami: &Origami, fold: &[Fold]) -> Origami {
     let mut o = origami.clone();
 
     for f in fold {
@@ -117,29 +120,206 @@ fn part2(origami: &Origami, fold: &Vec<Fold>) -> Origami {
     o
 }
 
-fn display(o: &Origami) {
-    let cols = *o.by_x.keys().max().unwrap();
-    let rows = *o.by_y.keys().max().unwrap();
+// glyph art row by row, '#' lit and '.' dark; glyphs are 4 columns wide
+// except Y, which spills into the gap before the next letter
+const FONT_4X6: [(char, &str); 18] = [
+    ('A', ".##.#..##..######..##..#"),
+    ('B', "###.#..####.#..##..####."),
+    ('C', ".##.#..##...#...#..#.##."),
+    ('E', "#####...###.#...#...####"),
+    ('F', "#####...###.#...#...#..."),
+    ('G', ".##.#..##...#.###..#.###"),
+    ('H', "#..##..######..##..##..#"),
+    ('I', ".###..#...#...#...#..###"),
+    ('J', "..##...#...#...##..#.##."),
+    ('K', "#..##.#.##..#.#.#.#.#..#"),
+    ('L', "#...#...#...#...#...####"),
+    ('O', ".##.#..##..##..##..#.##."),
+    ('P', "###.#..##..####.#...#..."),
+    ('R', "###.#..##..####.#.#.#..#"),
+    ('S', ".####...#....##....####."),
+    ('U', "#..##..##..##..##..#.##."),
+    ('Y', "#...##...#.#.#...#....#....#.."),
+    ('Z', "####...#..#..#..#...####"),
+];
 
-    for i in (0..=rows) {
-        for j in (0..=cols) {
-            if o.paper.contains(&(j,i)) {
-                print!("#");
-            } else {
-                print!(".")
-            }
+const FONT_6X10: [(char, &str); 15] = [
+    ('A', "..##...#..#.#....##....##....########....##....##....##....#"),
+    ('B', "#####.#....##....##....######.#....##....##....##....######."),
+    ('C', ".####.#....##.....#.....#.....#.....#.....#.....#....#.####."),
+    ('E', "#######.....#.....#.....#####.#.....#.....#.....#.....######"),
+    ('F', "#######.....#.....#.....#####.#.....#.....#.....#.....#....."),
+    ('G', ".####.#....##.....#.....#.....#..####....##....##...##.###.#"),
+    ('H', "#....##....##....##....########....##....##....##....##....#"),
+    ('J', "...###....#.....#.....#.....#.....#.....#.#...#.#...#..###.."),
+    ('K', "#....##...#.#..#..#.#...##....##....#.#...#..#..#...#.#....#"),
+    ('L', "#.....#.....#.....#.....#.....#.....#.....#.....#.....######"),
+    ('N', "#....###...###...##.#..##.#..##..#.##..#.##...###...###....#"),
+    ('P', "#####.#....##....##....######.#.....#.....#.....#.....#....."),
+    ('R', "#####.#....##....##....######.#..#..#...#.#...#.#....##....#"),
+    ('X', "#....##....#.#..#..#..#...##....##...#..#..#..#.#....##....#"),
+    ('Z', "######.....#.....#....#....#....#....#....#.....#.....######"),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct UnrecognisedGlyphs {
+    // glyph index and the first column of its cell
+    positions: Vec<(usize, i32)>,
+    art: String,
+}
+
+impl fmt::Display for UnrecognisedGlyphs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let positions: Vec<String> = self
+            .positions
+            .iter()
+            .map(|(idx, x)| format!("#{} (x={})", idx, x))
+            .collect();
+        write!(f, "unrecognised glyphs {}\n{}", positions.join(", "), self.art)
+    }
+}
+
+impl std::error::Error for UnrecognisedGlyphs {}
+
+fn render(o: &Origami) -> String {
+    let Some(max_x) = o.by_x.keys().last() else {
+        return String::new();
+    };
+    let max_y = o.by_y.keys().last().unwrap();
+
+    let mut art = String::new();
+    for i in 0..=*max_y {
+        for j in 0..=*max_x {
+            art.push(if o.paper.contains(&(j, i)) { '#' } else { '.' });
         }
-        println!();
+        art.push('\n');
+    }
+    art
+}
+
+// reads the folded paper as capital letters in one of the AoC fonts,
+// picked by the height of the text; letters sit in fixed cells from x=0,
+// one blank column apart in the small font and two in the large one
+fn recognise(o: &Origami) -> Result<String, UnrecognisedGlyphs> {
+    let Some(max_x) = o.by_x.keys().last() else {
+        return Ok(String::new());
+    };
+    let height = *o.by_y.keys().last().unwrap() + 1;
+    let (font, pitch): (&[(char, &str)], i32) = match height {
+        6 => (&FONT_4X6, 4 + 1),
+        10 => (&FONT_6X10, 6 + 2),
+        _ => (&[], max_x + 1),
+    };
+
+    let mut text = String::new();
+    let mut positions = Vec::new();
+    let glyphs = max_x / pitch + 1;
+    for idx in 0..glyphs {
+        let left = idx * pitch;
+        let matches = |art: &str| {
+            let width = art.len() as i32 / height;
+            let mut cells = art.chars();
+            (0..height).all(|i| (left..left + width).all(|j| (cells.next() == Some('#')) == o.paper.contains(&(j, i))))
+        };
+        match font.iter().find(|(_, art)| matches(art)) {
+            Some((c, _)) => text.push(*c),
+            None => positions.push((idx as usize, left)),
+        }
+    }
+
+    if positions.is_empty() {
+        Ok(text)
+    } else {
+        Err(UnrecognisedGlyphs { positions, art: render(o) })
     }
 }
 
 fn main() -> Result<()> {
-    let (origami, folds) = parse(&Path::new("input.txt"))?;
+    let (origami, folds) = parse(Path::new("input.txt"))?;
     let p1 = part1(&origami, folds.first().unwrap());
     println!("Part 1 {}", p1);
 
     let p2 = part2(&origami, &folds);
-    println!("Part 2 {}", p2.count());
-    display(&p2);
+    println!("Part 2 {}", recognise(&p2)?);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "6,10
+0,14
+9,10
+0,3
+10,4
+4,11
+6,0
+6,12
+4,1
+0,13
+10,12
+3,4
+3,0
+8,4
+1,10
+2,14
+8,10
+9,0
+
+fold along y=7
+fold along x=5
+";
+
+    fn example() -> (Origami, Vec<Fold>) {
+        parse_reader(EXAMPLE.as_bytes()).unwrap()
+    }
+
+    // lays the glyphs out the way the puzzle does, `pitch` columns apart
+    fn write(font: &[(char, &str)], height: usize, pitch: i32, text: &str) -> Origami {
+        let mut points = vec![];
+        for (idx, c) in text.chars().enumerate() {
+            let art = font.iter().find(|(g, _)| *g == c).unwrap().1;
+            let width = art.len() / height;
+            for (k, cell) in art.chars().enumerate() {
+                if cell == '#' {
+                    points.push((idx as i32 * pitch + (k % width) as i32, (k / width) as i32));
+                }
+            }
+        }
+        Origami::new(points)
+    }
+
+    #[test]
+    fn example_folds() {
+        let (origami, folds) = example();
+        assert_eq!(part1(&origami, &folds[0]), 17);
+        let folded = part2(&origami, &folds);
+        assert_eq!(folded.count(), 16);
+        assert_eq!(render(&folded), "#####\n#...#\n#...#\n#...#\n#####\n");
+    }
+
+    #[test]
+    fn reads_every_letter_of_both_fonts() {
+        let small: String = FONT_4X6.iter().map(|(c, _)| *c).collect();
+        assert_eq!(recognise(&write(&FONT_4X6, 6, 5, &small)), Ok(small));
+        let large: String = FONT_6X10.iter().map(|(c, _)| *c).collect();
+        assert_eq!(recognise(&write(&FONT_6X10, 10, 8, &large)), Ok(large));
+    }
+
+    #[test]
+    fn cells_start_at_the_origin() {
+        // J and I leave the first column dark, which must not shift the cells
+        assert_eq!(recognise(&write(&FONT_4X6, 6, 5, "JIY")), Ok("JIY".to_string()));
+        assert_eq!(recognise(&write(&FONT_4X6, 6, 5, "YIJ")), Ok("YIJ".to_string()));
+    }
+
+    #[test]
+    fn reports_unknown_glyphs() {
+        let mut points: Vec<(i32, i32)> = write(&FONT_4X6, 6, 5, "AB").paper.into_iter().collect();
+        points.retain(|&(x, _)| x < 5);
+        points.extend((0..6).map(|y| (6, y)));
+        let err = recognise(&Origami::new(points)).unwrap_err();
+        assert_eq!(err.positions, vec![(1, 5)]);
+    }
+}
@@ -1,76 +1,309 @@
-use anyhow::Result;
-use std::collections::{BTreeMap, HashMap, LinkedList};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use anyhow::{bail, Result};
+use num_bigint::BigUint;
+use semiring::{mat_pow_vec, Matrix, Modular, Semiring};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
 use std::path::Path;
 
-fn simulate_clever(template: &LinkedList<char>, mapping: &HashMap<(char, char), char>, steps: usize) -> HashMap<char, u128> {
-    let mut count = HashMap::new();
-    for ch in template {
-        count.entry(*ch).and_modify(|x| *x += 1).or_insert(1);
-    }
+// The polymer is a chain of k-element blocks, each sharing its last element
+// with the next one's first. A rule `w -> s` (|w| = k, |s| = k - 1) puts one
+// element of `s` into every gap of block `w`, which leaves 2k - 1 elements:
+// two blocks sharing the middle one. Blocks without a rule stay as they are.
+// For k = 2 this is plain pair insertion. Blocks don't overlap beyond their
+// shared ends, so with k > 2 the template has to be exactly such a chain.
+#[derive(Debug, Clone)]
+struct Rules {
+    k: usize,
+    insert: HashMap<Vec<char>, Vec<char>>,
+}
 
-    let mut pairs = HashMap::<(char, char), u128>::new();
-    let vec: Vec<_> = template.iter().collect();
-    for w in vec.windows(2) {
-        pairs.entry((*w[0], *w[1])).or_insert(1);
+impl Rules {
+    fn expand(&self, block: &[char]) -> Option<Vec<Vec<char>>> {
+        let inserted = self.insert.get(block)?;
+        let mut polymer = Vec::with_capacity(2 * self.k - 1);
+        for (idx, c) in block.iter().enumerate() {
+            polymer.push(*c);
+            if let Some(x) = inserted.get(idx) {
+                polymer.push(*x);
+            }
+        }
+        Some(vec![polymer[..self.k].to_vec(), polymer[self.k - 1..].to_vec()])
     }
+}
+
+struct Polymerization {
+    template: Vec<char>,
+    rules: Rules,
+    blocks: Vec<Vec<char>>,
+    ids: HashMap<Vec<char>, usize>,
+}
 
-    for _ in 0..steps {
-        let mut new_pairs = HashMap::new();
+impl Polymerization {
+    fn new(template: Vec<char>, rules: Rules) -> Result<Self> {
+        let k = rules.k;
+        if template.len() < k || !(template.len() - 1).is_multiple_of(k - 1) {
+            let shorter = k + (template.len().max(k) - k) / (k - 1) * (k - 1);
+            bail!(
+                "{}-element rules rewrite the template as a chain of {}-element blocks sharing their ends, \
+                 so it needs k + n(k - 1) elements; {} has {} (the nearest fitting lengths are {} and {})",
+                k,
+                k,
+                template.iter().collect::<String>(),
+                template.len(),
+                shorter,
+                shorter + k - 1
+            );
+        }
 
-        for ((a, b), cnt) in pairs {
-            if let Some(c) = mapping.get(&(a, b)) {
-                count.entry(*c).and_modify(|x| *x += cnt).or_insert(cnt);
-                new_pairs.entry((a, *c)).and_modify(|x| *x += cnt).or_insert(cnt);
-                new_pairs.entry((*c, b)).and_modify(|x| *x += cnt).or_insert(cnt);
+        // every block reachable from the template
+        let mut blocks: Vec<Vec<char>> = Vec::new();
+        let mut ids = HashMap::new();
+        let mut todo: Vec<Vec<char>> = template.windows(k).step_by(k - 1).map(|w| w.to_vec()).collect();
+        while let Some(block) = todo.pop() {
+            if ids.contains_key(&block) {
+                continue;
+            }
+            ids.insert(block.clone(), blocks.len());
+            blocks.push(block.clone());
+            if let Some(next) = rules.expand(&block) {
+                todo.extend(next);
             }
         }
 
-        pairs = new_pairs;
+        Ok(Polymerization { template, rules, blocks, ids })
     }
 
-    count
-}
+    fn transition<T: Semiring>(&self, unit: &T) -> Matrix<T> {
+        let n = self.blocks.len();
+        let mut m = vec![vec![unit.zero(); n]; n];
+        for (from, block) in self.blocks.iter().enumerate() {
+            match self.rules.expand(block) {
+                Some(next) => {
+                    for b in next {
+                        let to = self.ids[&b];
+                        m[to][from] = m[to][from].add(&unit.one());
+                    }
+                }
+                None => m[from][from] = m[from][from].add(&unit.one()),
+            }
+        }
+        m
+    }
 
-fn part_1(template: &LinkedList<char>, mapping: &HashMap<(char, char), char>) -> u128 {
-    let hmap = simulate_clever(template, mapping, 10);
+    fn block_counts<T: Semiring>(&self, steps: u64, unit: &T) -> Vec<T> {
+        let k = self.rules.k;
+        let mut v = vec![unit.zero(); self.blocks.len()];
+        for w in self.template.windows(k).step_by(k - 1) {
+            let id = self.ids[w];
+            v[id] = v[id].add(&unit.one());
+        }
 
-    hmap.values().max().unwrap() - hmap.values().min().unwrap()
+        mat_pow_vec(self.transition(unit), steps, v, unit)
+    }
+
+    // each block owns all its elements but the last, which the next block owns;
+    // the polymer's last element never moves
+    fn frequencies<T: Semiring>(&self, steps: u64, unit: &T) -> BTreeMap<char, T> {
+        let counts = self.block_counts(steps, unit);
+        let mut freq = BTreeMap::new();
+        for (block, count) in self.blocks.iter().zip(&counts) {
+            for c in &block[..block.len() - 1] {
+                let e = freq.entry(*c).or_insert_with(|| unit.zero());
+                *e = e.add(count);
+            }
+        }
+        let last = freq.entry(*self.template.last().unwrap()).or_insert_with(|| unit.zero());
+        *last = last.add(&unit.one());
+        freq
+    }
+
+    // elements the rules could produce but haven't yet are left out, so they
+    // can't become the minimum
+    fn exact(&self, steps: u64) -> BTreeMap<char, BigUint> {
+        self.frequencies(steps, &BigUint::ZERO)
+            .into_iter()
+            .filter(|(_, v)| *v != BigUint::ZERO)
+            .collect()
+    }
+
+    fn modulo(&self, steps: u64, m: u64) -> Result<BTreeMap<char, u64>> {
+        if m == 0 {
+            bail!("modulus must be positive");
+        }
+        Ok(self
+            .frequencies(steps, &Modular::new(0, m))
+            .into_iter()
+            .map(|(c, v)| (c, v.value))
+            .collect())
+    }
 }
 
-fn part_2(template: &LinkedList<char>, mapping: &HashMap<(char, char), char>) -> u128 {
-    let hmap = simulate_clever(template, mapping, 40);
+fn score(freq: &BTreeMap<char, BigUint>) -> BigUint {
+    freq.values().max().unwrap() - freq.values().min().unwrap()
+}
 
-    hmap.values().max().unwrap() - hmap.values().min().unwrap()
+fn parse(path: &Path) -> Result<(Vec<char>, Rules)> {
+    parse_text(&fs::read_to_string(path)?)
 }
 
-fn parse(path: &Path) -> Result<(LinkedList<char>, HashMap<(char, char), char>)> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
+fn parse_text(text: &str) -> Result<(Vec<char>, Rules)> {
+    let mut lines = text.lines();
 
-    let template = lines.next().unwrap()?.chars().collect();
+    let template = match lines.next() {
+        Some(line) => line.trim().chars().collect(),
+        None => bail!("empty input"),
+    };
     lines.next();
 
-    let mut hm = HashMap::new();
+    let mut insert = HashMap::new();
+    let mut k = 0;
     for line in lines {
-        let line = line?;
-        let (a, b, c) = scan_fmt::scan_fmt!(&line, "{/./}{/./} -> {/./}", char, char, char)?;
-        hm.insert((a, b), c);
+        let Some((lhs, rhs)) = line.split_once(" -> ") else {
+            bail!("not a rule: {:?}", line);
+        };
+        let (lhs, rhs): (Vec<char>, Vec<char>) = (lhs.chars().collect(), rhs.chars().collect());
+        if k == 0 {
+            k = lhs.len();
+        }
+        if lhs.len() < 2 || lhs.len() != k || rhs.len() != k - 1 {
+            bail!("rule {:?} is not a {}-element block and its {} insertions", line, k, k.max(1) - 1);
+        }
+        insert.insert(lhs, rhs);
     }
 
-    Ok((template, hm))
+    Ok((template, Rules { k: k.max(2), insert }))
+}
+
+fn print_frequencies<T: std::fmt::Display>(freq: &BTreeMap<char, T>) {
+    let items: Vec<String> = freq.iter().map(|(c, v)| format!("{}={}", c, v)).collect();
+    println!("  {}", items.join(" "));
 }
 
 fn main() -> Result<()> {
-    let (template, mapping) = parse(&Path::new("input.txt"))?;
+    let (template, rules) = parse(Path::new("input.txt"))?;
+    let polymer = Polymerization::new(template, rules)?;
 
-    let p1 = part_1(&template, &mapping);
-    println!("Part 1 {}", p1);
+    let p1 = polymer.exact(10);
+    println!("Part 1 {}", score(&p1));
 
-    let p2 = part_2(&template, &mapping);
-    println!("Part 2 {}", p2);
+    let p2 = polymer.exact(40);
+    println!("Part 2 {}", score(&p2));
+    print_frequencies(&p2);
+
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|a| a == "--steps") {
+        let steps: u64 = args.get(i + 1).map(|s| s.parse()).transpose()?.unwrap_or(0);
+        match args.iter().position(|a| a == "--mod") {
+            Some(j) => {
+                let m: u64 = args.get(j + 1).map(|s| s.parse()).transpose()?.unwrap_or(0);
+                println!("After {} steps (mod {}):", steps, m);
+                print_frequencies(&polymer.modulo(steps, m)?);
+            }
+            None => {
+                let freq = polymer.exact(steps);
+                println!("After {} steps, score {}:", steps, score(&freq));
+                print_frequencies(&freq);
+            }
+        }
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "NNCB
+
+CH -> B
+HH -> N
+CB -> H
+NH -> C
+HB -> C
+HC -> B
+HN -> C
+NN -> C
+BH -> H
+NC -> B
+NB -> B
+BN -> B
+BB -> N
+BC -> B
+CC -> N
+CN -> C";
+
+    fn polymer(text: &str) -> Result<Polymerization> {
+        let (template, rules) = parse_text(text)?;
+        Polymerization::new(template, rules)
+    }
+
+    // the rules applied to the whole string, one step at a time
+    fn simulate(text: &str, steps: usize) -> BTreeMap<char, BigUint> {
+        let (mut template, rules) = parse_text(text).unwrap();
+        let k = rules.k;
+        for _ in 0..steps {
+            let mut next = vec![template[0]];
+            for block in template.windows(k).step_by(k - 1) {
+                match rules.expand(block) {
+                    Some(halves) => next.extend(halves[0][1..].iter().chain(&halves[1][1..])),
+                    None => next.extend(&block[1..]),
+                }
+            }
+            template = next;
+        }
+        let mut freq = BTreeMap::new();
+        for c in template {
+            *freq.entry(c).or_insert(BigUint::ZERO) += 1u32;
+        }
+        freq
+    }
+
+    #[test]
+    fn example() {
+        let p = polymer(EXAMPLE).unwrap();
+        assert_eq!(score(&p.exact(10)), BigUint::from(1588u32));
+        assert_eq!(score(&p.exact(40)), BigUint::from(2188189693529u64));
+        assert_eq!(p.exact(10), simulate(EXAMPLE, 10));
+        assert_eq!(p.exact(10)[&'B'], BigUint::from(1749u32));
+    }
+
+    #[test]
+    fn early_scores_ignore_missing_elements() {
+        let p = polymer(EXAMPLE).unwrap();
+        for steps in 0..2 {
+            let freq = simulate(EXAMPLE, steps as usize);
+            assert_eq!(score(&p.exact(steps)), score(&freq), "after {} steps", steps);
+        }
+        assert_eq!(score(&p.exact(0)), BigUint::from(1u32));
+        assert_eq!(score(&p.exact(1)), BigUint::from(1u32));
+    }
+
+    #[test]
+    fn longer_blocks_match_simulation() {
+        let text = "ABCBA\n\nABC -> XY\nCXY -> AB\nYBC -> CC\nXYB -> AA\nCBA -> BB";
+        let p = polymer(text).unwrap();
+        for steps in 0..8 {
+            assert_eq!(p.exact(steps), simulate(text, steps as usize), "after {} steps", steps);
+        }
+    }
+
+    #[test]
+    fn modular_counts() {
+        let p = polymer(EXAMPLE).unwrap();
+        let exact = p.exact(1000);
+        let m = 1_000_000_007u64;
+        let reduced: BTreeMap<char, u64> = exact.iter().map(|(c, v)| (*c, (v % m).try_into().unwrap())).collect();
+        assert_eq!(p.modulo(1000, m).unwrap(), reduced);
+        assert!(p.modulo(10, 0).is_err());
+        assert!(p.modulo(1_000_000_000_000, m).is_ok());
+    }
+
+    #[test]
+    fn templates_must_be_block_chains() {
+        let err = polymer("NNCB\n\nNNC -> XY").err().unwrap();
+        assert!(err.to_string().contains("nearest fitting lengths are 3 and 5"), "{}", err);
+        assert!(polymer("NNCBA\n\nNNC -> XY").is_ok());
+        assert!(parse_text("NNCB\n\nNNC -> X").is_err());
+    }
+}
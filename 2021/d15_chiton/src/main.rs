@@ -1,22 +1,30 @@
-use std::cmp::{min, Ordering};
-use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use anyhow::{ensure, Result};
+use anyhow::{bail, Result};
+
+trait RiskMap {
+    fn rows(&self) -> usize;
+    fn cols(&self) -> usize;
+    fn get_risk(&self, r: usize, c: usize) -> u32;
+}
 
 #[derive(Debug, Clone)]
 struct Grid {
-    inner: Vec<Vec<i64>>
+    inner: Vec<Vec<u32>>
 }
 
 impl Grid {
-    fn create(inner: Vec<Vec<i64>>) -> Self {
+    fn create(inner: Vec<Vec<u32>>) -> Self {
         Self {
             inner
         }
     }
+}
 
+impl RiskMap for Grid {
     fn rows(&self) -> usize {
         self.inner.len()
     }
@@ -25,159 +33,165 @@ impl Grid {
         self.inner[0].len()
     }
 
-    fn get_risk(&self, r: usize, c: usize) -> i64 {
-        if r < self.rows() && c < self.cols() {
-            self.inner[r][c]
-        } else {
-            i32::MAX as i64
-        }
+    fn get_risk(&self, r: usize, c: usize) -> u32 {
+        self.inner[r][c]
     }
 }
 
-fn get(risk: &Vec<Vec<i64>>, r: i64, c: i64) -> i64 {
-    if (r as usize) < risk.len() && (c as usize) < risk[0].len() {
-        risk[r as usize][c as usize]
-    } else {
-        i32::MAX as i64
-    }
+// the grid repeated `factor` times each way, every tile one riskier than
+// the one above or to its left, wrapping 9 back to 1; computed on demand
+#[derive(Debug, Clone)]
+struct TiledGrid<'a> {
+    base: &'a Grid,
+    factor: usize,
 }
 
-fn part_1_stupid(grid: &Grid) -> i64 {
-    let mut total_risk = vec![vec![i64::MAX; grid.cols()]; grid.rows()];
-    total_risk[0][0] = 0;
-    for r in 0..grid.rows() {
-        for c in 0..grid.cols() {
-            total_risk[r][c] = min(min(
-                get(&total_risk, r as i64 - 1, c as i64),
-                get(&total_risk, r as i64, c as i64 - 1)
-            ) + grid.get_risk(r, c), total_risk[r][c]);
-        }
+impl RiskMap for TiledGrid<'_> {
+    fn rows(&self) -> usize {
+        self.base.rows() * self.factor
     }
 
-    total_risk[grid.rows() - 1][grid.cols() - 1]
+    fn cols(&self) -> usize {
+        self.base.cols() * self.factor
+    }
+
+    fn get_risk(&self, r: usize, c: usize) -> u32 {
+        let (rows, cols) = (self.base.rows(), self.base.cols());
+        let add = (r / rows + c / cols) as u32;
+        (self.base.get_risk(r % rows, c % cols) - 1 + add) % 9 + 1
+    }
 }
 
 #[derive(Debug)]
 struct Entry {
-    cost: i64,
+    priority: u64,
+    cost: u32,
     r: usize,
     c: usize
 }
 
-impl Entry {
-    fn new(cost: i64, r: usize, c: usize) -> Self {
-        Self {
-            cost,
-            r,
-            c
-        }
-    }
-}
-
 impl Eq for Entry {}
 
 impl PartialEq<Self> for Entry {
     fn eq(&self, other: &Self) -> bool {
-        self.cost == other.cost
+        self.priority == other.priority
     }
 }
 
 impl PartialOrd<Self> for Entry {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        other.cost.partial_cmp(&self.cost)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for Entry {
     fn cmp(&self, other: &Self) -> Ordering {
-        other.cost.cmp(&self.cost)
+        other.priority.cmp(&self.priority)
     }
 }
 
-fn get_neighbours(grid: &Grid, entry: &Entry) -> Vec<Entry> {
-    let d_pos = vec![(-1, 0), (0, -1), (0, 1), (1, 0)];
-    // let d_pos = vec![(0, 1), (1, 0)];
+#[derive(Debug, Clone)]
+struct Route {
+    risk: u32,
+    path: Vec<(usize, usize)>,
+}
 
-    let mut results = Vec::new();
-    for (dr, dc) in d_pos {
-        let r_n = entry.r as i32 + dr;
-        let c_n = entry.c as i32 + dc;
+const D_POS: [(i64, i64); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+const NO_PARENT: u8 = u8::MAX;
 
-        if r_n  < 0 || c_n < 0 || r_n >= grid.rows() as i32 || c_n >= grid.cols() as i32 {
-            continue;
-        }
+// what the search knows about a cell it has reached
+#[derive(Debug, Clone, Copy)]
+struct Visit {
+    best: u32,
+    // index into D_POS of the step that reached the cell
+    parent: u8,
+    done: bool,
+}
+
+const UNSEEN: Visit = Visit { best: u32::MAX, parent: NO_PARENT, done: false };
+const BLOCK: usize = 64;
+
+// visits kept in BLOCK x BLOCK blocks, allocated when the search first
+// reaches one, so a huge tiled map only costs the area actually explored
+struct Visits {
+    blocks: Vec<Option<Box<[Visit]>>>,
+    block_cols: usize,
+}
 
-        results.push(Entry::new(entry.cost + grid.get_risk(r_n as usize, c_n as usize), r_n as usize, c_n as usize));
+impl Visits {
+    fn new(rows: usize, cols: usize) -> Self {
+        let block_cols = cols.div_ceil(BLOCK);
+        Visits { blocks: vec![None; rows.div_ceil(BLOCK) * block_cols], block_cols }
     }
 
-    results
+    fn get(&self, r: usize, c: usize) -> Visit {
+        match &self.blocks[(r / BLOCK) * self.block_cols + c / BLOCK] {
+            Some(block) => block[(r % BLOCK) * BLOCK + c % BLOCK],
+            None => UNSEEN,
+        }
+    }
+
+    fn get_mut(&mut self, r: usize, c: usize) -> &mut Visit {
+        let block = self.blocks[(r / BLOCK) * self.block_cols + c / BLOCK].get_or_insert_with(|| vec![UNSEEN; BLOCK * BLOCK].into_boxed_slice());
+        &mut block[(r % BLOCK) * BLOCK + c % BLOCK]
+    }
 }
 
-fn part_2_shortest_path(grid: &Grid) -> i64 {
-    let mut b_heap = BinaryHeap::<Entry>::new();
-    let mut viz = vec![vec![false; grid.cols()]; grid.rows()];
+// A* from the top left to the bottom right; every cell costs at least 1,
+// so the Manhattan distance never overestimates
+fn shortest_path(grid: &dyn RiskMap) -> Option<Route> {
+    let (rows, cols) = (grid.rows(), grid.cols());
+    if rows == 0 || cols == 0 {
+        return None;
+    }
+    let target = (rows - 1, cols - 1);
+    let heuristic = |r: usize, c: usize| (target.0 - r + target.1 - c) as u64;
 
-    b_heap.push(Entry::new(0, 0, 0));
+    let mut visits = Visits::new(rows, cols);
+    let mut b_heap = BinaryHeap::<Entry>::new();
 
-    while !b_heap.is_empty() {
-        let entry = b_heap.pop().unwrap();
+    visits.get_mut(0, 0).best = 0;
+    b_heap.push(Entry { priority: heuristic(0, 0), cost: 0, r: 0, c: 0 });
 
-        if viz[entry.r][entry.c] {
+    while let Some(entry) = b_heap.pop() {
+        let visit = visits.get_mut(entry.r, entry.c);
+        if visit.done {
             continue;
         }
-
-        if entry.r == grid.rows() - 1 && entry.c == grid.cols() - 1 {
-            return entry.cost;
+        visit.done = true;
+
+        if (entry.r, entry.c) == target {
+            let mut path = vec![target];
+            let (mut r, mut c) = target;
+            while visits.get(r, c).parent != NO_PARENT {
+                let (dr, dc) = D_POS[visits.get(r, c).parent as usize];
+                r = (r as i64 - dr) as usize;
+                c = (c as i64 - dc) as usize;
+                path.push((r, c));
+            }
+            path.reverse();
+            return Some(Route { risk: entry.cost, path });
         }
 
-        viz[entry.r][entry.c] = true;
-
-        for n in get_neighbours(grid, &entry) {
-            if viz[n.r][n.c] {
+        for (dir, (dr, dc)) in D_POS.iter().enumerate() {
+            let r_n = entry.r as i64 + dr;
+            let c_n = entry.c as i64 + dc;
+            if r_n < 0 || c_n < 0 || r_n >= rows as i64 || c_n >= cols as i64 {
                 continue;
             }
-            b_heap.push(n);
-        }
-    }
-
-    -1
-}
-
-fn modify_fn(val: i64, add: i64) -> i64 {
-    let r = (val + add) % 10;
-
-    if r < val {
-        1 + r
-    } else {
-        r
-    }
-}
-fn modify_matrix(matrix: &Vec<Vec<i64>>, add: i64) -> Vec<Vec<i64>> {
-    let mut matrix = matrix.clone();
-    for row in matrix.iter_mut() {
-        for value in row.iter_mut() {
-            *value = modify_fn(*value, add);
+            let (r_n, c_n) = (r_n as usize, c_n as usize);
+            let cost = entry.cost + grid.get_risk(r_n, c_n);
+            let visit = visits.get_mut(r_n, c_n);
+            if visit.done || cost >= visit.best {
+                continue;
+            }
+            visit.best = cost;
+            visit.parent = dir as u8;
+            b_heap.push(Entry { priority: cost as u64 + heuristic(r_n, c_n), cost, r: r_n, c: c_n });
         }
     }
-    matrix
-}
-fn make_big_grid(grid: &Grid) -> Grid {
-    let mut inner = grid.inner.clone();
-
-    for (i, row) in inner.iter_mut().enumerate() {
-        row.append(&mut grid.inner[i].clone().iter().map(|x| modify_fn(*x, 1)).collect());
-        row.append(&mut grid.inner[i].clone().iter().map(|x| modify_fn(*x, 2)).collect());
-        row.append(&mut grid.inner[i].clone().iter().map(|x| modify_fn(*x, 3)).collect());
-        row.append(&mut grid.inner[i].clone().iter().map(|x| modify_fn(*x, 4)).collect());
-    }
-
-    let inner_aux = inner.clone();
-    inner.append(&mut modify_matrix(&inner_aux, 1));
-    inner.append(&mut modify_matrix(&inner_aux, 2));
-    inner.append(&mut modify_matrix(&inner_aux, 3));
-    inner.append(&mut modify_matrix(&inner_aux, 4));
 
-    Grid::create(inner)
+    None
 }
 
 fn parse(path: &Path) -> Result<Grid> {
@@ -187,24 +201,91 @@ fn parse(path: &Path) -> Result<Grid> {
     let mut inner = Vec::new();
     for line in reader.lines() {
         let line = line?;
-        inner.push(
-            line.chars().map(|c| c as i64 - '0' as i64).collect()
-        )
+        let row = line
+            .chars()
+            .map(|c| match c.to_digit(10) {
+                Some(d) if d > 0 => Ok(d),
+                _ => bail!("invalid risk level {:?}", c),
+            })
+            .collect::<Result<Vec<u32>>>()?;
+        inner.push(row);
+    }
+    if inner.is_empty() || inner.iter().any(|r| r.len() != inner[0].len() || r.is_empty()) {
+        bail!("the cave map is not a rectangle");
     }
 
     Ok(Grid::create(inner))
 }
 
 fn main() -> Result<()> {
-    let grid = parse(&Path::new("input.txt"))?;
-    let p1 = part_1_stupid(&grid);
-    println!("Part 1 {}", p1);
+    let grid = parse(Path::new("input.txt"))?;
+    let factor: usize = match std::env::args().skip_while(|a| a != "--tiles").nth(1) {
+        Some(f) => f.parse()?,
+        None => 5,
+    };
+    if factor < 1 {
+        bail!("--tiles must be at least 1");
+    }
 
-    let grid = make_big_grid(&grid);
-    let p2 = part_1_stupid(&grid);
-    println!("Part 2 {}", p2);
+    let p1 = shortest_path(&grid).map_or(-1, |r| r.risk as i64);
+    println!("Part 1 {}", p1);
 
-    let p2 = part_2_shortest_path(&grid);
-    println!("Part 2 {}", p2);
+    let route = shortest_path(&TiledGrid { base: &grid, factor });
+    println!("Part 2 {}", route.as_ref().map_or(-1, |r| r.risk as i64));
+    if std::env::args().any(|a| a == "--path") {
+        if let Some(route) = route {
+            let steps: Vec<String> = route.path.iter().map(|(r, c)| format!("{},{}", r, c)).collect();
+            println!("{}", steps.join(" "));
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "1163751742
+1381373672
+2136511328
+3694931569
+7463417111
+1319128137
+1359912421
+3125421639
+1293138521
+2311944581";
+
+    fn example() -> Grid {
+        Grid::create(EXAMPLE.lines().map(|l| l.chars().map(|c| c.to_digit(10).unwrap()).collect()).collect())
+    }
+
+    #[test]
+    fn lowest_risk() {
+        let grid = example();
+        assert_eq!(shortest_path(&grid).unwrap().risk, 40);
+        assert_eq!(shortest_path(&TiledGrid { base: &grid, factor: 5 }).unwrap().risk, 315);
+        assert_eq!(shortest_path(&TiledGrid { base: &grid, factor: 1 }).unwrap().risk, 40);
+    }
+
+    #[test]
+    fn path_adds_up_to_the_risk() {
+        let grid = example();
+        let tiled = TiledGrid { base: &grid, factor: 5 };
+        let route = shortest_path(&tiled).unwrap();
+        assert_eq!(route.path.first(), Some(&(0, 0)));
+        assert_eq!(route.path.last(), Some(&(49, 49)));
+        for w in route.path.windows(2) {
+            assert_eq!(w[0].0.abs_diff(w[1].0) + w[0].1.abs_diff(w[1].1), 1);
+        }
+        assert_eq!(route.path[1..].iter().map(|&(r, c)| tiled.get_risk(r, c)).sum::<u32>(), 315);
+    }
+
+    #[test]
+    fn tiles_wrap_nine_to_one() {
+        let grid = Grid::create(vec![vec![8]]);
+        let tiled = TiledGrid { base: &grid, factor: 3 };
+        assert_eq!((tiled.get_risk(0, 1), tiled.get_risk(1, 1), tiled.get_risk(2, 2)), (9, 1, 3));
+        assert!(shortest_path(&TiledGrid { base: &grid, factor: 0 }).is_none());
+    }
+}
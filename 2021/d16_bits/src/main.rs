@@ -1,7 +1,8 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use anyhow::Result;
+use std::fmt;
+use anyhow::{anyhow, bail, ensure, Result};
 use bitvec::macros::internal::funty::Integral;
 use bitvec::prelude::*;
 
@@ -31,11 +32,55 @@ impl<'a, T> BitVecReader<'a, T, Msb0>
 where
     T: BitStore {
 
-    fn load<I>(&mut self, size: usize) -> I
+    fn load<I>(&mut self, size: usize) -> Result<I>
     where
         I: Integral {
+        ensure!(
+            self.pos + size <= self.inner.len(),
+            "truncated packet: needed {} bits at bit {}, only {} left",
+            size,
+            self.pos,
+            self.inner.len() - self.pos
+        );
         self.pos += size;
-        self.inner[self.pos - size..self.pos].load_be()
+        Ok(self.inner[self.pos - size..self.pos].load_be())
+    }
+
+    fn remaining(&self) -> &BitSlice<T, Msb0> {
+        &self.inner[self.pos..]
+    }
+}
+
+// deeper nesting than any real transmission, shallow enough for the stack
+const MAX_DEPTH: usize = 256;
+
+#[derive(Debug, Default)]
+struct BitWriter {
+    inner: BitVec<u8, Msb0>
+}
+
+impl BitWriter {
+    fn store(&mut self, size: usize, value: u64) -> Result<()> {
+        ensure!(size == 64 || value >> size == 0, "{} does not fit in {} bits", value, size);
+        for bit in (0..size).rev() {
+            self.inner.push((value >> bit) & 1 == 1);
+        }
+        Ok(())
+    }
+
+    fn append(&mut self, other: &BitWriter) {
+        self.inner.extend_from_bitslice(&other.inner);
+    }
+
+    // zero padded to whole hex digits
+    fn to_hex(&self) -> String {
+        self.inner
+            .chunks(4)
+            .map(|nibble| {
+                let value = nibble.iter().fold(0u32, |acc, b| (acc << 1) | *b as u32) << (4 - nibble.len());
+                char::from_digit(value, 16).unwrap().to_ascii_uppercase()
+            })
+            .collect()
     }
 }
 
@@ -51,8 +96,8 @@ enum Operation {
 }
 
 impl Operation {
-    fn from(type_id: i64) -> Operation {
-        match type_id {
+    fn from(type_id: i64) -> Result<Operation> {
+        Ok(match type_id {
             0 => Operation::Sum,
             1 => Operation::Product,
             2 => Operation::Minimum,
@@ -60,43 +105,60 @@ impl Operation {
             5 => Operation::Greater,
             6 => Operation::Less,
             7 => Operation::Equal,
-            _ => panic!("At operation")
+            t => bail!("unknown operator type id {}", t)
+        })
+    }
+
+    fn type_id(&self) -> u64 {
+        match self {
+            Operation::Sum => 0,
+            Operation::Product => 1,
+            Operation::Minimum => 2,
+            Operation::Maximum => 3,
+            Operation::Greater => 5,
+            Operation::Less => 6,
+            Operation::Equal => 7
         }
     }
 
-    fn apply(&self, packets: &[Packet]) -> i64 {
+    fn name(&self) -> &'static str {
         match self {
-            Operation::Sum => {
-                packets.iter().map(|x| x.calculate()).sum()
-            }
-            Operation::Product => {
-                packets.iter().map(|x| x.calculate()).product()
-            }
-            Operation::Minimum => {
-                packets.iter().map(|x| x.calculate()).min().unwrap()
-            }
-            Operation::Maximum => {
-                packets.iter().map(|x| x.calculate()).max().unwrap()
-            }
-            Operation::Greater => {
-                assert_eq!(packets.len(), 2);
-                let a = packets[0].calculate();
-                let b = packets[1].calculate();
-                (a > b) as i64
-            }
-            Operation::Less => {
-                assert_eq!(packets.len(), 2);
-                let a = packets[0].calculate();
-                let b = packets[1].calculate();
-                (a < b) as i64
-            }
-            Operation::Equal => {
-                assert_eq!(packets.len(), 2);
-                let a = packets[0].calculate();
-                let b = packets[1].calculate();
-                (a == b) as i64
+            Operation::Sum => "sum",
+            Operation::Product => "product",
+            Operation::Minimum => "min",
+            Operation::Maximum => "max",
+            Operation::Greater => "gt",
+            Operation::Less => "lt",
+            Operation::Equal => "eq"
+        }
+    }
+
+    // sum and product take any number of operands, min and max at least
+    // one and the comparisons exactly two
+    fn check_arity(&self, operands: usize) -> Result<()> {
+        match self {
+            Operation::Sum | Operation::Product => {}
+            Operation::Minimum | Operation::Maximum => ensure!(operands > 0, "{} packet without operands", self.name()),
+            Operation::Greater | Operation::Less | Operation::Equal => {
+                ensure!(operands == 2, "{} packet with {} operands, expected 2", self.name(), operands)
             }
         }
+        Ok(())
+    }
+
+    fn apply(&self, packets: &[Packet]) -> Result<i64> {
+        self.check_arity(packets.len())?;
+        let overflow = || anyhow!("{} overflows 64 bits", self.name());
+        let values = packets.iter().map(Packet::calculate).collect::<Result<Vec<i64>>>()?;
+        Ok(match self {
+            Operation::Sum => values.iter().try_fold(0i64, |acc, v| acc.checked_add(*v)).ok_or_else(overflow)?,
+            Operation::Product => values.iter().try_fold(1i64, |acc, v| acc.checked_mul(*v)).ok_or_else(overflow)?,
+            Operation::Minimum => *values.iter().min().unwrap(),
+            Operation::Maximum => *values.iter().max().unwrap(),
+            Operation::Greater => (values[0] > values[1]) as i64,
+            Operation::Less => (values[0] < values[1]) as i64,
+            Operation::Equal => (values[0] == values[1]) as i64,
+        })
     }
 }
 
@@ -118,10 +180,11 @@ struct Packet {
 }
 
 impl Packet {
-    fn parse_literal(wrapper: &mut BitVecReader<u8, Msb0>) -> i64 {
+    fn parse_literal(wrapper: &mut BitVecReader<u8, Msb0>) -> Result<i64> {
         let mut literal: u64 = 0;
         loop {
-            let chunk: u64 = wrapper.load(5);
+            let chunk: u64 = wrapper.load(5)?;
+            ensure!(literal >> 59 == 0, "literal at bit {} does not fit in 63 bits", wrapper.pos);
             literal = (literal << 4) | (chunk & 0xF);
 
             if (chunk & (1 << 4)) == 0 {
@@ -129,21 +192,25 @@ impl Packet {
             }
         }
 
-        literal as i64
+        Ok(literal as i64)
     }
-    fn parse(wrapper: &mut BitVecReader<u8, Msb0>) -> Packet {
-        let version: u32 = wrapper.load(3);
-        let type_id: u32 = wrapper.load(3);
+
+    fn parse(wrapper: &mut BitVecReader<u8, Msb0>, depth: usize) -> Result<Packet> {
+        ensure!(depth <= MAX_DEPTH, "packets nested deeper than {} at bit {}", MAX_DEPTH, wrapper.pos);
+        let start = wrapper.pos;
+        let version: u32 = wrapper.load(3)?;
+        let type_id: u32 = wrapper.load(3)?;
 
         let packet_type = match type_id {
-            4 => PacketType::Literal(Self::parse_literal(wrapper)),
+            4 => PacketType::Literal(Self::parse_literal(wrapper)?),
             t => {
-                let type_id_length: u32 = wrapper.load(1);
+                let op = Operation::from(t as i64)?;
+                let type_id_length: u32 = wrapper.load(1)?;
                 let length = if type_id_length == 0 {
-                    let bts: u32 = wrapper.load(15);
+                    let bts: u32 = wrapper.load(15)?;
                     Length::InBits(bts as i64)
                 } else {
-                    let packets: u32 = wrapper.load(11);
+                    let packets: u32 = wrapper.load(11)?;
                     Length::InPackets(packets as i64)
                 };
 
@@ -151,24 +218,79 @@ impl Packet {
                 match &length {
                     Length::InBits(bts) => {
                         let pos = wrapper.pos;
+                        ensure!(pos + *bts as usize <= wrapper.inner.len(), "truncated packet: operator at bit {} spans {} bits", pos, bts);
                         while wrapper.pos - pos < *bts as usize {
-                            packets.push(Self::parse(wrapper));
+                            packets.push(Self::parse(wrapper, depth + 1)?);
                         }
+                        ensure!(wrapper.pos - pos == *bts as usize, "sub-packets at bit {} overrun their {} bits", pos, bts);
                     }
                     Length::InPackets(nr) => {
                         for _ in 0..*nr {
-                            packets.push(Self::parse(wrapper));
+                            packets.push(Self::parse(wrapper, depth + 1)?);
                         }
                     }
                 }
-                PacketType::Operator(Operation::from(t as i64), length, packets)
+                op.check_arity(packets.len()).map_err(|e| anyhow!("operator at bit {}: {}", start, e))?;
+                PacketType::Operator(op, length, packets)
             }
         };
 
-        Packet {
+        Ok(Packet {
             version: version as i64,
             packet_type
+        })
+    }
+
+    // the outermost packet; whatever follows it may only be zero padding
+    fn decode(bits: &BitVec<u8, Msb0>) -> Result<Packet> {
+        let mut reader = BitVecReader::new(bits);
+        let packet = Self::parse(&mut reader, 0)?;
+        if let Some(one) = reader.remaining().first_one() {
+            bail!("non-zero padding at bit {}", reader.pos + one);
+        }
+        Ok(packet)
+    }
+
+    // the length of an operator is recomputed from its sub-packets,
+    // kept in bits or in packets as the packet says
+    fn encode(&self, writer: &mut BitWriter) -> Result<()> {
+        writer.store(3, self.version as u64)?;
+        match &self.packet_type {
+            PacketType::Literal(l) => {
+                ensure!(*l >= 0, "negative literal {}", l);
+                writer.store(3, 4)?;
+                let groups = (64 - (*l as u64).leading_zeros() as usize).div_ceil(4).max(1);
+                for g in (0..groups).rev() {
+                    let more = if g > 0 { 1 << 4 } else { 0 };
+                    writer.store(5, more | ((*l as u64 >> (4 * g)) & 0xF))?;
+                }
+            }
+            PacketType::Operator(op, length, packets) => {
+                writer.store(3, op.type_id())?;
+                let mut body = BitWriter::default();
+                for p in packets {
+                    p.encode(&mut body)?;
+                }
+                match length {
+                    Length::InBits(_) => {
+                        writer.store(1, 0)?;
+                        writer.store(15, body.inner.len() as u64)?;
+                    }
+                    Length::InPackets(_) => {
+                        writer.store(1, 1)?;
+                        writer.store(11, packets.len() as u64)?;
+                    }
+                }
+                writer.append(&body);
+            }
         }
+        Ok(())
+    }
+
+    fn to_hex(&self) -> Result<String> {
+        let mut writer = BitWriter::default();
+        self.encode(&mut writer)?;
+        Ok(writer.to_hex())
     }
 
     fn add_versions(&self) -> i64 {
@@ -180,10 +302,10 @@ impl Packet {
         sum
     }
 
-    fn calculate(&self) -> i64 {
+    fn calculate(&self) -> Result<i64> {
         match &self.packet_type {
             PacketType::Literal(l) => {
-                *l
+                Ok(*l)
             }
             PacketType::Operator(op, _, p) => {
                 op.apply(p)
@@ -192,28 +314,128 @@ impl Packet {
     }
 }
 
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.packet_type {
+            PacketType::Literal(l) => write!(f, "{}", l),
+            PacketType::Operator(op, _, packets) => {
+                write!(f, "{}(", op.name())?;
+                for (i, p) in packets.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", p)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
 fn parse(path: &Path) -> Result<BitVec<u8, Msb0>> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
-    let line = reader.lines().next().unwrap()?;
-
-    let bytes: Vec<u8> = line
-        .as_bytes()
-        .chunks(2) // Each byte is represented by 2 hex characters
-        .map(|chunk| {
-            let hex_str = std::str::from_utf8(chunk).expect("Invalid UTF-8 in hex string");
-            u8::from_str_radix(hex_str, 16).expect("Invalid hex character")
-        })
-        .collect();
+    let line = reader.lines().next().ok_or_else(|| anyhow!("empty input"))??;
+
+    from_hex(line.trim())
+}
 
-    // Load the bytes into a BitVec with MSB-first ordering
-    Ok(BitVec::from_vec(bytes))
+fn from_hex(hex: &str) -> Result<BitVec<u8, Msb0>> {
+    let mut bits = BitVec::new();
+    for c in hex.chars() {
+        let nibble = c.to_digit(16).ok_or_else(|| anyhow!("invalid hex character {:?}", c))?;
+        for bit in (0..4).rev() {
+            bits.push((nibble >> bit) & 1 == 1);
+        }
+    }
+    Ok(bits)
 }
 
 fn main() -> Result<()> {
-    let bv = parse(&Path::new("input.txt"))?;
-    let pack = Packet::parse(&mut BitVecReader::new(&bv));
+    let bv = parse(Path::new("input.txt"))?;
+    let pack = Packet::decode(&bv)?;
     println!("Part 1 {}", pack.add_versions());
-    println!("Part 2 {}", pack.calculate());
+    println!("Part 2 {}", pack.calculate()?);
+
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|a| a == "--expr") {
+        println!("{}", pack);
+    }
+    if args.iter().any(|a| a == "--encode") {
+        let hex = pack.to_hex()?;
+        println!("{}", hex);
+        ensure!(Packet::decode(&from_hex(&hex)?)? == pack, "re-encoded packet decodes differently");
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(hex: &str) -> Result<Packet> {
+        Packet::decode(&from_hex(hex)?)
+    }
+
+    #[test]
+    fn version_sums() {
+        for (hex, sum) in [
+            ("8A004A801A8002F478", 16),
+            ("620080001611562C8802118E34", 12),
+            ("C0015000016115A2E0802F182340", 23),
+            ("A0016C880162017C3686B18A3D4780", 31),
+        ] {
+            assert_eq!(decode(hex).unwrap().add_versions(), sum, "{}", hex);
+        }
+    }
+
+    #[test]
+    fn values() {
+        for (hex, value) in [
+            ("C200B40A82", 3),
+            ("04005AC33890", 54),
+            ("880086C3E88112", 7),
+            ("CE00C43D881120", 9),
+            ("D8005AC2A8F0", 1),
+            ("F600BC2D8F", 0),
+            ("9C005AC2F8F0", 0),
+            ("9C0141080250320F1802104A08", 1),
+        ] {
+            assert_eq!(decode(hex).unwrap().calculate().unwrap(), value, "{}", hex);
+        }
+    }
+
+    #[test]
+    fn expression_and_round_trip() {
+        let packet = decode("9C0141080250320F1802104A08").unwrap();
+        assert_eq!(packet.to_string(), "eq(sum(1, 3), product(2, 2))");
+        assert_eq!(decode(&packet.to_hex().unwrap()).unwrap(), packet);
+    }
+
+    #[test]
+    fn operator_arity() {
+        let err = decode("16004400").unwrap_err();
+        assert!(err.to_string().contains("gt packet with 1 operands"), "{}", err);
+
+        let empty_min = Packet { version: 0, packet_type: PacketType::Operator(Operation::Minimum, Length::InPackets(0), vec![]) };
+        assert!(decode(&empty_min.to_hex().unwrap()).is_err());
+        assert!(empty_min.calculate().is_err());
+    }
+
+    #[test]
+    fn deep_nesting() {
+        let mut packet = Packet { version: 0, packet_type: PacketType::Literal(1) };
+        for _ in 0..MAX_DEPTH + 1 {
+            packet = Packet { version: 0, packet_type: PacketType::Operator(Operation::Sum, Length::InPackets(1), vec![packet]) };
+        }
+        let err = decode(&packet.to_hex().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("nested deeper"), "{}", err);
+    }
+
+    #[test]
+    fn truncated_and_padding() {
+        assert!(decode("D2FE2").is_err());
+        assert_eq!(decode("D2FE28").unwrap().calculate().unwrap(), 2021);
+        assert!(decode("D2FE29").is_err());
+    }
+}
@@ -1,94 +1,261 @@
-use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use anyhow::{bail, Result};
 
-fn get_t_from_eq(vel: i32, target: i32) -> Option<i32> {
-    let a = -0.5;
-    let b = vel as f64 + 0.5;
-    let c = -target as f64;
-    let delta = b*b - 4.0*a*c;
+// after t steps a coordinate launched at `vel` and slowing by 1 per step
+// sits at vel*t - t(t-1)/2, a parabola peaking at t = vel + 1/2
+fn get_y_pos_from_eq(vel: i64, t: i64) -> i64 {
+    vel * t - t * (t - 1) / 2
+}
+
+// x slows down towards 0 and then stays put
+fn get_x_pos_from_eq(vel: i64, t: i64) -> i64 {
+    let sign = vel.signum();
+    sign * get_y_pos_from_eq(vel.abs(), t.min(vel.abs()))
+}
 
+// the steps t >= 1 at which get_y_pos_from_eq(vel, t) >= target, if any:
+// the integer part of the interval between the roots of
+// t^2 - (2 vel + 1) t + 2 target = 0
+fn get_t_from_eq(vel: i64, target: i64) -> Option<(i64, i64)> {
+    let b = (2 * vel + 1) as f64;
+    let delta = b * b - 8.0 * target as f64;
     if delta < 0.0 {
-        return None
+        return None;
     }
 
-    let x1 = (-b + delta.sqrt()) / (2.0*a);
-    let x2 = (-b - delta.sqrt()) / (2.0*a);
+    // the float roots are only a guess, settle them on exact positions
+    let above = |t: i64| get_y_pos_from_eq(vel, t) >= target;
+    let peak = vel.max(1);
+    if !above(peak) {
+        return None;
+    }
+    let mut lo = (((b - delta.sqrt()) / 2.0).ceil() as i64).clamp(1, peak);
+    while lo > 1 && above(lo - 1) {
+        lo -= 1;
+    }
+    while !above(lo) {
+        lo += 1;
+    }
+    let mut hi = (((b + delta.sqrt()) / 2.0).floor() as i64).max(peak);
+    while above(hi + 1) {
+        hi += 1;
+    }
+    while !above(hi) {
+        hi -= 1;
+    }
+    Some((lo, hi))
+}
 
-    return Some(x1.max(x2).floor() as i32)
+#[derive(Debug, Clone, Copy)]
+struct Target {
+    x: (i64, i64),
+    y: (i64, i64),
 }
 
-fn get_y_pos_from_eq(vel: i32, t: i32) -> i32 {
-    if t == 0 {
-        return 0;
+impl Target {
+    fn parse(s: &str) -> Result<Target> {
+        let (x1, x2, y1, y2) = scan_fmt::scan_fmt!(s.trim(), "target area: x={}..{}, y={}..{}", i64, i64, i64, i64)?;
+        if x1 > x2 || y1 > y2 {
+            bail!("empty target area {:?}", s.trim());
+        }
+        Ok(Target { x: (x1, x2), y: (y1, y2) })
     }
+}
 
-    let vel = vel as f64;
-    let t = t as f64;
-    let y = (vel * t - t*(t-1.0)*0.5) as i32;
-    return y;
+// steps `start..=end` during which x is on target; `end` is None when the
+// probe stops above the target
+#[derive(Debug, Clone, Copy)]
+struct XWindow {
+    start: i64,
+    end: Option<i64>,
 }
 
-fn get_x_pos_from_eq(vel: i32, t: i32) -> i32 {
-    let max_f = (vel*(vel+1)) / 2;
-    if t > vel {
-        max_f
-    } else {
-        let vel = vel as f64;
-        let t = t as f64;
-        let y = (vel * t - t*(t-1.0)*0.5) as i32;
-        y
+fn x_window(vel: i64, (x_min, x_max): (i64, i64)) -> Option<XWindow> {
+    if vel < 0 {
+        return x_window(-vel, (-x_max, -x_min));
+    }
+    if x_max < 0 {
+        return None;
+    }
+    let start = if x_min <= 0 { 1 } else { get_t_from_eq(vel, x_min)?.0 };
+    let end = match get_t_from_eq(vel, x_max + 1) {
+        Some((over, _)) if over <= vel => Some(over - 1),
+        _ => None,
+    };
+    match end {
+        Some(end) if end < start => None,
+        end => Some(XWindow { start, end }),
     }
 }
 
-fn solve((x_min, x_max): (i32, i32), (y_min, y_max): (i32, i32)) -> i32 {
-    for y_vel in (1..=200).rev() {
-        if let Some(t) = get_t_from_eq(y_vel, y_max) {
-            let mut t = t;
-            while get_y_pos_from_eq(y_vel, t) >= y_min {
-                if get_y_pos_from_eq(y_vel, t) <= y_max {
-                    for x_vel in (1..=200) {
-                        let x_pos = get_x_pos_from_eq(x_vel, t);
-                        if x_pos <= x_max && x_pos >= x_min {
-                            return get_x_pos_from_eq(y_vel, t);
-                        }
-                    }
-                }
-                t += 1;
+// y passes the target on the way up and again on the way down; where the two
+// passes meet (apex inside the target) they form a single window
+fn y_windows(vel: i64, (y_min, y_max): (i64, i64)) -> Vec<(i64, i64)> {
+    let Some((lo, hi)) = get_t_from_eq(vel, y_min) else {
+        return Vec::new();
+    };
+    match get_t_from_eq(vel, y_max + 1) {
+        None => vec![(lo, hi)],
+        Some((a, b)) => [(lo, a - 1), (b + 1, hi)].into_iter().filter(|(s, e)| s <= e).collect(),
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Shot {
+    velocity: (i64, i64),
+    windows: Vec<RangeInclusive<i64>>,
+}
+
+impl Shot {
+    fn apex(&self) -> i64 {
+        let vy = self.velocity.1.max(0);
+        vy * (vy + 1) / 2
+    }
+}
+
+fn shots(target: &Target) -> Result<Vec<Shot>> {
+    let (x_min, x_max) = target.x;
+    let (y_min, y_max) = target.y;
+    // a first step past the target in x can't come back
+    let x_vels = x_min.min(0)..=x_max.max(0);
+    let xs: Vec<(i64, XWindow)> = x_vels.filter_map(|vx| Some((vx, x_window(vx, target.x)?))).collect();
+
+    // a probe launched at vy > 0 rises through vy, 2vy - 1, ... and falls
+    // back through the same heights to 0 at step 2vy + 1, then to -(vy + 1)
+    let mut vy_max = y_max.max(-y_min - 1).max(0);
+    if y_min <= 0 && 0 <= y_max {
+        if let Some((vx, _)) = xs.iter().find(|(_, xw)| xw.end.is_none()) {
+            bail!("x velocity {} stops above the target and y = 0 is on target, any y velocity hits", vx);
+        }
+        if let Some(end) = xs.iter().filter_map(|(_, xw)| xw.end).max() {
+            vy_max = vy_max.max(end / 2);
+        }
+    }
+    let y_vels = y_min.min(0)..=vy_max;
+
+    let mut shots = Vec::new();
+    for vy in y_vels {
+        let ys = y_windows(vy, (y_min, y_max));
+        if ys.is_empty() {
+            continue;
+        }
+        for (vx, xw) in &xs {
+            let windows: Vec<RangeInclusive<i64>> = ys
+                .iter()
+                .map(|&(s, e)| s.max(xw.start)..=xw.end.map_or(e, |end| e.min(end)))
+                .filter(|w| !w.is_empty())
+                .collect();
+            if !windows.is_empty() {
+                shots.push(Shot { velocity: (*vx, vy), windows });
             }
         }
     }
+    Ok(shots)
+}
+
+fn main() -> Result<()> {
+    let target = Target::parse(&fs::read_to_string(Path::new("input.txt"))?)?;
+    let shots = shots(&target)?;
+
+    match shots.iter().map(Shot::apex).max() {
+        Some(p1) => println!("Part 1 {}", p1),
+        None => println!("Part 1: the target can't be hit"),
+    }
+    println!("Part 2 {}", shots.len());
 
-    0
+    if std::env::args().any(|a| a == "--shots") {
+        for shot in &shots {
+            let windows: Vec<String> = shot.windows.iter().map(|w| format!("{}..={}", w.start(), w.end())).collect();
+            let (vx, vy) = shot.velocity;
+            let t = *shot.windows[0].start();
+            println!(
+                "{},{} steps {} first hit {},{}",
+                vx,
+                vy,
+                windows.join(" "),
+                get_x_pos_from_eq(vx, t),
+                get_y_pos_from_eq(vy, t)
+            );
+        }
+    }
+    Ok(())
 }
 
-fn solve2((x_min, x_max): (i32, i32), (y_min, y_max): (i32, i32)) -> i32 {
-    let mut hset = BTreeSet::<(i32, i32)>::new();
-
-    for y_vel in -10000..=10000 {
-        if let Some(t) = get_t_from_eq(y_vel, y_max) {
-            let mut t = t;
-            while get_y_pos_from_eq(y_vel, t) >= y_min {
-                if get_y_pos_from_eq(y_vel, t) <= y_max {
-                    for x_vel in 1..=10000 {
-                        let x_pos = get_x_pos_from_eq(x_vel, t);
-                        if x_pos <= x_max && x_pos >= x_min {
-                            hset.insert((x_vel, y_vel));
-                        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the steps at which a probe is on target, one step at a time
+    fn simulate(target: &Target, (mut vx, mut vy): (i64, i64)) -> Vec<i64> {
+        let (mut x, mut y) = (0, 0);
+        let mut hits = Vec::new();
+        for t in 1.. {
+            x += vx;
+            y += vy;
+            vx -= vx.signum();
+            vy -= 1;
+            if y < target.y.0 && vy < 0 {
+                break;
+            }
+            if (target.x.0..=target.x.1).contains(&x) && (target.y.0..=target.y.1).contains(&y) {
+                hits.push(t);
+            }
+        }
+        hits
+    }
+
+    #[test]
+    fn example_target() {
+        let target = Target::parse("target area: x=20..30, y=-10..-5").unwrap();
+        let shots = shots(&target).unwrap();
+        assert_eq!(shots.iter().map(Shot::apex).max(), Some(45));
+        assert_eq!(shots.len(), 112);
+    }
+
+    #[test]
+    fn windows_match_simulation() {
+        let mut seed = 11u64;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 33) % n) as i64 - (n / 2) as i64
+        };
+        for _ in 0..300 {
+            let (a, b, c, d) = (next(41), next(41), next(41), next(41));
+            let target = Target { x: (a.min(b), a.max(b)), y: (c.min(d), c.max(d)) };
+            let Ok(shots) = shots(&target) else {
+                assert!(target.y.0 <= 0 && 0 <= target.y.1, "{:?}", target);
+                continue;
+            };
+            let mut expected = Vec::new();
+            for vx in -25..=25 {
+                for vy in -25..=60 {
+                    let hits = simulate(&target, (vx, vy));
+                    if !hits.is_empty() {
+                        expected.push(((vx, vy), hits));
                     }
                 }
-                t += 1;
             }
+            let mut found: Vec<((i64, i64), Vec<i64>)> =
+                shots.iter().map(|s| (s.velocity, s.windows.iter().cloned().flatten().collect())).collect();
+            found.sort();
+            assert_eq!(found, expected, "{:?}", target);
         }
     }
 
-    hset.len() as i32
-}
+    #[test]
+    fn closed_forms() {
+        assert_eq!(get_y_pos_from_eq(2, 5), 0);
+        assert_eq!(get_x_pos_from_eq(-3, 10), -6);
+        assert_eq!(get_t_from_eq(2, 3), Some((2, 3)));
+        assert_eq!(get_t_from_eq(2, 4), None);
+    }
 
-fn main() {
-    let t = get_t_from_eq(2, -7).unwrap_or(0);
-    let mx = solve((235, 280), (-73, -46));
-    let all = solve2((253, 280), (-73, -46));
-    let all2 = solve2((20, 30), (-10, -5));
-    println!("t {}, mx {}, all {}", t, mx, all);
-    println!("{}", get_y_pos_from_eq(4, 5));
-    println!("{}", all2);
+    #[test]
+    fn rejects_unbounded_and_empty_targets() {
+        let err = shots(&Target::parse("target area: x=2..4, y=-1..1").unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "x velocity 2 stops above the target and y = 0 is on target, any y velocity hits");
+        assert!(Target::parse("target area: x=5..1, y=-3..-2").is_err());
+    }
 }
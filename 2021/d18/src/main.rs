@@ -1,363 +1,335 @@
-use std::cell::{Ref, RefCell};
-use std::collections::LinkedList;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::ops::Add;
 use std::path::Path;
-use std::rc::{Rc, Weak};
-use crate::Content::Leaf;
-use crate::Direction::Left;
-use anyhow::Result;
-
-#[derive(Debug, Clone)]
-struct Node {
-    parent: Option<Weak<RefCell<Node>>>,
-    content: Content,
+use std::str::FromStr;
+use anyhow::{anyhow, bail, Result};
+use rayon::prelude::*;
+
+// a snailfish number as its regular numbers from left to right, each with
+// how many pairs enclose it; the tree shape follows from the depths alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Regular {
+    value: u32,
+    depth: u8,
 }
 
-#[derive(Debug, Clone)]
-enum Content {
-    Leaf(i32),
-    List(Rc<RefCell<Node>>, Rc<RefCell<Node>>)
-}
+// how deeply a reduced number may nest its pairs; one level more is
+// allowed before reducing, as long as those pairs hold regular numbers
+const MAX_DEPTH: usize = 4;
 
-#[derive(Debug, Clone)]
-enum Direction {
-    Left,
-    Right
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Snailfish {
+    items: Vec<Regular>,
 }
 
-type DList = LinkedList<Direction>;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    // the pair whose left number was at this index
+    Explode(usize),
+    Split(usize),
+}
 
-impl Node {
-    fn new() -> Self {
-        Node {
-            parent: None,
-            content: Leaf(0),
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Explode(i) => write!(f, "explode at {}", i),
+            Action::Split(i) => write!(f, "split at {}", i),
         }
     }
+}
 
-    fn create(parent: Option<Weak<RefCell<Node>>>, content: Box<dyn Fn(Option<Weak<RefCell<Node>>>) -> Content>) -> Rc<RefCell<Node>> {
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().parent = parent;
-        node.borrow_mut().content = content(Some(Rc::downgrade(&node)));
-
-        node
-    }
-
-    fn create_template<F>(parent: Option<Weak<RefCell<Node>>>, content: F)  -> Rc<RefCell<Node>>
-        where F: Fn(Option<Weak<RefCell<Node>>>) -> Content {
-        let mut node = Rc::new(RefCell::new(Node::new()));
-        node.borrow_mut().parent = parent;
-        node.borrow_mut().content = content(Some(Rc::downgrade(&node)));
-
-        node
+impl Snailfish {
+    // the pair of both numbers, not yet reduced
+    fn join(&self, other: &Snailfish) -> Snailfish {
+        let items = self
+            .items
+            .iter()
+            .chain(&other.items)
+            .map(|r| Regular { value: r.value, depth: r.depth + 1 })
+            .collect();
+        Snailfish { items }
     }
 
-    fn explode(slf: Rc<RefCell<Node>>, depth: i32, stack: &mut DList) -> bool {
-        if depth >= 4 {
-            let content = slf.borrow().get_leaf_pair().clone();
-            if let Some((l1, l2)) = content {
-                Self::modify_left(slf.borrow().parent.clone().unwrap(), l1, stack.clone());
-                Self::modify_right(slf.borrow().parent.clone().unwrap(), l2, stack.clone());
-                slf.borrow_mut().content = Content::Leaf(0);
-                return true;
-            }
+    // a pair nested inside four pairs is always two regular numbers,
+    // the leftmost one goes first
+    fn explode(&mut self) -> Option<Action> {
+        let i = self.items.iter().position(|r| r.depth as usize > MAX_DEPTH)?;
+        let (left, right) = (self.items[i], self.items[i + 1]);
+        if i > 0 {
+            self.items[i - 1].value += left.value;
         }
-        let content = slf.borrow().content.clone();
-        match content {
-            Content::Leaf(i) => {
-                return false;
-            }
-            Content::List(left, right) => {
-                stack.push_back(Direction::Left);
-                let left_node = left.clone();
-                let res = Node::explode(left_node,depth + 1, stack);
-                stack.pop_back();
-                if res {
-                    return res;
-                }
-                stack.push_back(Direction::Right);
-                let right_node = right.clone();
-                let res = Node::explode(right_node, depth + 1, stack);
-                stack.pop_back();
-                if res {
-                    return res;
-                }
-            }
+        if let Some(next) = self.items.get_mut(i + 2) {
+            next.value += right.value;
         }
-
-        return false;
+        self.items.splice(i..i + 2, [Regular { value: 0, depth: left.depth - 1 }]);
+        Some(Action::Explode(i))
     }
 
-    fn modify_left(mut parent: Weak<RefCell<Node>>, value: i32, mut stack: DList) {
-        while let Some(Left) = stack.back() {
-            stack.pop_back();
-            let p1 = parent.upgrade().unwrap().borrow().parent.clone();
-            if p1.is_some() {
-                parent = p1.unwrap();
-            }
-        }
-
-        if stack.is_empty() {
-            return;
-        }
-
-        let mut parent = parent.upgrade().unwrap();
-        if let Content::List(left, ..) = parent.clone().borrow().content.clone() {
-            parent = left.clone();
-        } else {
-            return;
-        }
+    fn split(&mut self) -> Option<Action> {
+        let i = self.items.iter().position(|r| r.value >= 10)?;
+        let Regular { value, depth } = self.items[i];
+        self.items.splice(
+            i..=i,
+            [Regular { value: value / 2, depth: depth + 1 }, Regular { value: value.div_ceil(2), depth: depth + 1 }],
+        );
+        Some(Action::Split(i))
+    }
 
-        while let Content::List(_, right) = parent.clone().borrow().content.clone() {
-            let right = right.clone();
-            parent = right;
+    fn reduce_traced(&mut self, trace: &mut dyn FnMut(Action, &Snailfish)) {
+        while let Some(action) = self.explode().or_else(|| self.split()) {
+            trace(action, self);
         }
+    }
 
-        let lf = parent.clone().borrow().content.clone();
-        if let Content::Leaf(x) = lf {
-            parent.borrow_mut().content = Content::Leaf(x + value);
-        }
+    fn reduce(&mut self) {
+        self.reduce_traced(&mut |_, _| {});
     }
 
-    fn modify_right(mut parent: Weak<RefCell<Node>>, value: i32, mut stack: DList) {
-        while let Some(Direction::Right) = stack.back() {
-            stack.pop_back();
-            let p1 = parent.upgrade().unwrap().borrow().parent.clone();
-            if p1.is_some() {
-                parent = p1.unwrap();
+    fn magnitude(&self) -> u64 {
+        fn walk(items: &[Regular], pos: &mut usize, depth: u8) -> u64 {
+            if items[*pos].depth == depth {
+                *pos += 1;
+                return items[*pos - 1].value as u64;
             }
+            3 * walk(items, pos, depth + 1) + 2 * walk(items, pos, depth + 1)
         }
+        walk(&self.items, &mut 0, 0)
+    }
+}
 
-        if stack.is_empty() {
-            return;
-        }
-
-        let mut parent = parent.upgrade().unwrap();
-        if let Content::List(_, right) = parent.clone().borrow().content.clone() {
-            parent = right.clone();
-        } else {
-            return;
-        }
-
-        while let Content::List(left, _) = parent.clone().borrow().content.clone() {
-            parent = left.clone();
-        }
-        let lf = parent.clone().borrow().content.clone();
+impl Add for &Snailfish {
+    type Output = Snailfish;
 
-        if let Content::Leaf(x) = lf {
-            parent.borrow_mut().content = Content::Leaf(x + value);
-        }
+    // a parsed number may still need reducing, which has to happen before
+    // joining pushes its pairs another level down
+    fn add(self, other: &Snailfish) -> Snailfish {
+        let reduced = |n: &Snailfish| {
+            let mut n = n.clone();
+            n.reduce();
+            n
+        };
+        let mut sum = reduced(self).join(&reduced(other));
+        sum.reduce();
+        sum
     }
+}
 
-    fn get_leaf_pair(&self) -> Option<(i32, i32)> {
-        if let Content::List(c1, c2) = &self.content {
-            let lit1 = &c1.borrow().content;
-            let lit2 = &c2.borrow().content;
-            match (lit1, lit2) {
-                (Content::Leaf(l1), Content::Leaf(l2)) => Some((*l1, *l2)),
-                (_, _) => None
-            }
-        } else {
-            None
-        }
-    }
+impl FromStr for Snailfish {
+    type Err = anyhow::Error;
 
-    fn split(slf: Rc<RefCell<Node>>) -> bool {
-        let content = slf.borrow().content.clone();
-
-        return match content {
-            Content::Leaf(i) => {
-                if i >= 10 {
-                    let div = i as f64 / 2.0;
-                    let parent = Some(Rc::downgrade(&slf));
-                    slf.borrow_mut().content = Content::List(
-                        Node::create_template(parent.clone(), |_| {
-                            Content::Leaf(div.floor() as i32)
-                        }),
-                        Node::create_template(parent, |_| {
-                            Content::Leaf(div.ceil() as i32)
-                        })
-                    );
-                    true
-                } else {
-                    false
+    fn from_str(s: &str) -> Result<Snailfish> {
+        // how far each open pair has got: 0 before its first element, 1 after
+        // it, 2 after the comma and 3 once both elements are in
+        let mut open: Vec<u8> = Vec::new();
+        let mut items = Vec::new();
+        let mut chars = s.trim().char_indices().peekable();
+        while let Some((idx, chr)) = chars.next() {
+            match chr {
+                // explode only takes pairs of two regular numbers
+                '[' if open.len() > MAX_DEPTH => bail!("pair at {} is nested deeper than {} in {:?}", idx, MAX_DEPTH + 1, s),
+                '[' if open.last().is_none_or(|n| n % 2 == 0) => open.push(0),
+                ',' if open.last() == Some(&1) => *open.last_mut().unwrap() += 1,
+                ']' if open.last() == Some(&3) => {
+                    open.pop();
+                    if let Some(parent) = open.last_mut() {
+                        *parent += 1;
+                    }
                 }
-            }
-            Content::List(left, right) => {
-                let res = Node::split(left);
-                if res {
-                    return res;
+                ']' => bail!("unbalanced ']' at {} in {:?}", idx, s),
+                d if d.is_ascii_digit() && open.last().is_some_and(|n| n % 2 == 0) => {
+                    let mut value = d.to_digit(10).unwrap();
+                    while let Some((_, d)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                        value = value
+                            .checked_mul(10)
+                            .and_then(|v| v.checked_add(d.to_digit(10).unwrap()))
+                            .ok_or_else(|| anyhow!("number at {} is too large", idx))?;
+                    }
+                    items.push(Regular { value, depth: u8::try_from(open.len())? });
+                    *open.last_mut().unwrap() += 1;
                 }
-                let res = Node::split(right);
-                if res {
-                    return res;
+                c => bail!("unexpected {:?} at {} in {:?}", c, idx, s),
+            }
+            if open.is_empty() {
+                if let Some((idx, _)) = chars.next() {
+                    bail!("trailing input at {} in {:?}", idx, s);
                 }
-
-                false
+                return Ok(Snailfish { items });
             }
         }
+        bail!("unbalanced snailfish number {:?}, {} pairs left open", s, open.len())
     }
+}
 
-    fn reduce(slf: Rc<RefCell<Node>>) {
-        loop {
-            if Node::explode(slf.clone(), 0, &mut DList::new()) {
-                // println!("Explode");
-                // Node::print(slf.clone());
-                continue;
-            }
-
-            if Node::split(slf.clone()) {
-                // println!("Split");
-                // Node::print(slf.clone());
-                continue;
+impl fmt::Display for Snailfish {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn walk(items: &[Regular], pos: &mut usize, depth: u8, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            if items[*pos].depth == depth {
+                *pos += 1;
+                return write!(f, "{}", items[*pos - 1].value);
             }
-
-            break;
+            write!(f, "[")?;
+            walk(items, pos, depth + 1, f)?;
+            write!(f, ",")?;
+            walk(items, pos, depth + 1, f)?;
+            write!(f, "]")
         }
+        walk(&self.items, &mut 0, 0, f)
     }
+}
 
-    fn add(a: Rc<RefCell<Node>>, b: Rc<RefCell<Node>>) -> Rc<RefCell<Node>> {
-        Node::create_template(None, |p| {
-            a.borrow_mut().parent = p.clone();
-            b.borrow_mut().parent = p.clone();
+fn parse(path: &Path) -> Result<Vec<Snailfish>> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
 
-            Content::List(a.clone(), b.clone())
-        })
+    let mut vc = Vec::new();
+    for line in reader.lines() {
+        vc.push(line?.parse()?);
     }
 
-    fn parse(parent: Option<Weak<RefCell<Node>>>, s: &str) -> Rc<RefCell<Node>> {
-        if s.len() == 1 {
-            return Node::create_template(parent, |_| {
-                Content::Leaf(s.parse().unwrap())
-            });
-        }
+    Ok(vc)
+}
 
-        let mut count = 0;
-        let mut split = 0;
+fn part2(v: &[Snailfish]) -> u64 {
+    (0..v.len())
+        .into_par_iter()
+        .flat_map_iter(|i| (0..v.len()).filter(move |&j| j != i).map(move |j| (i, j)))
+        .map(|(i, j)| (&v[i] + &v[j]).magnitude())
+        .max()
+        .unwrap_or(0)
+}
 
-        for (idx, chr) in s.chars().enumerate() {
-            match chr {
-                '[' => count+=1,
-                ']' => count-=1,
-                ',' => if count == 1 {
-                    split = idx;
-                    break;
-                }
-                _ => {}
-            }
+fn main() -> Result<()> {
+    let vc = parse(Path::new("input.txt"))?;
+    let trace = std::env::args().any(|a| a == "--trace");
+
+    let mut numbers = vc.iter();
+    let mut sum = numbers.next().ok_or_else(|| anyhow!("no snailfish numbers"))?.clone();
+    for n in numbers {
+        if !trace {
+            sum = &sum + n;
+            continue;
         }
-
-        Node::create_template(parent, |p| {
-            Content::List(
-                Node::parse(p.clone(), &s[1..split]),
-                Node::parse(p, &s[split+1..s.len()-1])
-            )
-        })
+        println!("  {}\n+ {}", sum, n);
+        sum = sum.join(n);
+        println!("= {}", sum);
+        sum.reduce_traced(&mut |action, now| println!("{:>16}: {}", action.to_string(), now));
     }
+    println!("{}", sum);
+    println!("Part 1 {}", sum.magnitude());
+    println!("Part 2 {}", part2(&vc));
+    Ok(())
+}
 
-    fn print_r(slf: Rc<RefCell<Node>>) {
-        let content = slf.borrow().content.clone();
-        match content {
-            Leaf(i) => {
-                print!("{}",i)
-            }
-            Content::List(l, r) => {
-                print!("[");
-                Node::print_r(l.clone());
-                print!(",");
-                Node::print_r(r.clone());
-                print!("]");
-            }
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOMEWORK: &str = "[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]
+[[[5,[2,8]],4],[5,[[9,9],0]]]
+[6,[[[6,2],[5,6]],[[7,6],[4,7]]]]
+[[[6,[0,7]],[0,9]],[4,[9,[9,0]]]]
+[[[7,[6,4]],[3,[1,3]]],[[[5,5],1],9]]
+[[6,[[7,3],[3,2]]],[[[3,8],[5,7]],4]]
+[[[[5,4],[7,7]],8],[[8,3],8]]
+[[9,3],[[9,9],[6,[4,9]]]]
+[[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]
+[[[[5,2],5],[8,[3,7]]],[[5,[7,5]],[4,4]]]";
+
+    fn numbers(text: &str) -> Vec<Snailfish> {
+        text.lines().map(|l| l.parse().unwrap()).collect()
     }
 
-    fn magnitude(slf: Rc<RefCell<Node>>) -> i32 {
-        let content = slf.borrow().content.clone();
-        match content {
-            Leaf(i) => {
-                i
-            }
-            Content::List(l, r) => {
-                3 * Node::magnitude(l) + 2 * Node::magnitude(r)
-            }
-        }
+    fn sum(numbers: &[Snailfish]) -> Snailfish {
+        numbers[1..].iter().fold(numbers[0].clone(), |acc, n| &acc + n)
     }
 
-    fn print(slf: Rc<RefCell<Node>>) {
-        Node::print_r(slf);
-        println!();
+    #[test]
+    fn homework() {
+        let numbers = numbers(HOMEWORK);
+        let total = sum(&numbers);
+        assert_eq!(total.to_string(), "[[[[6,6],[7,6]],[[7,7],[7,0]]],[[[7,7],[7,7]],[[7,8],[9,9]]]]");
+        assert_eq!(total.magnitude(), 4140);
+        assert_eq!(part2(&numbers), 3993);
     }
 
-    fn deep_copy_i(parent: Option<Weak<RefCell<Node>>>, slf: NodePtr) -> NodePtr {
-        let n = Rc::new(RefCell::new(Node::new()));
-        n.borrow_mut().parent = parent;
-
-        let content = slf.borrow().content.clone();
-        n.borrow_mut().content = match content {
-            Leaf(i) => Content::Leaf(i),
-            Content::List(l, r) => Content::List(
-                Node::deep_copy_i(Some(Rc::downgrade(&n)), l),
-                Node::deep_copy_i(Some(Rc::downgrade(&n)), r)
-            )
-        };
-
-        n
+    #[test]
+    fn magnitudes() {
+        for (text, magnitude) in [
+            ("[[1,2],[[3,4],5]]", 143),
+            ("[[[[0,7],4],[[7,8],[6,0]]],[8,1]]", 1384),
+            ("[[[[1,1],[2,2]],[3,3]],[4,4]]", 445),
+            ("[[[[8,7],[7,7]],[[8,6],[7,7]]],[[[0,7],[6,6]],[8,7]]]", 3488),
+        ] {
+            assert_eq!(text.parse::<Snailfish>().unwrap().magnitude(), magnitude);
+        }
     }
 
-    fn deep_copy(slf: Rc<RefCell<Node>>) -> NodePtr {
-        Node::deep_copy_i(None, slf)
+    #[test]
+    fn explode_then_split() {
+        let a: Snailfish = "[[[[4,3],4],4],[7,[[8,4],9]]]".parse().unwrap();
+        let mut sum = a.join(&"[1,1]".parse().unwrap());
+        let mut actions = vec![];
+        sum.reduce_traced(&mut |action, now| actions.push((action, now.to_string())));
+        assert_eq!(actions, vec![
+            (Action::Explode(0), "[[[[0,7],4],[7,[[8,4],9]]],[1,1]]".to_string()),
+            (Action::Explode(4), "[[[[0,7],4],[15,[0,13]]],[1,1]]".to_string()),
+            (Action::Split(3), "[[[[0,7],4],[[7,8],[0,13]]],[1,1]]".to_string()),
+            (Action::Split(6), "[[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]".to_string()),
+            (Action::Explode(6), "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]".to_string()),
+        ]);
+        assert_eq!(sum, sum_of(&["[[[[4,3],4],4],[7,[[8,4],9]]]", "[1,1]"]));
     }
-}
-
-type NodePtr = Rc<RefCell<Node>>;
-
-fn parse(path: &Path) -> Result<Vec<NodePtr>> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
 
-    let mut vc = Vec::new();
-    for line in reader.lines() {
-        let line = line?;
-        vc.push(Node::parse(None, &line));
+    fn sum_of(lines: &[&str]) -> Snailfish {
+        sum(&lines.iter().map(|l| l.parse().unwrap()).collect::<Vec<_>>())
     }
 
-    Ok(vc)
-}
-
-fn part2(v: &Vec<NodePtr>) -> i32 {
-    let mut max = 0;
-
-    for i in 0..v.len() {
-        for j in 0..v.len() {
-            if i == j {
-                continue
-            }
-            let n1 = Node::deep_copy(v[i].clone());
-            let n2 = Node::deep_copy(v[j].clone());
-
-            let sum = Node::add(n1, n2);
-
-            Node::reduce(sum.clone());
-            max = max.max(Node::magnitude(sum));
+    #[test]
+    fn rejects_malformed_numbers() {
+        for text in ["[[[[[[1,2],3],4],5],6],7]", "[[[[[[1,2],3],4],5],6]", "[1,2]]", "[[1,2]", "[1]", "[1,2,3]", "[1,[2,3]4]", "[[1,2][3,4]]", "5", ""] {
+            assert!(text.parse::<Snailfish>().is_err(), "{:?} parsed", text);
         }
+        assert_eq!(
+            "[[[[[[1,2],3],4],5],6],7]".parse::<Snailfish>().unwrap_err().to_string(),
+            "pair at 5 is nested deeper than 5 in \"[[[[[[1,2],3],4],5],6],7]\""
+        );
     }
 
-    max
-}
+    #[test]
+    fn explode_examples() {
+        for (before, after) in [
+            ("[[[[[9,8],1],2],3],4]", "[[[[0,9],2],3],4]"),
+            ("[7,[6,[5,[4,[3,2]]]]]", "[7,[6,[5,[7,0]]]]"),
+            ("[[6,[5,[4,[3,2]]]],1]", "[[6,[5,[7,0]]],3]"),
+            ("[[3,[2,[1,[7,3]]]],[6,[5,[4,[3,2]]]]]", "[[3,[2,[8,0]]],[9,[5,[4,[3,2]]]]]"),
+        ] {
+            let mut n: Snailfish = before.parse().unwrap();
+            assert_eq!(n.to_string(), before);
+            n.explode();
+            assert_eq!(n.to_string(), after);
+        }
+        // unreduced operands are reduced before they are added
+        let sum = &"[[[[[9,8],1],2],3],4]".parse::<Snailfish>().unwrap() + &"[1,1]".parse().unwrap();
+        assert_eq!(sum.to_string(), "[[[[5,0],9],4],[1,1]]");
+    }
 
-fn main() -> Result<()> {
-    let vc = parse(&Path::new("input.txt"))?;
-    let p2 = part2(&vc);
-
-    let sum = vc.into_iter().reduce(|acc, e| {
-        let n = Node::add(acc.clone(), e.clone());
-        Node::reduce(n.clone());
-        n
-    }).unwrap();
-    Node::print(sum.clone());
-    let magn = Node::magnitude(sum);
-    println!("Part 1 {}", magn);
-    println!("Part 2 {}", p2);
-    Ok(())
+    #[test]
+    fn trace_output_parses_back() {
+        let numbers = numbers(HOMEWORK);
+        let mut lines = Vec::new();
+        let mut total = numbers[0].clone();
+        for n in &numbers[1..] {
+            total = total.join(n);
+            lines.push(total.to_string());
+            total.reduce_traced(&mut |_, now| lines.push(now.to_string()));
+        }
+        assert!(lines.len() > 100);
+        for line in &lines {
+            let parsed: Snailfish = line.parse().unwrap();
+            assert_eq!(&parsed.to_string(), line);
+        }
+        assert_eq!(lines.last().unwrap(), &sum(&numbers).to_string());
+    }
 }
@@ -1,12 +1,15 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write as _;
+use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::ops::{Add, Sub};
 use std::path::Path;
 use anyhow::Result;
-use scan_fmt::parse::scan;
 
-#[derive(Debug, Clone, Copy)]
+// named after the new x and z, N for negated
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Orientation {
     XZ,  // (X, Y, Z)
     XY,  // (X, -Z, Y)
@@ -110,6 +113,13 @@ impl Orientation {
             Orientation::NZNY => {Point::new(-z, x, -y)}
         }
     }
+
+    // the orientation that applies `inner` first and then `self`
+    fn compose(&self, inner: &Orientation) -> Orientation {
+        let probe = Point::new(1, 2, 3);
+        let target = self.reorient(&inner.reorient(&probe));
+        *Self::all_variants().iter().find(|o| o.reorient(&probe) == target).unwrap()
+    }
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -131,6 +141,11 @@ impl Point {
             (p1.y - p2.y).abs() +
             (p1.z - p2.z).abs()
     }
+
+    fn squared_distance(p1: &Point, p2: &Point) -> i64 {
+        let d = *p1 - *p2;
+        d.x as i64 * d.x as i64 + d.y as i64 * d.y as i64 + d.z as i64 * d.z as i64
+    }
 }
 
 impl Add for Point {
@@ -157,40 +172,25 @@ impl Sub for Point {
     }
 }
 
-fn find_translation_and_orientation(s1: &Vec<Point>, s2: &Vec<Point>) -> Option<(Point, Orientation)> {
-
-    for s1_reference in s1 {
-        let mut s1_set = HashSet::new();
-        for s1_point in s1 {
-            if s1_reference == s1_point { continue; }
-            s1_set.insert(*s1_reference - *s1_point);
-        }
 
-        for orientation in Orientation::all_variants() {
-            for s2_reference in s2 {
-                let mut count = 1;
-
-                for s2_point in s2 {
-                    if s2_reference == s2_point { continue; }
-                    let point = orientation.reorient(s2_reference) - orientation.reorient(s2_point);
-                    if s1_set.contains(&point) {
-                        count += 1;
-                    }
-                }
-
-                if count >= 12 {
-                    return Some((
-                        *s1_reference - orientation.reorient(s2_reference),
-                        orientation.clone()
-                    ));
-                }
-            }
+// squared distances between every two beacons of a scanner; they don't
+// change with position or orientation, so scanners sharing `overlap`
+// beacons share at least overlap * (overlap - 1) / 2 of them
+fn fingerprint(scanner: &[Point]) -> HashMap<i64, usize> {
+    let mut distances = HashMap::new();
+    for (i, p1) in scanner.iter().enumerate() {
+        for p2 in &scanner[i + 1..] {
+            *distances.entry(Point::squared_distance(p1, p2)).or_insert(0) += 1;
         }
     }
-    None
+    distances
+}
+
+fn shared_distances(f1: &HashMap<i64, usize>, f2: &HashMap<i64, usize>) -> usize {
+    f1.iter().map(|(d, c)| (*c).min(*f2.get(d).unwrap_or(&0))).sum()
 }
 
-fn find_translation_and_orientation_efficient(s1: &Vec<Point>, s2: &Vec<Point>) -> Option<(Point, Orientation)> {
+fn find_translation_and_orientation_efficient(s1: &[Point], s2: &[Point], overlap: usize) -> Option<(Point, Orientation)> {
     for orientation in Orientation::all_variants() {
         let s2_oriented = s2.iter().map(|x| orientation.reorient(x)).collect::<Vec<Point>>();
         let mut translation_counts = HashMap::new();
@@ -198,14 +198,13 @@ fn find_translation_and_orientation_efficient(s1: &Vec<Point>, s2: &Vec<Point>)
         for s1_point in s1 {
             for s2_point in &s2_oriented {
                 let translation = s1_point.sub(*s2_point);
-                *translation_counts.entry(translation).or_insert(1) += 1;
+                *translation_counts.entry(translation).or_insert(0) += 1;
             }
         }
 
         for (translation, count) in translation_counts {
-            if count >= 12 {
-                // let transform = Box::new(move |p: &Point3D| rotation(p).add(&translation));
-                return Some((translation, orientation.clone()));
+            if count >= overlap {
+                return Some((translation, *orientation));
             }
         }
     }
@@ -213,76 +212,78 @@ fn find_translation_and_orientation_efficient(s1: &Vec<Point>, s2: &Vec<Point>)
     None
 }
 
-fn translate_relative_to(node: i32, graph: &HashMap<i32, Vec<(i32, Point, Orientation)>>, viz: &mut HashSet<i32>, scanners: &Vec<Vec<Point>>) -> Vec<Point> {
-    viz.insert(node);
-
-    let mut result = vec![];
-    for (neigh, n_p, n_o) in graph.get(&node).cloned().unwrap_or_default() {
-        if viz.contains(&neigh) { continue }
-        result.append(
-            &mut translate_relative_to(neigh, graph, viz, scanners)
-                .iter()
-                .map(|p| {
-                    n_p + n_o.reorient(p)
-                })
-                .collect()
-        );
+type Graph = HashMap<usize, Vec<(usize, Point, Orientation)>>;
+
+fn get_graph(scanners: &[Vec<Point>], overlap: usize) -> Graph {
+    let mut graph = Graph::new();
+    let fingerprints: Vec<HashMap<i64, usize>> = scanners.iter().map(|s| fingerprint(s)).collect();
+    let needed = overlap * overlap.saturating_sub(1) / 2;
+
+    for (i, scanner1) in scanners.iter().enumerate() {
+        for (j, scanner2) in scanners.iter().enumerate() {
+            if i == j || shared_distances(&fingerprints[i], &fingerprints[j]) < needed {
+                continue;
+            }
+            if let Some((p, t)) = find_translation_and_orientation_efficient(scanner1, scanner2, overlap) {
+                graph.entry(i).or_default().push((j, p, t));
+            }
+        }
     }
 
-    result.append(&mut scanners[node as usize].clone());
-    result
+    graph
+}
+
+// where each scanner sits and how it is turned, seen from scanner 0
+#[derive(Debug, Clone, Copy)]
+struct Pose {
+    position: Point,
+    orientation: Orientation,
 }
 
-fn translate_origins_relative_to(node: i32, graph: &HashMap<i32, Vec<(i32, Point, Orientation)>>, viz: &mut HashSet<i32>) -> Vec<Point> {
-    viz.insert(node);
-
-    let mut result = vec![];
-    for (neigh, n_p, n_o) in graph.get(&node).cloned().unwrap_or_default() {
-        if viz.contains(&neigh) { continue }
-        result.append(
-            &mut translate_origins_relative_to(neigh, graph, viz)
-                .iter()
-                .map(|p| {
-                    n_p + n_o.reorient(p)
-                })
-                .collect()
-        );
+impl Pose {
+    fn place(&self, p: &Point) -> Point {
+        self.position + self.orientation.reorient(p)
     }
-
-    result.push(Point::new(0, 0, 0));
-    result
 }
 
-fn get_graph(scanners: &Vec<Vec<Point>>) -> HashMap<i32, Vec<(i32, Point, Orientation)>> {
-    let mut graph = HashMap::<i32, Vec<(i32, Point, Orientation)>>::new();
+struct Map {
+    poses: Vec<Option<Pose>>,
+    beacons: Vec<Point>,
+}
 
-    for (i, scanner1) in scanners.iter().enumerate() {
-        for (j, scanner2) in scanners.iter().enumerate() {
-            if i == j { continue; }
-            if let Some((p, t)) = find_translation_and_orientation_efficient(scanner1, scanner2) {
-                graph
-                    .entry(i as i32)
-                    .and_modify(|v| v.push((j as i32, p, t)))
-                    .or_insert(vec![(j as i32, p, t)]);
+fn build_map(graph: &Graph, scanners: &[Vec<Point>]) -> Map {
+    let mut poses = vec![None; scanners.len()];
+    poses[0] = Some(Pose { position: Point::new(0, 0, 0), orientation: Orientation::XZ });
+    let mut queue = VecDeque::from([0]);
+    while let Some(node) = queue.pop_front() {
+        let pose = poses[node].unwrap();
+        for (neigh, n_p, n_o) in graph.get(&node).into_iter().flatten() {
+            if poses[*neigh].is_some() {
+                continue;
             }
+            poses[*neigh] = Some(Pose { position: pose.place(n_p), orientation: pose.orientation.compose(n_o) });
+            queue.push_back(*neigh);
         }
     }
 
-    graph
-}
+    let beacons: HashSet<Point> = scanners
+        .iter()
+        .zip(&poses)
+        .filter_map(|(s, pose)| pose.map(|pose| s.iter().map(move |p| pose.place(p))))
+        .flatten()
+        .collect();
+    let mut beacons: Vec<Point> = beacons.into_iter().collect();
+    beacons.sort();
 
-fn part1(graph: &HashMap<i32, Vec<(i32, Point, Orientation)>>, scanners: &Vec<Vec<Point>>) -> usize {
-    let points = translate_relative_to(0, graph, &mut HashSet::new(), &scanners);
-    let mut hset = HashSet::new();
-    for p in points {
-        hset.insert(p);
-    }
+    Map { poses, beacons }
+}
 
-    hset.len()
+fn part1(map: &Map) -> usize {
+    map.beacons.len()
 }
 
-fn part2(graph: &HashMap<i32, Vec<(i32, Point, Orientation)>>) -> i32 {
-    let origins = translate_origins_relative_to(0, graph, &mut HashSet::new());
+fn part2(map: &Map) -> i32 {
+    let origins: Vec<Point> = map.poses.iter().flatten().map(|p| p.position).collect();
 
     let mut mx = 0;
     for p1 in &origins {
@@ -297,22 +298,62 @@ fn part2(graph: &HashMap<i32, Vec<(i32, Point, Orientation)>>) -> i32 {
     mx
 }
 
+fn to_csv(map: &Map) -> String {
+    let mut out = String::from("kind,scanner,x,y,z,orientation\n");
+    for (id, pose) in map.poses.iter().enumerate() {
+        if let Some(Pose { position: p, orientation }) = pose {
+            writeln!(out, "scanner,{},{},{},{},{:?}", id, p.x, p.y, p.z, orientation).unwrap();
+        }
+    }
+    for p in &map.beacons {
+        writeln!(out, "beacon,,{},{},{},", p.x, p.y, p.z).unwrap();
+    }
+    out
+}
+
+fn to_json(map: &Map) -> String {
+    let scanners: Vec<String> = map
+        .poses
+        .iter()
+        .enumerate()
+        .filter_map(|(id, pose)| {
+            let Pose { position: p, orientation } = (*pose)?;
+            Some(format!(
+                "    {{\"id\": {}, \"position\": [{}, {}, {}], \"orientation\": \"{:?}\"}}",
+                id, p.x, p.y, p.z, orientation
+            ))
+        })
+        .collect();
+    let beacons: Vec<String> = map.beacons.iter().map(|p| format!("    [{}, {}, {}]", p.x, p.y, p.z)).collect();
+    let unplaced: Vec<String> = map
+        .poses
+        .iter()
+        .enumerate()
+        .filter(|(_, p)| p.is_none())
+        .map(|(id, _)| id.to_string())
+        .collect();
+    format!(
+        "{{\n  \"scanners\": [\n{}\n  ],\n  \"beacons\": [\n{}\n  ],\n  \"unplaced\": [{}]\n}}\n",
+        scanners.join(",\n"),
+        beacons.join(",\n"),
+        unplaced.join(", ")
+    )
+}
+
 fn parse(path: &Path) -> Result<Vec<Vec<Point>>> {
     let mut v = vec![];
     let file = File::open(path)?;
     let reader = BufReader::new(file);
     let mut lines = reader.lines();
 
-    while let Some(_) = lines.next() {
+    while lines.next().is_some() {
         let mut scanner = vec![];
         loop {
             let line = lines.next().transpose()?;
 
-            if line.is_none() {
+            let Some(line) = line else {
                 break;
-            }
-
-            let line = line.unwrap();
+            };
             if line.is_empty() {
                 break;
             }
@@ -327,11 +368,115 @@ fn parse(path: &Path) -> Result<Vec<Vec<Point>>> {
 }
 
 fn main() -> Result<()> {
-    let scanners = parse(&Path::new("input.txt"))?;
-    let graph = get_graph(&scanners);
-    let p1 = part1(&graph, &scanners);
-    let p2 = part2(&graph);
-    println!("Part 1 {}", p1);
-    println!("Part 2 {}", p2);
+    let args: Vec<String> = std::env::args().collect();
+    let flag = |name: &str| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1));
+    let overlap: usize = flag("--overlap").map(|s| s.parse()).transpose()?.unwrap_or(12);
+
+    let scanners = parse(Path::new("input.txt"))?;
+    let graph = get_graph(&scanners, overlap);
+    let map = build_map(&graph, &scanners);
+
+    for (id, pose) in map.poses.iter().enumerate() {
+        if pose.is_some() {
+            continue;
+        }
+        if graph.get(&id).is_none_or(|e| e.is_empty()) {
+            eprintln!("scanner {} overlaps no other scanner", id);
+        } else {
+            eprintln!("scanner {} is not connected to scanner 0", id);
+        }
+    }
+
+    println!("Part 1 {}", part1(&map));
+    println!("Part 2 {}", part2(&map));
+
+    if let Some(path) = flag("--csv") {
+        fs::write(path, to_csv(&map))?;
+    }
+    if let Some(path) = flag("--json") {
+        fs::write(path, to_json(&map))?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn inverse(o: &Orientation) -> Orientation {
+        *Orientation::all_variants().iter().find(|i| o.compose(i) == Orientation::XZ).unwrap()
+    }
+
+    // scanners in known poses, each reporting the beacons of its own group
+    // and of the next one, so neighbours share `shared` beacons
+    fn survey(poses: &[Pose], shared: usize) -> (Vec<Vec<Point>>, Vec<Point>) {
+        let mut seed = 19u64;
+        let mut next = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 33) % 2001) as i32 - 1000
+        };
+        let groups: Vec<Vec<Point>> = (0..=poses.len()).map(|_| (0..shared).map(|_| Point::new(next(), next(), next())).collect()).collect();
+        let scanners = poses
+            .iter()
+            .enumerate()
+            .map(|(k, pose)| {
+                let back = inverse(&pose.orientation);
+                groups[k].iter().chain(&groups[k + 1]).map(|&b| back.reorient(&(b - pose.position))).collect()
+            })
+            .collect();
+        let mut beacons: Vec<Point> = groups.into_iter().flatten().collect();
+        beacons.sort();
+        (scanners, beacons)
+    }
+
+    #[test]
+    fn orientations_form_a_group() {
+        let all = Orientation::all_variants();
+        let probe = Point::new(1, 2, 3);
+        let images: HashSet<Point> = all.iter().map(|o| o.reorient(&probe)).collect();
+        assert_eq!(images.len(), 24);
+        for a in all {
+            assert_eq!(inverse(a).compose(a), Orientation::XZ);
+            for b in all {
+                assert_eq!(a.compose(b).reorient(&probe), a.reorient(&b.reorient(&probe)));
+            }
+        }
+    }
+
+    #[test]
+    fn reconstructs_the_survey() {
+        let variants = Orientation::all_variants();
+        let poses = vec![
+            Pose { position: Point::new(0, 0, 0), orientation: Orientation::XZ },
+            Pose { position: Point::new(68, -1246, -43), orientation: variants[5] },
+            Pose { position: Point::new(1105, -1205, 1229), orientation: variants[13] },
+            Pose { position: Point::new(-92, -2380, -20), orientation: variants[22] },
+        ];
+        let (scanners, beacons) = survey(&poses, 12);
+        let map = build_map(&get_graph(&scanners, 12), &scanners);
+        assert_eq!(map.beacons, beacons);
+        assert_eq!(part1(&map), 60);
+        for (found, pose) in map.poses.iter().zip(&poses) {
+            let found = found.unwrap();
+            assert_eq!((found.position, found.orientation), (pose.position, pose.orientation));
+        }
+        // scanners 2 and 3 are furthest apart
+        assert_eq!(part2(&map), 1197 + 1175 + 1249);
+    }
+
+    #[test]
+    fn reports_unplaced_scanners() {
+        let poses = vec![
+            Pose { position: Point::new(0, 0, 0), orientation: Orientation::XZ },
+            Pose { position: Point::new(10, 20, 30), orientation: Orientation::ZY },
+        ];
+        let (mut scanners, _) = survey(&poses, 12);
+        // too few shared beacons left to place the second scanner
+        scanners[1].drain(..2);
+        let map = build_map(&get_graph(&scanners, 12), &scanners);
+        assert!(map.poses[1].is_none());
+        assert!(to_json(&map).ends_with("\"unplaced\": [1]\n}\n"));
+        assert_eq!(to_csv(&map).lines().nth(1), Some("scanner,0,0,0,0,XZ"));
+        assert_eq!(part2(&map), 0);
+    }
+}
@@ -1,129 +1,289 @@
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use anyhow::Result;
-type Image = Vec<Vec<char>>;
-
-fn expand(image: &Image, round: i32) -> Image {
-    let mut expanded = image.clone();
-    let expanded_cols = image[0].len();
-    let elm = if round % 2 == 0 {
-        '#'
-    } else {
-        '.'
-    };
+use anyhow::{anyhow, bail, Result};
+
+struct Algorithm {
+    table: [bool; 512],
+    // four output pixels at once, keyed by the six columns of the three
+    // rows around them (leftmost column in the lowest bit)
+    kernel: Vec<u8>,
+}
 
-    expanded.insert(0, vec![elm; expanded_cols + 4]);
-    expanded.insert(0, vec![elm; expanded_cols + 4]);
-    for i in 2..expanded.len() {
-        expanded[i].insert(0, elm);
-        expanded[i].insert(0, elm);
-        expanded[i].push(elm);
-        expanded[i].push(elm);
+impl Algorithm {
+    fn new(table: [bool; 512]) -> Self {
+        let kernel = (0..1usize << 18)
+            .map(|key| {
+                let (top, mid, bot) = (key >> 12, key >> 6 & 63, key & 63);
+                let triple = |cols: usize, p: usize| (cols >> p & 1) << 2 | (cols >> (p + 1) & 1) << 1 | (cols >> (p + 2) & 1);
+                (0..4)
+                    .filter(|&p| table[triple(top, p) << 6 | triple(mid, p) << 3 | triple(bot, p)])
+                    .fold(0u8, |acc, p| acc | 1 << p)
+            })
+            .collect();
+        Algorithm { table, kernel }
     }
-    expanded.push(vec![elm; expanded_cols + 4]);
-    expanded.push(vec![elm; expanded_cols + 4]);
+}
 
-    expanded
+// a finite window of pixels, one bit each, in an infinite plane whose every
+// other pixel has the `background` value
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Image {
+    width: usize,
+    height: usize,
+    rows: Vec<Vec<u64>>,
+    background: bool,
 }
 
-fn enhance(image: &Image, algorithm: &Vec<char>, round: i32) -> Image {
-    let image = expand(image, round);
-    let mut enhanced = image.clone();
-    for i in 1..image.len() - 1 {
-        for j in 1..image[0].len() - 1 {
-            let mut index: usize = 0;
-
-            for k_i in -1i32..=1 {
-                for k_j in -1i32..=1 {
-                    let ii = i as i32 + k_i;
-                    let jj = j as i32 + k_j;
-                    index = index << 1;
-                    index = index | if image[ii as usize][jj as usize] == '.' {
-                        0
-                    } else {
-                        1
-                    };
-                }
-            }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lit {
+    Finite(usize),
+    Infinite,
+}
 
-            enhanced[i][j] = algorithm[index];
+impl fmt::Display for Lit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lit::Finite(n) => write!(f, "{}", n),
+            Lit::Infinite => write!(f, "infinite"),
         }
     }
+}
 
-    enhanced.remove(0);
-    enhanced.pop();
+impl Image {
+    fn new(width: usize, height: usize, background: bool) -> Self {
+        Image { width, height, rows: vec![vec![0; width.div_ceil(64)]; height], background }
+    }
 
-    for i in 0..enhanced.len() {
-        enhanced[i].remove(0);
-        enhanced[i].pop();
+    fn set(&mut self, i: usize, j: usize, lit: bool) {
+        let word = &mut self.rows[i][j / 64];
+        if lit {
+            *word |= 1 << (j % 64);
+        } else {
+            *word &= !(1 << (j % 64));
+        }
     }
 
-    enhanced
-}
+    fn get(&self, i: isize, j: isize) -> bool {
+        if i < 0 || j < 0 || i >= self.height as isize || j >= self.width as isize {
+            return self.background;
+        }
+        let (i, j) = (i as usize, j as usize);
+        self.rows[i][j / 64] >> (j % 64) & 1 == 1
+    }
 
-fn print(image: &Image) {
-    for row in image {
-        for col in row {
-            print!("{}", col);
+    // the row with `pad` background pixels on either side and a spare
+    // word, so the kernel can read it without bounds checks
+    fn padded_row(&self, i: isize, pad: usize) -> Vec<u64> {
+        let width = self.width + 2 * pad;
+        let fill = if self.background { u64::MAX } else { 0 };
+        if i < 0 || i >= self.height as isize {
+            return vec![fill; width.div_ceil(64) + 1];
+        }
+        let mut out = vec![0; width.div_ceil(64) + 1];
+        for (t, w) in self.rows[i as usize].iter().enumerate() {
+            out[t] |= w << pad;
+            out[t + 1] |= w >> (64 - pad);
+        }
+        if self.background {
+            out[0] |= (1 << pad) - 1;
+            for k in pad + self.width..out.len() * 64 {
+                out[k / 64] |= 1 << (k % 64);
+            }
         }
-        println!();
+        out
     }
-}
 
-fn part1(image: &Image, algorithm: &Vec<char>) -> usize {
-    let image = enhance(image, algorithm, 1);
-    print(&image);
+    // every output pixel looks at the 3x3 square around it; the window
+    // grows by one pixel on each side and the background turns into
+    // whatever a square full of background becomes
+    fn enhance(&self, algorithm: &Algorithm) -> Image {
+        let background = algorithm.table[if self.background { 511 } else { 0 }];
+        let mut out = Image::new(self.width + 2, self.height + 2, background);
 
-    println!();
-    println!();
+        // six columns from padded column k on
+        let chunk = |row: &[u64], k: usize| {
+            let (w, off) = (k / 64, k % 64);
+            ((row[w] as u128 | (row[w + 1] as u128) << 64) >> off) as usize & 63
+        };
+        let mut window: Vec<Vec<u64>> = (-3..0).map(|i| self.padded_row(i, 2)).collect();
+        for oi in 0..out.height {
+            window.remove(0);
+            window.push(self.padded_row(oi as isize, 2));
+            let (top, mid, bot) = (&window[0], &window[1], &window[2]);
 
-    let image = enhance(&image, algorithm, 2);
-    print(&image);
+            // output column oj is centred on padded column oj + 1
+            let row = &mut out.rows[oi];
+            for oj in (0..out.width).step_by(4) {
+                let key = chunk(top, oj) << 12 | chunk(mid, oj) << 6 | chunk(bot, oj);
+                row[oj / 64] |= (algorithm.kernel[key] as u64) << (oj % 64);
+            }
+            if !out.width.is_multiple_of(64) {
+                *row.last_mut().unwrap() &= (1 << (out.width % 64)) - 1;
+            }
+        }
+        out
+    }
 
-    image
-        .iter()
-        .flat_map(|x| x.iter())
-        .filter(|x| **x == '#')
-        .count()
+    fn lit(&self) -> Lit {
+        if self.background {
+            return Lit::Infinite;
+        }
+        Lit::Finite(self.rows.iter().flatten().map(|w| w.count_ones() as usize).sum())
+    }
 }
 
-fn part2(image: &Image, algorithm: &Vec<char>) -> usize {
+impl fmt::Display for Image {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for i in 0..self.height {
+            let row: String = (0..self.width).map(|j| if self.get(i as isize, j as isize) { '#' } else { '.' }).collect();
+            writeln!(f, "{}", row)?;
+        }
+        Ok(())
+    }
+}
+
+fn run(image: &Image, algorithm: &Algorithm, rounds: usize) -> Image {
     let mut image = image.clone();
-    for i in 1..=50 {
-        image = enhance(&image, algorithm, i);
+    for _ in 0..rounds {
+        image = image.enhance(algorithm);
     }
-    print(&image);
     image
-        .iter()
-        .flat_map(|x| x.iter())
-        .filter(|x| **x == '#')
-        .count()
 }
 
-fn parse(path: &Path) -> Result<(Vec<char>, Image)> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+fn parse(path: &Path) -> Result<(Algorithm, Image)> {
+    parse_reader(BufReader::new(File::open(path)?))
+}
+
+fn parse_reader(reader: impl BufRead) -> Result<(Algorithm, Image)> {
     let mut lines = reader.lines();
 
-    let algorithm = lines.next().unwrap()?.chars().collect();
+    let pixel = |c: char| match c {
+        '#' => Ok(true),
+        '.' => Ok(false),
+        c => Err(anyhow!("invalid pixel {:?}", c)),
+    };
+
+    let line = lines.next().ok_or_else(|| anyhow!("empty input"))??;
+    let bits = line.chars().map(pixel).collect::<Result<Vec<bool>>>()?;
+    let table: [bool; 512] = bits.try_into().map_err(|b: Vec<bool>| anyhow!("the algorithm has {} entries, not 512", b.len()))?;
     lines.next();
 
-    let mut image = Image::new();
+    let mut pixels = Vec::new();
     for line in lines {
-        let line = line?;
-        image.push(line.chars().collect());
+        pixels.push(line?.chars().map(pixel).collect::<Result<Vec<bool>>>()?);
+    }
+    let width = pixels.first().map_or(0, |r| r.len());
+    if pixels.iter().any(|r| r.len() != width) {
+        bail!("the image is not a rectangle");
+    }
+
+    let mut image = Image::new(width, pixels.len(), false);
+    for (i, row) in pixels.iter().enumerate() {
+        for (j, lit) in row.iter().enumerate() {
+            image.set(i, j, *lit);
+        }
     }
 
-    Ok((algorithm, image))
+    Ok((Algorithm::new(table), image))
 }
 
 fn main() -> Result<()> {
-    let (algo, image) = parse(&Path::new("input.txt"))?;
-    let p1 = part1(&image, &algo);
-    println!("Part 1 {}", p1);
+    let (algo, image) = parse(Path::new("input.txt"))?;
+    let args: Vec<String> = std::env::args().collect();
+
+    let p1 = run(&image, &algo, 2);
+    println!("Part 1 {}", p1.lit());
 
-    let p2 = part2(&image, &algo);
-    println!("Part 2 {}", p2);
+    let p2 = run(&image, &algo, 50);
+    println!("Part 2 {}", p2.lit());
+
+    if let Some(i) = args.iter().position(|a| a == "--rounds") {
+        let rounds: usize = args.get(i + 1).ok_or_else(|| anyhow!("--rounds needs a count"))?.parse()?;
+        let image = run(&image, &algo, rounds);
+        println!("After {} rounds {}", rounds, image.lit());
+        if args.iter().any(|a| a == "--show") {
+            print!("{}", image);
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "..#.#..#####.#.#.#.###.##.....###.##.#..###.####..#####..#....#..#..##..###..######.###...####..#..#####..##..#.#####...##.#.#..#.##..#.#......#.###.######.###.####...#.##.##..#..#..#####.....#.#....###..#.##......#.....#..#..#..##..#...##.######.####.####.#.#...#.......#..#.#.#...####.##.#......#..#...##.#.##..#...##.#.##..###.#......#.#.......#.#.#.####.###.##...#.....####.#..#..#.##.#....##..#.####....##...##..#...#......#.#.......#.......##..####..#...#.#.#...##..#.#..###..#####........#..####......#..#
+
+#..#.
+#....
+##..#
+..#..
+..###
+";
+
+    fn parse_text(text: &str) -> Result<(Algorithm, Image)> {
+        parse_reader(text.as_bytes())
+    }
+
+    // the same step pixel by pixel, over the plane as a function
+    fn naive(image: &Image, table: &[bool; 512]) -> Image {
+        let mut out = Image::new(image.width + 2, image.height + 2, table[if image.background { 511 } else { 0 }]);
+        for i in 0..out.height {
+            for j in 0..out.width {
+                let mut idx = 0;
+                for di in -2..=0 {
+                    for dj in -2..=0 {
+                        idx = idx << 1 | image.get(i as isize + di, j as isize + dj) as usize;
+                    }
+                }
+                out.set(i, j, table[idx]);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn example_image() {
+        let (algorithm, image) = parse_text(EXAMPLE).unwrap();
+        assert_eq!(run(&image, &algorithm, 2).lit(), Lit::Finite(35));
+        assert_eq!(run(&image, &algorithm, 50).lit(), Lit::Finite(3351));
+    }
+
+    #[test]
+    fn kernel_matches_the_naive_step() {
+        let mut seed = 20u64;
+        let mut bit = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            seed >> 63 == 1
+        };
+        for (width, height) in [(1, 1), (3, 5), (63, 4), (64, 3), (130, 6)] {
+            let table: [bool; 512] = std::array::from_fn(|_| bit());
+            let algorithm = Algorithm::new(table);
+            let mut image = Image::new(width, height, false);
+            for i in 0..height {
+                for j in 0..width {
+                    image.set(i, j, bit());
+                }
+            }
+            let (mut fast, mut slow) = (image.clone(), image);
+            for _ in 0..3 {
+                fast = fast.enhance(&algorithm);
+                slow = naive(&slow, &table);
+                assert_eq!(fast, slow, "{}x{}", width, height);
+            }
+        }
+    }
+
+    #[test]
+    fn flashing_background_is_infinite() {
+        let mut table = [false; 512];
+        table[0] = true;
+        let algorithm = Algorithm::new(table);
+        let once = run(&Image::new(2, 2, false), &algorithm, 1);
+        assert_eq!((once.lit(), once.to_string()), (Lit::Infinite, "####\n####\n####\n####\n".to_string()));
+        assert_eq!(run(&once, &algorithm, 1).lit(), Lit::Finite(0));
+        assert!(parse_text("#.#\n\n#\n").is_err());
+    }
+}
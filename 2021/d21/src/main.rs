@@ -1,110 +1,253 @@
-fn wrap_dice(dice: i32) -> i32 {
-    if dice % 100 == 0 {
-        100
-    } else {
-        dice % 100
-    }
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use anyhow::{anyhow, bail, Result};
+
+// spaces are numbered 1..=board; a die shows 1..=faces
+#[derive(Debug, Clone, Copy)]
+struct Rules {
+    board: u32,
+    faces: u32,
+    rolls: u32,
+    target: u32,
 }
 
-fn step(p: i32, score: i32, dice: i32) -> (i32, i32, i32) {
-    let offset = wrap_dice(dice + 1) + wrap_dice(dice + 2) + wrap_dice(dice + 3);
-    let p = wrap_pos(p + offset);
-    let score = score + p;
-    let dice = wrap_dice(dice + 3);
+impl Rules {
+    fn practice() -> Self {
+        Rules { board: 10, faces: 100, rolls: 3, target: 1000 }
+    }
 
-    (p, score, dice)
-}
-fn simulate(mut p1: i32, mut p2: i32) -> (i32, i32){
-    let mut dice = 0;
-    let mut p1_score = 0;
-    let mut p2_score = 0;
-    let mut steps = 0;
-    let loser = loop {
-        (p1, p1_score, dice) = step(p1, p1_score, dice);
-        steps += 3;
-        if p1_score >= 1000 {
-            break p2_score;
-        }
+    fn dirac() -> Self {
+        Rules { target: 21, faces: 3, ..Rules::practice() }
+    }
 
-        (p2, p2_score, dice) = step(p2, p2_score, dice);
-        steps += 3;
-        if p2_score >= 1000 {
-            break p1_score;
+    fn wrap_pos(&self, pos: u32) -> u32 {
+        (pos - 1) % self.board + 1
+    }
+
+    // in how many ways each total of one turn's rolls comes up
+    fn roll_totals(&self) -> Vec<(u32, u128)> {
+        let mut ways = vec![1u128];
+        for _ in 0..self.rolls {
+            let mut next = vec![0u128; ways.len() + self.faces as usize];
+            for (total, w) in ways.iter().enumerate() {
+                for face in 1..=self.faces as usize {
+                    next[total + face] += w;
+                }
+            }
+            ways = next;
         }
-    };
-    (loser, steps)
+        ways.into_iter().enumerate().filter(|(_, w)| *w > 0).map(|(t, w)| (t as u32, w)).collect()
+    }
 }
-fn wrap_pos(pos: i32) -> i32 {
-    if pos % 10 == 0 {
-        10
-    } else {
-        pos % 10
+
+struct Practice {
+    scores: Vec<u32>,
+    winner: usize,
+    rolls: u32,
+}
+
+// a deterministic die counting 1, 2, ..., faces, 1, ...
+fn simulate(rules: &Rules, start: &[u32]) -> Practice {
+    let mut pos = start.to_vec();
+    let mut scores = vec![0; start.len()];
+    let mut rolls = 0;
+    loop {
+        for p in 0..pos.len() {
+            let offset: u32 = (0..rules.rolls)
+                .map(|_| {
+                    rolls += 1;
+                    (rolls - 1) % rules.faces + 1
+                })
+                .sum();
+            pos[p] = rules.wrap_pos(pos[p] + offset);
+            scores[p] += pos[p];
+            if scores[p] >= rules.target {
+                return Practice { scores, winner: p, rolls };
+            }
+        }
     }
 }
-fn simulate_player(state: &[[i128; 22];11]) -> ([[i128; 22];11], i128, i128) {
-    let clamp = |s: i32| s.min(21);
-    let mut state_aux = [[0i128; 22];11];
-    for i in 0..11 {
-        for j in 0..21 {
-            for d1 in 1..=3 {
-                for d2 in 1..=3 {
-                    for d3 in 1..=3 {
-                        let dd = d1 + d2 + d3;
-                        let ii = wrap_pos(i + dd) as usize;
-                        let jj = clamp(j + wrap_pos(i + dd)) as usize;
-                        state_aux[ii][jj] += state[i as usize][j as usize];
+
+// one player's universes that haven't won yet, by position and score
+struct PlayerState {
+    state: Vec<Vec<u128>>,
+}
+
+impl PlayerState {
+    fn new(rules: &Rules, start: u32) -> Self {
+        let mut state = vec![vec![0u128; rules.target as usize]; rules.board as usize + 1];
+        state[start as usize][0] = 1;
+        PlayerState { state }
+    }
+
+    // plays one turn in every universe; gives the universes that just won
+    // and those still playing
+    fn turn(&mut self, rules: &Rules, totals: &[(u32, u128)]) -> Result<(u128, u128)> {
+        let overflow = || anyhow!("universe counts overflow 128 bits");
+        let mut next = vec![vec![0u128; rules.target as usize]; rules.board as usize + 1];
+        let mut won = 0u128;
+        for (pos, scores) in self.state.iter().enumerate().skip(1) {
+            for (score, count) in scores.iter().enumerate().filter(|(_, c)| **c > 0) {
+                for (total, ways) in totals {
+                    let n_pos = rules.wrap_pos(pos as u32 + total);
+                    let n_score = score + n_pos as usize;
+                    let n = count.checked_mul(*ways).ok_or_else(overflow)?;
+                    if n_score >= rules.target as usize {
+                        won = won.checked_add(n).ok_or_else(overflow)?;
+                    } else {
+                        let cell = &mut next[n_pos as usize][n_score];
+                        *cell = cell.checked_add(n).ok_or_else(overflow)?;
                     }
                 }
             }
         }
+        self.state = next;
+        let playing = self.state.iter().flatten().try_fold(0u128, |acc, c| acc.checked_add(*c)).ok_or_else(overflow)?;
+        Ok((won, playing))
     }
+}
+
+// players' games only meet when someone wins: player p wins in round r in
+// every universe where it just won and nobody won before, i.e. players
+// before it are still playing after round r and those after it after r - 1
+fn simulate_dp(rules: &Rules, start: &[u32]) -> Result<Vec<u128>> {
+    let overflow = || anyhow!("universe counts overflow 128 bits");
+    let totals = rules.roll_totals();
+    let mut players: Vec<PlayerState> = start.iter().map(|s| PlayerState::new(rules, *s)).collect();
+    let mut playing = vec![1u128; start.len()];
+    let mut wins = vec![0u128; start.len()];
 
-    let mut winning = 0;
-    for i in 1..11 {
-        winning += state_aux[i][21];
-        state_aux[i][21] = 0;
+    while playing.iter().all(|p| *p > 0) {
+        for p in 0..players.len() {
+            let (won, still) = players[p].turn(rules, &totals)?;
+            let others = playing
+                .iter()
+                .enumerate()
+                .filter(|(q, _)| *q != p)
+                .try_fold(1u128, |acc, (_, n)| acc.checked_mul(*n))
+                .ok_or_else(overflow)?;
+            wins[p] = won.checked_mul(others).and_then(|w| w.checked_add(wins[p])).ok_or_else(overflow)?;
+            playing[p] = still;
+            if still == 0 {
+                break;
+            }
+        }
     }
 
-    let mut not_winning = 0;
-    for i in 1..11 {
-        for j in 1..21 {
-            not_winning += state_aux[i][j];
+    Ok(wins)
+}
+
+fn parse(path: &Path, board: u32) -> Result<Vec<u32>> {
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
+
+    let mut start = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let (player, pos) = scan_fmt::scan_fmt!(&line, "Player {} starting position: {}", usize, u32)?;
+        if player != start.len() + 1 {
+            bail!("expected player {}, found {:?}", start.len() + 1, line);
+        }
+        if !(1..=board).contains(&pos) {
+            bail!("player {} starts off the board at {}", player, pos);
         }
+        start.push(pos);
+    }
+    if start.is_empty() {
+        bail!("no players");
     }
 
-    (state_aux, winning, not_winning)
+    Ok(start)
 }
 
-fn simulate_dp(p1: i32, p2: i32) -> i128 {
-    let mut p1_dp = [[0i128; 22];11]; p1_dp[p1 as usize][0] = 1;
-    let mut p2_dp = [[0i128; 22];11]; p2_dp[p2 as usize][0] = 1;
-    let mut p1_total: i128 = 0;
-    let mut p2_total: i128 = 0;
-    let mut p1_last_non_winning = 1;
-    let mut p2_last_non_winning = 1;
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let flag = |name: &str| -> Result<Option<u32>> {
+        match args.iter().position(|a| a == name) {
+            Some(i) => Ok(Some(args.get(i + 1).ok_or_else(|| anyhow!("{} needs a value", name))?.parse()?)),
+            None => Ok(None),
+        }
+    };
+
+    let mut practice = Rules::practice();
+    let mut dirac = Rules::dirac();
+    if let Some(board) = flag("--board")? {
+        practice.board = board;
+        dirac.board = board;
+    }
+    if let Some(rolls) = flag("--rolls")? {
+        practice.rolls = rolls;
+        dirac.rolls = rolls;
+    }
+    practice.faces = flag("--die")?.unwrap_or(practice.faces);
+    practice.target = flag("--target")?.unwrap_or(practice.target);
+    dirac.faces = flag("--dirac-die")?.unwrap_or(dirac.faces);
+    dirac.target = flag("--dirac-target")?.unwrap_or(dirac.target);
+    for rules in [&practice, &dirac] {
+        if rules.board == 0 || rules.faces == 0 || rules.rolls == 0 || rules.target == 0 {
+            bail!("board, die faces, rolls and target must all be positive");
+        }
+    }
 
-    for _ in 0..21 {
-        let (p1_new, p1_winning, p1_current_not_winning) = simulate_player(&p1_dp);
-        let (p2_new, p2_winning, p2_current_not_winning) = simulate_player(&p2_dp);
+    let start = parse(Path::new("input.txt"), practice.board)?;
 
-        p1_last_non_winning = p1_current_not_winning;
+    let game = simulate(&practice, &start);
+    let loser = (0..start.len()).filter(|p| *p != game.winner).map(|p| game.scores[p]).min().unwrap_or(0);
+    println!("Part 1 {}", loser as u64 * game.rolls as u64);
 
-        p1_total += p1_winning * p2_last_non_winning;
-        p2_total += p2_winning * p1_last_non_winning;
+    let wins = simulate_dp(&dirac, &start)?;
+    println!("Part 2 {}", wins.iter().max().unwrap());
+    for (p, w) in wins.iter().enumerate() {
+        println!("  player {} wins in {} universes", p + 1, w);
+    }
+    Ok(())
+}
 
-        p2_last_non_winning = p2_current_not_winning;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        p1_dp = p1_new;
-        p2_dp = p2_new;
+    // every universe followed on its own
+    fn brute_force(rules: &Rules, pos: &mut [u32], scores: &mut [u32], turn: usize, wins: &mut [u128]) {
+        for (total, ways) in rules.roll_totals() {
+            for _ in 0..ways {
+                let (old_pos, old_score) = (pos[turn], scores[turn]);
+                pos[turn] = rules.wrap_pos(pos[turn] + total);
+                scores[turn] += pos[turn];
+                if scores[turn] >= rules.target {
+                    wins[turn] += 1;
+                } else {
+                    brute_force(rules, pos, scores, (turn + 1) % pos.len(), wins);
+                }
+                (pos[turn], scores[turn]) = (old_pos, old_score);
+            }
+        }
     }
 
-    p1_total.max(p2_total)
-}
+    #[test]
+    fn example_games() {
+        let game = simulate(&Rules::practice(), &[4, 8]);
+        assert_eq!((game.winner, game.scores.clone(), game.rolls), (0, vec![1000, 745], 993));
+        assert_eq!(simulate_dp(&Rules::dirac(), &[4, 8]).unwrap(), vec![444356092776315, 341960390180808]);
+    }
 
-fn main() {
-    let (loser, steps) = simulate(1, 3);
-    println!("Part 1 {}", loser * steps);
+    #[test]
+    fn roll_totals() {
+        assert_eq!(Rules::dirac().roll_totals(), vec![(3, 1), (4, 3), (5, 6), (6, 7), (7, 6), (8, 3), (9, 1)]);
+        assert_eq!(Rules { faces: 6, rolls: 1, ..Rules::practice() }.roll_totals().len(), 6);
+    }
 
-    let universes = simulate_dp(4, 8);
-    println!("Part 2 {}", universes);
+    #[test]
+    fn other_rules_match_brute_force() {
+        for (rules, start) in [
+            (Rules { board: 5, faces: 2, rolls: 2, target: 7 }, vec![1, 3, 5]),
+            (Rules { board: 7, faces: 3, rolls: 1, target: 9 }, vec![2, 6]),
+            (Rules { board: 4, faces: 2, rolls: 3, target: 8 }, vec![4]),
+        ] {
+            let mut wins = vec![0; start.len()];
+            brute_force(&rules, &mut start.clone(), &mut vec![0; start.len()], 0, &mut wins);
+            assert_eq!(simulate_dp(&rules, &start).unwrap(), wins, "{:?}", rules);
+        }
+    }
 }
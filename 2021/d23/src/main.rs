@@ -1,204 +1,409 @@
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashSet};
+use std::collections::{BinaryHeap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use anyhow::{anyhow, bail, Result};
 
+const EMPTY: u8 = 0;
+const MAX_KINDS: usize = 20;
+
+// room `r` is home to amphipod type `r`, shown as 'A' + r, which spends
+// 10^r energy per step
 #[derive(Debug, Clone)]
-struct State {
+struct Burrow {
+    // diagram columns of the hallway cells, left to right
+    hallway: Vec<usize>,
+    // hallway index of each room's entrance
+    entrances: Vec<usize>,
     depth: usize,
-    s: [Vec<char>; 11],
-    cost: i64,
 }
 
-impl Eq for State {}
+// hallway cells, then each room from the top down; EMPTY or type + 1
+type Cells = Vec<u8>;
 
-impl PartialEq<Self> for State {
-    fn eq(&self, other: &Self) -> bool {
-        self.cost.eq(&other.cost)
-    }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Place {
+    Hallway(usize),
+    Room(usize, usize),
 }
 
-impl PartialOrd<Self> for State {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        other.cost.partial_cmp(&self.cost)
-    }
+#[derive(Debug, Clone, Copy)]
+struct Move {
+    kind: u8,
+    from: Place,
+    to: Place,
+    energy: u64,
 }
 
-impl Ord for State {
-    fn cmp(&self, other: &Self) -> Ordering {
-        other.cost.cmp(&self.cost)
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let place = |p: &Place| match p {
+            Place::Hallway(h) => format!("hallway {}", h + 1),
+            Place::Room(r, s) => format!("room {} slot {}", r + 1, s + 1),
+        };
+        write!(f, "{}: {} -> {} ({} energy)", (b'A' + self.kind - 1) as char, place(&self.from), place(&self.to), self.energy)
     }
 }
 
-impl State {
-    fn new(depth: usize, r1: Vec<char>, r2: Vec<char>, r3: Vec<char>, r4: Vec<char>) -> Self {
-        Self {
-            depth,
-            s: [vec!['.'], vec!['.'], r1, vec!['.'], r2, vec!['.'], r3, vec!['.'], r4, vec!['.'], vec!['.']],
-            cost: 0
-        }
+fn overflow() -> anyhow::Error {
+    anyhow!("the energy overflows 64 bits")
+}
+
+impl Burrow {
+    fn energy(kind: u8) -> u64 {
+        10u64.pow(kind as u32 - 1)
     }
-    fn go(&self, from: usize, to: usize) -> Option<State> {
-        if self.s[from].is_empty() {
-            return None;
-        }
 
-        let from_last = *self.s[from].last().unwrap();
-        if State::get_room(from_last) == from && self.is_all_same(from_last, from) {
-            return None;
-        }
-        if State::is_hallway(from) && State::is_hallway(to) {
+    // with 20 types one step already costs 10^19, so anything more can overflow
+    fn walk(kind: u8, steps: usize) -> Result<u64> {
+        (steps as u64).checked_mul(Self::energy(kind)).ok_or_else(overflow)
+    }
+
+    fn slot(&self, room: usize, slot: usize) -> usize {
+        self.hallway.len() + room * self.depth + slot
+    }
+
+    fn is_entrance(&self, h: usize) -> bool {
+        self.entrances.contains(&h)
+    }
+
+    // hallway cells strictly after `from` up to and including `to` are free
+    fn is_hallway_clear(&self, cells: &Cells, from: usize, to: usize) -> bool {
+        let range = if from < to { from + 1..=to } else { to..=from - 1 };
+        range.into_iter().all(|h| cells[h] == EMPTY)
+    }
+
+    // the topmost amphipod of a room that still holds a stranger
+    fn leaving(&self, cells: &Cells, room: usize) -> Option<usize> {
+        let slots = (0..self.depth).map(|s| cells[self.slot(room, s)]);
+        if slots.clone().all(|c| c == EMPTY || c == room as u8 + 1) {
             return None;
         }
-        if State::is_hallway(from) && from_last == '.' {
+        (0..self.depth).find(|&s| cells[self.slot(room, s)] != EMPTY)
+    }
+
+    // the deepest free slot, once only its own kind are left in a room
+    fn entering(&self, cells: &Cells, room: usize) -> Option<usize> {
+        if (0..self.depth).any(|s| !matches!(cells[self.slot(room, s)], c if c == EMPTY || c == room as u8 + 1)) {
             return None;
         }
-        if State::is_hallway(to) && *self.s[to].last().unwrap() != '.' {
-            return None;
+        (0..self.depth).rev().find(|&s| cells[self.slot(room, s)] == EMPTY)
+    }
+
+    fn moves(&self, cells: &Cells) -> Result<Vec<(Cells, Move)>> {
+        let mut out = Vec::new();
+        let mut go = |from: usize, to: usize, mv: Move| {
+            let mut next = cells.clone();
+            next[to] = next[from];
+            next[from] = EMPTY;
+            out.push((next, mv));
+        };
+
+        // hallway to its own room
+        for (h, &kind) in cells[..self.hallway.len()].iter().enumerate() {
+            if kind == EMPTY {
+                continue;
+            }
+            let room = kind as usize - 1;
+            let Some(slot) = self.entering(cells, room) else {
+                continue;
+            };
+            let entrance = self.entrances[room];
+            if self.is_hallway_clear(cells, h, entrance) {
+                let steps = h.abs_diff(entrance) + slot + 1;
+                let mv = Move { kind, from: Place::Hallway(h), to: Place::Room(room, slot), energy: Self::walk(kind, steps)? };
+                go(h, self.slot(room, slot), mv);
+            }
         }
 
-        if State::is_hallway(from) &&
-            State::get_room(from_last) == to &&
-            self.is_hallway_clear(from, to) &&
-            self.is_all_same(from_last, to) {
-
-            let mut new_state = self.clone();
-            new_state.s[from][0] = '.';
-            new_state.s[to].push(from_last);
-            new_state.cost += (self.get_steps(from, to) * State::get_energy(from_last));
-
-            return Some(new_state);
-        } else if !State::is_hallway(from) &&
-            State::is_hallway(to) &&
-            self.is_hallway_clear(from, to) {
-
-            let mut new_state = self.clone();
-            new_state.s[from].pop();
-            new_state.s[to][0] = from_last;
-            new_state.cost += (self.get_steps(from, to) * State::get_energy(from_last));
-
-            return Some(new_state);
-        } else if !State::is_hallway(from) &&
-            !State::is_hallway(to) &&
-            self.is_hallway_clear(from, to) &&
-            State::get_room(from_last) == to &&
-            self.is_all_same(from_last, to)
-            {
-
-            let mut new_state = self.clone();
-            new_state.s[from].pop();
-            new_state.s[to].push(from_last);
-            new_state.cost += (self.get_steps(from, to) * State::get_energy(from_last));
-
-            return Some(new_state);
+        for room in 0..self.entrances.len() {
+            let Some(slot) = self.leaving(cells, room) else {
+                continue;
+            };
+            let kind = cells[self.slot(room, slot)];
+            let entrance = self.entrances[room];
+
+            // straight into its own room
+            let home = kind as usize - 1;
+            if home != room {
+                if let Some(to) = self.entering(cells, home) {
+                    if self.is_hallway_clear(cells, entrance, self.entrances[home]) {
+                        let steps = slot + 1 + entrance.abs_diff(self.entrances[home]) + to + 1;
+                        let mv = Move { kind, from: Place::Room(room, slot), to: Place::Room(home, to), energy: Self::walk(kind, steps)? };
+                        go(self.slot(room, slot), self.slot(home, to), mv);
+                    }
+                }
+            }
+
+            // out to a hallway cell that isn't in front of a room
+            for h in 0..self.hallway.len() {
+                if self.is_entrance(h) || cells[h] != EMPTY || !self.is_hallway_clear(cells, entrance, h) {
+                    continue;
+                }
+                let steps = slot + 1 + entrance.abs_diff(h);
+                let mv = Move { kind, from: Place::Room(room, slot), to: Place::Hallway(h), energy: Self::walk(kind, steps)? };
+                go(self.slot(room, slot), h, mv);
+            }
         }
+        Ok(out)
+    }
 
-        None
+    fn is_organized(&self, cells: &Cells) -> bool {
+        (0..self.entrances.len()).all(|r| (0..self.depth).all(|s| cells[self.slot(r, s)] == r as u8 + 1))
     }
 
-    fn is_hallway(h: usize) -> bool {
-        match h {
-            0 => true,
-            1 => true,
-            3 => true,
-            5 => true,
-            7 => true,
-            9 => true,
-            10 => true,
-            _ => false
+    // every misplaced amphipod still walks to its room's entrance and one
+    // step in; k of them entering one room fill its top k slots
+    fn heuristic(&self, cells: &Cells) -> Result<u64> {
+        let mut cost = 0u64;
+        let mut entering = vec![0usize; self.entrances.len()];
+        let mut count = |kind: u8, steps: usize| {
+            entering[kind as usize - 1] += 1;
+            Self::walk(kind, steps)
+        };
+        for (h, &kind) in cells[..self.hallway.len()].iter().enumerate() {
+            if kind != EMPTY {
+                cost = cost.checked_add(count(kind, h.abs_diff(self.entrances[kind as usize - 1]) + 1)?).ok_or_else(overflow)?;
+            }
+        }
+        for room in 0..self.entrances.len() {
+            // amphipods at home with no stranger below them stay put
+            let settled = (0..self.depth).rev().take_while(|&s| cells[self.slot(room, s)] == room as u8 + 1).count();
+            for slot in 0..self.depth - settled {
+                let kind = cells[self.slot(room, slot)];
+                if kind == EMPTY {
+                    continue;
+                }
+                let home = kind as usize - 1;
+                // leaving and coming back means a step aside and back at least
+                let sideways = self.entrances[room].abs_diff(self.entrances[home]).max(2);
+                cost = cost.checked_add(count(kind, slot + 1 + sideways + 1)?).ok_or_else(overflow)?;
+            }
+        }
+        for (room, k) in entering.iter().enumerate() {
+            cost = cost.checked_add(Self::walk(room as u8 + 1, k * k.saturating_sub(1) / 2)?).ok_or_else(overflow)?;
         }
+        Ok(cost)
     }
+}
 
-    fn get_room(a: char) -> usize {
-        match a {
-            'A' => 2,
-            'B' => 4,
-            'C' => 6,
-            'D' => 8,
-            _ => usize::MAX
-        }
+#[derive(Debug)]
+struct Entry {
+    priority: u64,
+    cost: u64,
+    cells: Cells,
+}
+
+impl Eq for Entry {}
+
+impl PartialEq<Self> for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.priority == other.priority
     }
+}
 
-    fn get_energy(a: char) -> i64 {
-        match a {
-            'A' => 1,
-            'B' => 10,
-            'C' => 100,
-            'D' => 1000,
-            _ => panic!("Say what?!")
-        }
+impl PartialOrd<Self> for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
+}
 
-    fn is_all_same(&self, a: char, to: usize) -> bool {
-        self.s[to]
-            .iter()
-            .all(|c| a == *c)
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.priority.cmp(&self.priority)
     }
+}
+
+// A* over burrow states; gives the least energy and the moves spending it,
+// or an error once some way of organizing costs more than a u64 holds
+fn find_min(burrow: &Burrow, start: Cells) -> Result<Option<(u64, Vec<Move>)>> {
+    let mut q = BinaryHeap::<Entry>::new();
+    let mut best = HashMap::<Cells, u64>::new();
+    let mut parent = HashMap::<Cells, (Cells, Move)>::new();
+
+    best.insert(start.clone(), 0);
+    q.push(Entry { priority: burrow.heuristic(&start)?, cost: 0, cells: start });
+    while let Some(Entry { cost, cells, .. }) = q.pop() {
+        if best.get(&cells).is_some_and(|b| *b < cost) {
+            continue;
+        }
+        if burrow.is_organized(&cells) {
+            let mut moves = Vec::new();
+            let mut at = cells;
+            while let Some((prev, mv)) = parent.get(&at) {
+                moves.push(*mv);
+                at = prev.clone();
+            }
+            moves.reverse();
+            return Ok(Some((cost, moves)));
+        }
 
-    fn is_hallway_clear(&self, from: usize, to: usize) -> bool {
-        for i in (from.min(to)..=from.max(to)) {
-            if State::is_hallway(i) && self.s[i][0] != '.' && i != from {
-                return false;
+        for (next, mv) in burrow.moves(&cells)? {
+            let n_cost = cost.checked_add(mv.energy).ok_or_else(overflow)?;
+            if best.get(&next).is_some_and(|b| *b <= n_cost) {
+                continue;
             }
+            best.insert(next.clone(), n_cost);
+            parent.insert(next.clone(), (cells.clone(), mv));
+            let priority = n_cost.checked_add(burrow.heuristic(&next)?).ok_or_else(overflow)?;
+            q.push(Entry { priority, cost: n_cost, cells: next });
         }
+    }
+    Ok(None)
+}
 
-        true
+fn parse(diagram: &str) -> Result<(Burrow, Cells)> {
+    let lines: Vec<Vec<char>> = diagram.lines().map(|l| l.chars().collect()).collect();
+    if lines.len() < 3 {
+        bail!("the burrow needs a hallway and at least one row of rooms");
     }
 
-    fn get_steps(&self, from: usize, to: usize) -> i64 {
-        let depth = if State::is_hallway(from) {
-            (self.depth - self.s[to].len()) as i64
-        } else if State::is_hallway(to) {
-            (self.depth - self.s[from].len()) as i64 + 1
-        } else {
-            (self.depth - self.s[from].len()) as i64 +
-                (self.depth - self.s[to].len()) as i64 + 1
-        };
+    let hallway: Vec<usize> = (0..lines[1].len()).filter(|&c| lines[1][c] == '.').collect();
+    let is_cell = |c: char| c == '.' || c.is_ascii_uppercase();
+    let room_cols: Vec<usize> = (0..lines[2].len()).filter(|&c| is_cell(lines[2][c])).collect();
+    let rows: Vec<&Vec<char>> = lines[2..].iter().take_while(|l| room_cols.iter().any(|&c| l.get(c).is_some_and(|x| is_cell(*x)))).collect();
 
-        (to as i64 - from as i64).abs() + depth
+    let mut entrances = Vec::new();
+    for col in &room_cols {
+        match hallway.iter().position(|h| h == col) {
+            Some(h) => entrances.push(h),
+            None => bail!("the room at column {} doesn't open onto the hallway", col),
+        }
+    }
+    // type r spends 10^r energy a step, which u64 holds up to r = 19;
+    // longer walks and totals are checked as the search goes
+    if room_cols.len() > MAX_KINDS {
+        bail!("{} rooms but at most {} amphipod types", room_cols.len(), MAX_KINDS);
     }
 
-    fn is_orgranized(&self) -> bool {
-        self.is_all_same('A', 2) && self.s[2].len() == self.depth &&
-            self.is_all_same('B', 4) && self.s[4].len() == self.depth &&
-            self.is_all_same('C', 6) && self.s[6].len() == self.depth &&
-            self.is_all_same('D', 8) && self.s[8].len() == self.depth
+    let burrow = Burrow { hallway, entrances, depth: rows.len() };
+    let mut cells = vec![EMPTY; burrow.slot(room_cols.len(), 0)];
+    let mut seen = vec![0; room_cols.len()];
+    for (slot, row) in rows.iter().enumerate() {
+        for (room, &col) in room_cols.iter().enumerate() {
+            let c = row.get(col).copied().unwrap_or('#');
+            if !is_cell(c) {
+                bail!("room {} is shallower than the others", room + 1);
+            }
+            if c != '.' {
+                let kind = c as u8 - b'A';
+                if kind as usize >= room_cols.len() {
+                    bail!("amphipod {} has no room", c);
+                }
+                seen[kind as usize] += 1;
+                cells[burrow.slot(room, slot)] = kind + 1;
+            }
+        }
+    }
+    if let Some(kind) = seen.iter().position(|&n| n != burrow.depth) {
+        bail!("{} amphipods of type {} for rooms {} deep", seen[kind], (b'A' + kind as u8) as char, burrow.depth);
     }
+
+    Ok((burrow, cells))
 }
 
-fn find_min_bfs(state: State) -> i64 {
-    let mut q = BinaryHeap::<State>::new();
-    let mut hs = HashSet::<([Vec<char>; 11], i64)>::new();
+// part 2 folds two more rows in under the first row of rooms
+fn unfold(diagram: &str) -> Option<String> {
+    let mut lines: Vec<&str> = diagram.lines().collect();
+    if lines.len() < 4 || lines[2].chars().filter(|c| c.is_ascii_uppercase() || *c == '.').count() != 4 {
+        return None;
+    }
+    lines.splice(3..3, ["  #D#C#B#A#", "  #D#B#A#C#"]);
+    Some(lines.join("\n"))
+}
 
-    hs.insert((state.s.clone(), state.cost));
-    q.push(state);
-    while !q.is_empty() {
-        let state = q.pop().unwrap();
-        if state.is_orgranized() {
-            return state.cost;
+fn solve(diagram: &str, show: bool) -> Result<Option<u64>> {
+    let (burrow, cells) = parse(diagram)?;
+    let Some((energy, moves)) = find_min(&burrow, cells)? else {
+        return Ok(None);
+    };
+    if show {
+        for mv in &moves {
+            println!("  {}", mv);
         }
+    }
+    Ok(Some(energy))
+}
 
-        for i in 0..11 {
-            for j in 0..11 {
-                if let Some(aux) = state.go(i, j) {
-                    if !hs.contains(&(aux.s.clone(), aux.cost)) {
-                        hs.insert((aux.s.clone(), aux.cost));
-                        q.push(aux);
-                    }
-                }
-            }
-        }
+fn main() -> Result<()> {
+    let diagram = fs::read_to_string(Path::new("input.txt"))?;
+    let show = std::env::args().any(|a| a == "--moves");
+
+    match solve(&diagram, show)? {
+        Some(energy) => println!("Part 1 {}", energy),
+        None => println!("Part 1: the amphipods can't be organized"),
     }
-    0
+    match unfold(&diagram) {
+        Some(unfolded) => match solve(&unfolded, show)? {
+            Some(energy) => println!("Part 2 {}", energy),
+            None => println!("Part 2: the amphipods can't be organized"),
+        },
+        None => println!("Part 2: the folded rows only fit four rooms"),
+    }
+    Ok(())
 }
 
-fn main() {
-    let state = State::new(
-        4,
-        vec!['C', 'D', 'D', 'B'],
-        vec!['D', 'B', 'C', 'D'],
-        vec!['B', 'A', 'B', 'C'],
-        vec!['A', 'C', 'A', 'A']
-    );
-    let min_energy = find_min_bfs(state.clone());
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "#############
+#...........#
+###B#C#B#D###
+  #A#D#C#A#
+  #########";
+
+    #[test]
+    fn example() {
+        assert_eq!(solve(EXAMPLE, false).unwrap(), Some(12521));
+        assert_eq!(solve(&unfold(EXAMPLE).unwrap(), false).unwrap(), Some(44169));
+    }
+
+    #[test]
+    fn moves_add_up() {
+        let (burrow, cells) = parse(EXAMPLE).unwrap();
+        let (energy, moves) = find_min(&burrow, cells).unwrap().unwrap();
+        assert_eq!(moves.iter().map(|m| m.energy).sum::<u64>(), energy);
+    }
+
+    #[test]
+    fn other_shapes() {
+        let two = "#######\n#.....#\n###B#A###\n  #A#B#\n  #####";
+        let (burrow, cells) = parse(two).unwrap();
+        assert_eq!(burrow.entrances.len(), 2);
+        assert!(find_min(&burrow, cells).unwrap().is_some());
+        assert!(unfold(two).is_none());
+    }
+
+    #[test]
+    fn rejects_bad_burrows() {
+        assert!(parse("#####\n#...#").is_err());
+        // three A amphipods for rooms two deep
+        assert!(parse("#######\n#.....#\n###A#A###\n  #A#B#\n  #####").is_err());
 
-    println!("Part 1 {}", min_energy);
+        let err = parse(&row_of(MAX_KINDS + 1, false)).unwrap_err();
+        assert!(err.to_string().contains("amphipod types"), "{}", err);
+    }
+
+    // one room per type, in order except that the last two are swapped
+    fn row_of(kinds: usize, swapped: bool) -> String {
+        let hallway = format!("#{}#", ".".repeat(2 * kinds + 1));
+        let mut rooms: Vec<char> = (0..kinds).map(|k| (b'A' + k as u8) as char).collect();
+        if swapped {
+            rooms.swap(kinds - 2, kinds - 1);
+        }
+        let rooms: String = rooms.iter().map(|c| format!("{}#", c)).collect();
+        format!("{}\n{}\n#{}\n", "#".repeat(hallway.len()), hallway, rooms)
+    }
+
+    #[test]
+    fn energy_overflow_is_an_error() {
+        // the second last type steps aside, the last walks home, then the
+        // second last walks home: 6 * 10^(k-2) + 4 * 10^(k-1)
+        assert_eq!(solve(&row_of(18, true), false).unwrap(), Some(46 * 10u64.pow(16)));
+        assert_eq!(solve(&row_of(MAX_KINDS, false), false).unwrap(), Some(0));
+        let err = solve(&row_of(MAX_KINDS, true), false).unwrap_err();
+        assert_eq!(err.to_string(), "the energy overflows 64 bits");
+    }
 }
@@ -22,6 +22,15 @@ impl Register {
             _ => anyhow::bail!("Register parse error"),
         }
     }
+
+    fn index(&self) -> usize {
+        match self {
+            Register::X => 0,
+            Register::Y => 1,
+            Register::W => 2,
+            Register::Z => 3,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -80,7 +89,7 @@ struct Alu {
 impl Alu {
     fn execute_all<'a, I>(
         &mut self, 
-        instructions: &Vec<Instruction>, 
+        instructions: &[Instruction], 
         input_iterator: &mut I) 
     where 
         I: Iterator<Item=&'a i64> {
@@ -150,48 +159,512 @@ impl Alu {
     }
 }
 
-fn should_decrease(instr: &Vec<Instruction>) -> bool {
-    let instruction = &instr[5];
-    match instruction {
-        Instruction::Add(_, Operand::Constant(c)) => *c < 0,
-        _ => panic!("instruction not valid")
+// what a register holds, in terms of the input digits
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Sym {
+    Const(i64),
+    // input digit `i` (1..=9) plus an offset
+    Digit(usize, i64),
+    // a number in base `base`, its digits most significant first; MONAD
+    // keeps a stack this way, pushing with `mul z 26` and popping with `div z 26`
+    Stack(i64, Vec<Sym>),
+}
+
+impl Sym {
+    fn range(&self) -> Option<(i64, i64)> {
+        match self {
+            Sym::Const(c) => Some((*c, *c)),
+            Sym::Digit(_, c) => Some((1 + c, 9 + c)),
+            Sym::Stack(..) => None,
+        }
     }
+
+    // a value that fits in one digit of base `base`
+    fn is_digit_of(&self, base: i64) -> bool {
+        self.range().is_some_and(|(lo, hi)| lo >= 0 && hi < base)
+    }
+
+    fn stack(base: i64, mut items: Vec<Sym>) -> Sym {
+        while items.len() > 1 && items[0] == Sym::Const(0) {
+            items.remove(0);
+        }
+        match items.len() {
+            0 => Sym::Const(0),
+            1 => items.pop().unwrap(),
+            _ => Sym::Stack(base, items),
+        }
+    }
+}
+
+// `digit + offset` compared with another digit or a constant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rhs {
+    Digit(usize, i64),
+    Const(i64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Constraint {
+    digit: usize,
+    offset: i64,
+    rhs: Rhs,
+    equal: bool,
 }
 
-fn part2(alu: Alu, instrs: &Vec<Vec<Instruction>>, idx: usize) -> bool {
-    if idx >= instrs.len() {
-        return alu.z == 0;
+impl Constraint {
+    fn holds(&self, digits: &[i64]) -> Option<bool> {
+        let lhs = *digits.get(self.digit)? + self.offset;
+        let rhs = match self.rhs {
+            Rhs::Digit(d, c) => *digits.get(d)? + c,
+            Rhs::Const(c) => c,
+        };
+        Some((lhs == rhs) == self.equal)
     }
+}
 
-    let instructions = &instrs[idx];
+impl std::fmt::Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let term = |d: usize, c: i64| match c {
+            0 => format!("d{}", d + 1),
+            c if c > 0 => format!("d{} + {}", d + 1, c),
+            c => format!("d{} - {}", d + 1, -c),
+        };
+        let rhs = match self.rhs {
+            Rhs::Digit(d, c) => term(d, c),
+            Rhs::Const(c) => c.to_string(),
+        };
+        write!(f, "{} {} {}", term(self.digit, self.offset), if self.equal { "==" } else { "!=" }, rhs)
+    }
+}
 
-    let decrease = should_decrease(instructions);
-    let z = alu.z;
+// one way through the program: every `eql` that depends on the input
+// forks it into the world where the comparison held and the one where it didn't
+#[derive(Debug, Clone)]
+struct World {
+    regs: [Sym; 4],
+    inputs: usize,
+    constraints: Vec<Constraint>,
+}
 
-    for i in 1..=9 {
-        let input = vec![i];
-        let mut aux_alu = alu.clone();
-        let mut iter = input.iter();
-        aux_alu.execute_all(instructions, &mut iter);
+const MAX_WORLDS: usize = 1 << 16;
 
-        if decrease {
-            let z = z / 26;
-            let aux_alu_z = aux_alu.z / 26;
+fn unsupported(n: usize, instruction: &Instruction, why: &str) -> anyhow::Error {
+    anyhow::anyhow!("instruction {} ({:?}): {}", n + 1, instruction, why)
+}
 
-            if ((z == 0 && aux_alu_z == 0) || (z > aux_alu_z)) && part2(aux_alu.clone(), instrs, idx + 1) {
-                println!("{}", i);
-                return true;
+impl World {
+    fn value(&self, op: &Operand) -> Sym {
+        match op {
+            Operand::Constant(c) => Sym::Const(*c),
+            Operand::Variable(r) => self.regs[r.index()].clone(),
+        }
+    }
+
+    fn step(mut self, n: usize, instruction: &Instruction) -> Result<Vec<World>> {
+        let fail = |why: &str| unsupported(n, instruction, why);
+        let (dest, op) = match instruction {
+            Instruction::Inp(r) => {
+                self.regs[r.index()] = Sym::Digit(self.inputs, 0);
+                self.inputs += 1;
+                return Ok(vec![self]);
             }
-        } else {
-            if part2(aux_alu.clone(), instrs, idx + 1) {
-                println!("{}", i);
+            Instruction::Add(r, op)
+            | Instruction::Mul(r, op)
+            | Instruction::Div(r, op)
+            | Instruction::Mod(r, op)
+            | Instruction::Eql(r, op) => (r.index(), self.value(op)),
+        };
+        let a = self.regs[dest].clone();
+
+        let result = match (instruction, &a, &op) {
+            (Instruction::Add(..), Sym::Const(x), Sym::Const(y)) => Sym::Const(x + y),
+            (Instruction::Add(..), v, Sym::Const(0)) | (Instruction::Add(..), Sym::Const(0), v) => v.clone(),
+            (Instruction::Add(..), Sym::Digit(i, c), Sym::Const(k)) | (Instruction::Add(..), Sym::Const(k), Sym::Digit(i, c)) => {
+                Sym::Digit(*i, c + k)
+            }
+            (Instruction::Add(..), Sym::Stack(base, items), v) if items.last() == Some(&Sym::Const(0)) && v.is_digit_of(*base) => {
+                let mut items = items.clone();
+                *items.last_mut().unwrap() = v.clone();
+                Sym::stack(*base, items)
+            }
+
+            (Instruction::Mul(..), Sym::Const(x), Sym::Const(y)) => Sym::Const(x * y),
+            (Instruction::Mul(..), _, Sym::Const(0)) | (Instruction::Mul(..), Sym::Const(0), _) => Sym::Const(0),
+            (Instruction::Mul(..), v, Sym::Const(1)) | (Instruction::Mul(..), Sym::Const(1), v) => v.clone(),
+            (Instruction::Mul(..), Sym::Stack(base, items), Sym::Const(b)) if b == base => {
+                let mut items = items.clone();
+                items.push(Sym::Const(0));
+                Sym::stack(*base, items)
+            }
+            (Instruction::Mul(..), v, Sym::Const(b)) if *b > 1 && v.is_digit_of(*b) => Sym::stack(*b, vec![v.clone(), Sym::Const(0)]),
+
+            (Instruction::Div(..), _, Sym::Const(0)) | (Instruction::Mod(..), _, Sym::Const(0)) => return Err(fail("division by zero")),
+            (Instruction::Div(..), Sym::Const(x), Sym::Const(y)) => Sym::Const(x / y),
+            (Instruction::Div(..), v, Sym::Const(1)) => v.clone(),
+            (Instruction::Div(..), Sym::Stack(base, items), Sym::Const(b)) if b == base => {
+                Sym::stack(*base, items[..items.len() - 1].to_vec())
+            }
+            (Instruction::Div(..), v, Sym::Const(b)) if *b > 0 && v.is_digit_of(*b) => Sym::Const(0),
+
+            (Instruction::Mod(..), Sym::Const(x), Sym::Const(y)) => Sym::Const(x % y),
+            (Instruction::Mod(..), Sym::Stack(base, items), Sym::Const(b)) if b == base => items.last().unwrap().clone(),
+            (Instruction::Mod(..), v, Sym::Const(b)) if v.is_digit_of(*b) => v.clone(),
+
+            (Instruction::Eql(..), x, y) => {
+                let (Some((x_lo, x_hi)), Some((y_lo, y_hi))) = (x.range(), y.range()) else {
+                    return Err(fail("comparing a stack"));
+                };
+                if x_hi < y_lo || y_hi < x_lo {
+                    Sym::Const(0)
+                } else {
+                    let (digit, offset, rhs) = match (x, y) {
+                        (Sym::Const(a), Sym::Const(b)) => {
+                            self.regs[dest] = Sym::Const((a == b) as i64);
+                            return Ok(vec![self]);
+                        }
+                        (Sym::Digit(i, c), Sym::Digit(j, d)) if i == j => {
+                            self.regs[dest] = Sym::Const((c == d) as i64);
+                            return Ok(vec![self]);
+                        }
+                        (Sym::Digit(i, c), Sym::Digit(j, d)) => (*i, *c, Rhs::Digit(*j, *d)),
+                        (Sym::Digit(i, c), Sym::Const(k)) | (Sym::Const(k), Sym::Digit(i, c)) => (*i, *c, Rhs::Const(*k)),
+                        _ => unreachable!(),
+                    };
+                    let mut worlds = Vec::new();
+                    for equal in [true, false] {
+                        let mut world = self.clone();
+                        world.regs[dest] = Sym::Const(equal as i64);
+                        world.constraints.push(Constraint { digit, offset, rhs, equal });
+                        if world.is_satisfiable() {
+                            worlds.push(world);
+                        }
+                    }
+                    return Ok(worlds);
+                }
+            }
+            _ => return Err(fail("outside what the analyser understands")),
+        };
+
+        self.regs[dest] = result;
+        Ok(vec![self])
+    }
+
+    // the world narrowed to the inputs that leave z at 0; a digit may still
+    // be 0 for some inputs, and a stack is 0 only when all its digits are
+    fn ending_at_zero(mut self) -> Option<World> {
+        let terms = match self.regs[Register::Z.index()].clone() {
+            Sym::Stack(_, items) => items,
+            z => vec![z],
+        };
+        for term in terms {
+            match term {
+                Sym::Const(0) => {}
+                Sym::Digit(digit, offset) => self.constraints.push(Constraint { digit, offset, rhs: Rhs::Const(0), equal: true }),
+                _ => return None,
+            }
+        }
+        self.is_satisfiable().then_some(self)
+    }
+
+    fn is_satisfiable(&self) -> bool {
+        self.solve(&(1..=9).collect::<Vec<_>>(), self.inputs).is_some()
+    }
+
+    // the first digits (in `order` preference, most significant first)
+    // meeting every constraint
+    fn solve(&self, order: &[i64], len: usize) -> Option<Vec<i64>> {
+        fn go(world: &World, order: &[i64], len: usize, digits: &mut Vec<i64>) -> bool {
+            if digits.len() == len {
                 return true;
             }
+            for &d in order {
+                digits.push(d);
+                let ok = world.constraints.iter().all(|c| c.holds(digits) != Some(false)) && world.can_complete(digits);
+                if ok && go(world, order, len, digits) {
+                    return true;
+                }
+                digits.pop();
+            }
+            false
+        }
+        let mut digits = Vec::with_capacity(len);
+        go(self, order, len, &mut digits).then_some(digits)
+    }
+
+    // every equality tying an assigned digit to a later one leaves that
+    // one a value between 1 and 9
+    fn can_complete(&self, digits: &[i64]) -> bool {
+        self.constraints.iter().filter(|c| c.equal).all(|c| match c.rhs {
+            Rhs::Digit(j, d) => {
+                let (i, o) = (c.digit, c.offset);
+                match (digits.get(i), digits.get(j)) {
+                    (Some(a), None) => (1..=9).contains(&(a + o - d)),
+                    (None, Some(b)) => (1..=9).contains(&(b + d - o)),
+                    _ => true,
+                }
+            }
+            Rhs::Const(k) => digits.get(c.digit).is_some() || (1..=9).contains(&(k - c.offset)),
+        })
+    }
+}
+
+// the worlds in which z ends up 0
+fn analyse(instructions: &[Instruction]) -> Result<Vec<World>> {
+    let mut worlds = vec![World { regs: [Sym::Const(0), Sym::Const(0), Sym::Const(0), Sym::Const(0)], inputs: 0, constraints: Vec::new() }];
+    for (n, instruction) in instructions.iter().enumerate() {
+        let mut next = Vec::new();
+        for world in worlds {
+            next.extend(world.step(n, instruction)?);
+        }
+        if next.len() > MAX_WORLDS {
+            anyhow::bail!("more than {} ways through the program by instruction {}", MAX_WORLDS, n + 1);
+        }
+        worlds = next;
+    }
+    Ok(worlds.into_iter().filter_map(World::ending_at_zero).collect())
+}
+
+fn model_number(worlds: &[World], order: &[i64]) -> Option<i64> {
+    worlds
+        .iter()
+        .filter_map(|w| w.solve(order, w.inputs))
+        .map(|digits| digits.iter().fold(0, |acc, d| acc * 10 + d))
+        .reduce(|a, b| if order[0] == 9 { a.max(b) } else { a.min(b) })
+}
+
+fn accepts(instructions: &[Instruction], number: i64) -> bool {
+    let digits: Vec<i64> = number.to_string().chars().map(|c| c.to_digit(10).unwrap() as i64).collect();
+    let mut alu = Alu::default();
+    alu.execute_all(instructions, &mut digits.iter());
+    alu.z == 0
+}
+
+// a register index into `[x, y, w, z]`
+type Reg = u8;
+
+const REG_NAMES: [char; 4] = ['x', 'y', 'w', 'z'];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Add,
+    Mul,
+    Div,
+    Mod,
+    Eql,
+}
+
+impl Kind {
+    fn apply(self, a: i64, b: i64) -> i64 {
+        match self {
+            Kind::Add => a + b,
+            Kind::Mul => a * b,
+            Kind::Div => a / b,
+            Kind::Mod => a % b,
+            Kind::Eql => (a == b) as i64,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Add => "add",
+            Kind::Mul => "mul",
+            Kind::Div => "div",
+            Kind::Mod => "mod",
+            Kind::Eql => "eql",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Inp(Reg),
+    Set(Reg, i64),
+    Mov(Reg, Reg),
+    Imm(Kind, Reg, i64),
+    Var(Kind, Reg, Reg),
+}
+
+impl Op {
+    fn dest(&self) -> Reg {
+        match *self {
+            Op::Inp(d) | Op::Set(d, _) | Op::Mov(d, _) | Op::Imm(_, d, _) | Op::Var(_, d, _) => d,
+        }
+    }
+
+    fn reads(&self) -> Vec<Reg> {
+        match *self {
+            Op::Inp(_) | Op::Set(..) => vec![],
+            Op::Mov(_, s) => vec![s],
+            Op::Imm(_, d, _) => vec![d],
+            Op::Var(_, d, s) => vec![d, s],
+        }
+    }
+}
+
+impl std::fmt::Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let r = |r: Reg| REG_NAMES[r as usize];
+        match *self {
+            Op::Inp(d) => write!(f, "inp {}", r(d)),
+            Op::Set(d, c) => write!(f, "set {} {}", r(d), c),
+            Op::Mov(d, s) => write!(f, "mov {} {}", r(d), r(s)),
+            Op::Imm(k, d, c) => write!(f, "{} {} {}", k.name(), r(d), c),
+            Op::Var(k, d, s) => write!(f, "{} {} {}", k.name(), r(d), r(s)),
+        }
+    }
+}
+
+// an ALU program with every operand known at compile time folded in and
+// every write nobody reads dropped
+#[derive(Debug, Clone)]
+struct Program {
+    ops: Vec<Op>,
+}
+
+impl Program {
+    // only the registers in `outputs` need their final values
+    fn compile(instructions: &[Instruction], outputs: &[Register]) -> Program {
+        let mut known: [Option<i64>; 4] = [Some(0); 4];
+        let mut ops = Vec::new();
+        for instruction in instructions {
+            let (kind, dest, operand) = match instruction {
+                Instruction::Inp(r) => {
+                    known[r.index()] = None;
+                    ops.push(Op::Inp(r.index() as Reg));
+                    continue;
+                }
+                Instruction::Add(r, op) => (Kind::Add, r, op),
+                Instruction::Mul(r, op) => (Kind::Mul, r, op),
+                Instruction::Div(r, op) => (Kind::Div, r, op),
+                Instruction::Mod(r, op) => (Kind::Mod, r, op),
+                Instruction::Eql(r, op) => (Kind::Eql, r, op),
+            };
+            let d = dest.index();
+            let (b, src) = match operand {
+                Operand::Constant(c) => (Some(*c), None),
+                Operand::Variable(s) => (known[s.index()], Some(s.index())),
+            };
+            // left alone, a division by zero traps at run time as in `Alu`
+            let traps = matches!(kind, Kind::Div | Kind::Mod) && b == Some(0);
+
+            let op = match (known[d], b) {
+                _ if traps => None,
+                (Some(a), Some(b)) => {
+                    known[d] = Some(kind.apply(a, b));
+                    ops.push(Op::Set(d as Reg, kind.apply(a, b)));
+                    continue;
+                }
+                (_, Some(0)) if kind == Kind::Mul => Some(Op::Set(d as Reg, 0)),
+                (Some(0), _) if kind == Kind::Mul => Some(Op::Set(d as Reg, 0)),
+                (_, Some(0)) if kind == Kind::Add => continue,
+                (_, Some(1)) if matches!(kind, Kind::Mul | Kind::Div) => continue,
+                (Some(0), None) if kind == Kind::Add => Some(Op::Mov(d as Reg, src.unwrap() as Reg)),
+                (Some(1), None) if kind == Kind::Mul => Some(Op::Mov(d as Reg, src.unwrap() as Reg)),
+                _ if kind == Kind::Eql && src == Some(d) => Some(Op::Set(d as Reg, 1)),
+                _ => None,
+            };
+            let op = op.unwrap_or(match b {
+                Some(b) => Op::Imm(kind, d as Reg, b),
+                None => Op::Var(kind, d as Reg, src.unwrap() as Reg),
+            });
+            known[d] = match op {
+                Op::Set(_, c) => Some(c),
+                Op::Mov(_, s) => known[s as usize],
+                _ => None,
+            };
+            ops.push(op);
+        }
+
+        // walking backwards, a write to a register that isn't read before
+        // its next write (or the end) is dead; inputs are always consumed
+        let mut live = [false; 4];
+        for r in outputs {
+            live[r.index()] = true;
+        }
+        let mut kept = Vec::with_capacity(ops.len());
+        for op in ops.into_iter().rev() {
+            let d = op.dest() as usize;
+            if !live[d] && !matches!(op, Op::Inp(_)) {
+                continue;
+            }
+            live[d] = false;
+            for s in op.reads() {
+                live[s as usize] = true;
+            }
+            kept.push(op);
+        }
+        kept.reverse();
+        Program { ops: kept }
+    }
+
+    // the registers at the end, or None if the input runs out
+    fn run(&self, inputs: &[i64]) -> Option<[i64; 4]> {
+        let mut regs = [0i64; 4];
+        let mut inputs = inputs.iter();
+        for op in &self.ops {
+            match *op {
+                Op::Inp(d) => regs[d as usize] = *inputs.next()?,
+                Op::Set(d, c) => regs[d as usize] = c,
+                Op::Mov(d, s) => regs[d as usize] = regs[s as usize],
+                Op::Imm(k, d, c) => regs[d as usize] = k.apply(regs[d as usize], c),
+                Op::Var(k, d, s) => regs[d as usize] = k.apply(regs[d as usize], regs[s as usize]),
+            }
+        }
+        Some(regs)
+    }
+}
+
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (n, op) in self.ops.iter().enumerate() {
+            writeln!(f, "{:4}  {}", n, op)?;
         }
+        Ok(())
+    }
+}
+
+// runs `count` pseudo-random model numbers through both the interpreter
+// and the compiled program, which must agree on z
+fn check_compiled(instructions: &[Instruction], program: &Program, count: u64) -> Result<()> {
+    let inputs = instructions.iter().filter(|i| matches!(i, Instruction::Inp(_))).count();
+    let mut seed = 0x2545f4914f6cdd1du64;
+    let mut numbers = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let digits: Vec<i64> = (0..inputs)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                (seed % 9 + 1) as i64
+            })
+            .collect();
+        numbers.push(digits);
     }
 
-    return false;
-}   
+    let start = std::time::Instant::now();
+    let compiled: Vec<i64> = numbers.iter().map(|n| program.run(n).unwrap()[Register::Z.index()]).collect();
+    let compiled_time = start.elapsed();
+
+    let start = std::time::Instant::now();
+    let interpreted: Vec<i64> = numbers
+        .iter()
+        .map(|n| {
+            let mut alu = Alu::default();
+            alu.execute_all(instructions, &mut n.iter());
+            alu.z
+        })
+        .collect();
+    let interpreted_time = start.elapsed();
+
+    if let Some(i) = (0..numbers.len()).find(|&i| compiled[i] != interpreted[i]) {
+        anyhow::bail!("{:?}: compiled z = {}, interpreted z = {}", numbers[i], compiled[i], interpreted[i]);
+    }
+    println!(
+        "{} inputs agree: compiled {:?}, interpreted {:?}",
+        count, compiled_time, interpreted_time
+    );
+    Ok(())
+}
 
 fn parse(path: &Path) -> Result<Vec<Instruction>> {
     let file = File::open(path)?;
@@ -206,21 +679,159 @@ fn parse(path: &Path) -> Result<Vec<Instruction>> {
     Ok(v)
 }
 
-fn test(instructions: &Vec<Instruction>) {
-    //               |     | |         | | | |  
-    let input = vec![1,1,9,1,1,9,9,9,9,7,5,3,6,1];
-    let mut input_iter = input.iter();
-    let mut alu = Alu::default();
-    
-    for instruction in instructions {
-        alu.execute(instruction, &mut input_iter);
-        println!("{:?}", alu);
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let instructions = parse(Path::new("input.txt"))?;
+
+    let program = Program::compile(&instructions, &[Register::Z]);
+    if args.iter().any(|a| a == "--disassemble") {
+        println!("{} instructions, {} after compiling", instructions.len(), program.ops.len());
+        print!("{}", program);
+    }
+    if let Some(i) = args.iter().position(|a| a == "--check") {
+        let count = args.get(i + 1).ok_or_else(|| anyhow::anyhow!("--check needs a count"))?.parse()?;
+        check_compiled(&instructions, &program, count)?;
     }
-}
 
-fn main() -> Result<()> {
-    let instructions = parse(&Path::new("input.txt"))?;
-    let chunked: Vec<Vec<Instruction>> = instructions.chunks(18).map(|c| c.to_vec()).collect();
-    part2(Alu::default(), &chunked, 0);
+    let worlds = analyse(&instructions)?;
+
+    if args.iter().any(|a| a == "--constraints") {
+        for world in &worlds {
+            let constraints: Vec<String> = world.constraints.iter().map(|c| c.to_string()).collect();
+            println!("{}", constraints.join(", "));
+        }
+    }
+
+    let largest: Vec<i64> = (1..=9).rev().collect();
+    let smallest: Vec<i64> = (1..=9).collect();
+    for (part, order) in [(1, largest), (2, smallest)] {
+        match model_number(&worlds, &order) {
+            Some(n) if accepts(&instructions, n) => println!("Part {} {}", part, n),
+            Some(n) => anyhow::bail!("the interpreter rejects {}, the analysis is wrong", n),
+            None => println!("Part {}: no model number is accepted", part),
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(text: &str) -> Vec<Instruction> {
+        text.lines().map(|l| Instruction::parse(l).unwrap()).collect()
+    }
+
+    fn answers(instructions: &[Instruction]) -> (Option<i64>, Option<i64>) {
+        let worlds = analyse(instructions).unwrap();
+        let largest: Vec<i64> = (1..=9).rev().collect();
+        let smallest: Vec<i64> = (1..=9).collect();
+        (model_number(&worlds, &largest), model_number(&worlds, &smallest))
+    }
+
+    // a MONAD made of blocks that push `digit + add_y` on a base 26 stack, or
+    // pop the top and push again unless the digit is `top + add_x`
+    fn monad(blocks: &[(i64, i64, i64)]) -> Vec<Instruction> {
+        let mut text = String::new();
+        for (div, add_x, add_y) in blocks {
+            text += &format!(
+                "inp w\nmul x 0\nadd x z\nmod x 26\ndiv z {}\nadd x {}\neql x w\neql x 0\nmul y 0\nadd y 25\nmul y x\nadd y 1\nmul z y\nmul y 0\nadd y w\nadd y {}\nmul y x\nadd z y\n",
+                div, add_x, add_y
+            );
+        }
+        program(&text)
+    }
+
+    #[test]
+    fn generated_monads() {
+        let mut seed = 7u64;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) % n
+        };
+        for _ in 0..20 {
+            // seven pushes and seven pops in a random balanced order
+            let mut blocks = vec![(0, 0, 0); 14];
+            let (mut largest, mut smallest) = ([0i64; 14], [0i64; 14]);
+            let (mut open, mut pushes) = (Vec::new(), 0);
+            for j in 0..14 {
+                if pushes < 7 && (open.is_empty() || next(2) == 0) {
+                    blocks[j] = (1, 10 + next(6) as i64, 1 + next(16) as i64);
+                    open.push(j);
+                    pushes += 1;
+                    continue;
+                }
+                let i = open.pop().unwrap();
+                let diff = next(17) as i64 - 8;
+                blocks[j] = (26, diff - blocks[i].2, 1 + next(16) as i64);
+                largest[i] = 9 - diff.max(0);
+                smallest[i] = 1 + (-diff).max(0);
+                (largest[j], smallest[j]) = (largest[i] + diff, smallest[i] + diff);
+            }
+            let number = |digits: [i64; 14]| digits.iter().fold(0, |acc, d| acc * 10 + d);
+            let instructions = monad(&blocks);
+            assert_eq!(answers(&instructions), (Some(number(largest)), Some(number(smallest))));
+            assert!(accepts(&instructions, number(largest)) && accepts(&instructions, number(smallest)));
+        }
+    }
+
+    #[test]
+    fn final_z_may_be_a_digit() {
+        // z = d1 - 3 is only 0 for d1 = 3
+        assert_eq!(answers(&program("inp w\nadd z w\nadd z -3")), (Some(3), Some(3)));
+        assert_eq!(answers(&program("inp w\nadd z w\nadd z 1")), (None, None));
+    }
+
+    #[test]
+    fn final_z_may_be_a_stack() {
+        // z = 26(d1 - 1) + (d2 - 1), so only 11 leaves it at 0
+        let instructions = program("inp w\nadd w -1\nadd z w\nmul z 26\ninp x\nadd x -1\nadd z x");
+        assert_eq!(answers(&instructions), (Some(11), Some(11)));
+        assert_eq!((11..=99).filter(|&n| n % 10 != 0 && accepts(&instructions, n)).collect::<Vec<_>>(), vec![11]);
+    }
+
+    #[test]
+    fn constraints_read_as_equations() {
+        let instructions = monad(&[(1, 12, 4), (26, -6, 3)]);
+        let worlds = analyse(&instructions).unwrap();
+        let constraints: Vec<String> = worlds.iter().flat_map(|w| w.constraints.iter().map(|c| c.to_string())).collect();
+        assert_eq!(constraints, vec!["d1 - 2 == d2"]);
+        assert_eq!(answers(&instructions), (Some(97), Some(31)));
+    }
+
+    #[test]
+    fn compiled_programs_agree_with_the_alu() {
+        let instructions = monad(&[(1, 12, 4), (1, 11, 11), (26, -5, 2), (1, 10, 7), (26, -9, 16), (26, -13, 5)]);
+        let all = [Register::X, Register::Y, Register::W, Register::Z];
+        let program = Program::compile(&instructions, &all);
+        assert!(program.ops.len() < instructions.len());
+        let mut seed = 48u64;
+        for _ in 0..500 {
+            let digits: Vec<i64> = (0..6)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    ((seed >> 33) % 9 + 1) as i64
+                })
+                .collect();
+            let mut alu = Alu::default();
+            alu.execute_all(&instructions, &mut digits.iter());
+            assert_eq!(program.run(&digits), Some([alu.x, alu.y, alu.w, alu.z]), "{:?}", digits);
+        }
+        assert_eq!(program.run(&[1, 2]), None);
+        check_compiled(&instructions, &Program::compile(&instructions, &[Register::Z]), 200).unwrap();
+    }
+
+    #[test]
+    fn folds_constants_and_drops_dead_stores() {
+        let instructions = program("inp w\nmul x 0\nadd x 5\nadd z x\nmul y 0\nadd y w\nmul y 1\nadd z y\neql x x");
+        let program = Program::compile(&instructions, &[Register::Z]);
+        assert_eq!(program.to_string(), "   0  inp w\n   1  set z 5\n   2  mov y w\n   3  add z y\n");
+        assert_eq!(program.run(&[4]).unwrap()[Register::Z.index()], 9);
+    }
+
+    #[test]
+    fn division_by_zero_is_left_to_trap() {
+        let program = Program::compile(&program("inp w\ndiv w 0\nadd z w"), &[Register::Z]);
+        assert!(program.ops.contains(&Op::Imm(Kind::Div, Register::W.index() as Reg, 0)));
+    }
+}
@@ -1,120 +1,307 @@
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
-use anyhow::Result;
-use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use anyhow::{anyhow, bail, Result};
+use automaton::{Automaton, Edges, Grid, HerdRules};
 
-type Grid = Vec<Vec<char>>;
+// one bit per cell, bit j of word k being column 64k + j; bits past the
+// last column are always clear
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Herd {
+    rows: usize,
+    cols: usize,
+    words: usize,
+    east: Vec<u64>,
+    south: Vec<u64>,
+}
+
+impl Herd {
+    fn new(rows: usize, cols: usize) -> Self {
+        let words = cols.div_ceil(64);
+        Herd { rows, cols, words, east: vec![0; rows * words], south: vec![0; rows * words] }
+    }
+
+    fn row(bits: &[u64], words: usize, i: usize) -> &[u64] {
+        &bits[i * words..(i + 1) * words]
+    }
 
-fn print_grid(g: &Grid) {
-    for row in g {
-        for chr in row {
-            print!("{}", chr);
+    fn get(bits: &[u64], words: usize, i: usize, j: usize) -> bool {
+        bits[i * words + j / 64] >> (j % 64) & 1 == 1
+    }
+
+    fn last_mask(&self) -> u64 {
+        match self.cols % 64 {
+            0 => u64::MAX,
+            r => (1 << r) - 1,
         }
-        println!();
     }
-    println!();
-}
 
-fn move_cucumber(i: usize, j: usize, g: &Grid) -> Option<(usize, usize)> {
-    if !(i < g.len() && j < g[0].len()) { return None; }
-
-    let chr = g[i][j];
-    match chr {
-        '.' => None,
-        '>' => {
-            let ii = i;
-            let jj = (j+1) % g[0].len();
-            if g[ii][jj] == '.' {
-                Some((ii, jj))
-            } else {
-                None
-            }
+    // out[j] = row[j - 1], the last column wrapping round to the first
+    fn shift_east(&self, row: &[u64], out: &mut [u64]) {
+        let mut carry = (row[self.words - 1] >> ((self.cols - 1) % 64)) & 1;
+        for (o, w) in out.iter_mut().zip(row) {
+            *o = (w << 1) | carry;
+            carry = w >> 63;
         }
-        'v' => {
-            let ii = (i + 1) % g.len();
-            let jj = j;
-            
-            if g[ii][jj] == '.' {
-                Some((ii, jj))
-            } else {
-                None
-            }
+        out[self.words - 1] &= self.last_mask();
+    }
+
+    // out[j] = row[j + 1], the first column wrapping round to the last
+    fn shift_west(&self, row: &[u64], out: &mut [u64]) {
+        for k in 0..self.words {
+            let next = if k + 1 < self.words { row[k + 1] << 63 } else { 0 };
+            out[k] = (row[k] >> 1) | next;
         }
-        _   => panic!("Unrecognized seabed tile!!") 
+        out[self.words - 1] |= (row[0] & 1) << ((self.cols - 1) % 64);
     }
-}
 
-fn find_landing_time(mut g: Grid) -> usize {
-    let mut steps = 0;
-    loop {
-        let mut moved = false;
-        let mut g_aux = vec![vec!['.'; g[0].len()]; g.len()];
-        for i in 0..g.len() {
-            for j in 0..g[0].len() {
-                if g[i][j] == '.' {continue;}
-
-                if g[i][j] == '>' {
-                    if let Some((ii, jj)) = move_cucumber(i, j, &g) {
-                        g_aux[ii][jj] = g[i][j];
-                        moved |= true;
-                    } else {
-                        g_aux[i][j] = g[i][j];
-                    }
-                } else{
-                    g_aux[i][j] = g[i][j];
-                }
-            }
+    // both species take their turn; gives how many cucumbers moved
+    fn step(&mut self) -> usize {
+        if self.cols == 0 {
+            return 0;
         }
+        let w = self.words;
+        let mut moved = 0;
+        let mut free = vec![0u64; w];
+        let mut ahead = vec![0u64; w];
+        let mut behind = vec![0u64; w];
 
-        g = g_aux;
-        let mut g_aux = vec![vec!['.'; g[0].len()]; g.len()];
-        for i in 0..g.len() {
-            for j in 0..g[0].len() {
-                if g[i][j] == '.' {continue;}
+        // east-facing: a cucumber stays if the cell ahead is taken, and one
+        // arrives wherever the cell behind held one and this one was free
+        for i in 0..self.rows {
+            let range = i * w..(i + 1) * w;
+            for (k, f) in free.iter_mut().enumerate() {
+                *f = !(self.east[i * w + k] | self.south[i * w + k]);
+            }
+            free[w - 1] &= self.last_mask();
+            self.shift_west(&free, &mut ahead);
+            self.shift_east(&self.east[range.clone()], &mut behind);
+            for (k, cell) in self.east[range].iter_mut().enumerate() {
+                let arriving = behind[k] & free[k];
+                moved += arriving.count_ones() as usize;
+                *cell = (*cell & !ahead[k]) | arriving;
+            }
+        }
 
-                if g[i][j] == 'v' {
-                    if let Some((ii, jj)) = move_cucumber(i, j, &g) {
-                        g_aux[ii][jj] = g[i][j];
-                        moved |= true;
-                    } else {
-                        g_aux[i][j] = g[i][j];
-                    }
-                } else {
-                    g_aux[i][j] = g[i][j];
-                }
+        // south-facing, the same a row at a time
+        let free: Vec<u64> = (0..self.rows * w)
+            .map(|k| {
+                let f = !(self.east[k] | self.south[k]);
+                if k % w == w - 1 { f & self.last_mask() } else { f }
+            })
+            .collect();
+        let old = self.south.clone();
+        for i in 0..self.rows {
+            let (above, below) = ((i + self.rows - 1) % self.rows, (i + 1) % self.rows);
+            for k in 0..w {
+                let arriving = old[above * w + k] & free[i * w + k];
+                moved += arriving.count_ones() as usize;
+                self.south[i * w + k] = (old[i * w + k] & !free[below * w + k]) | arriving;
             }
         }
+        moved
+    }
 
-        g = g_aux;
-        steps += 1;
+    fn tile(&self, i: usize, j: usize) -> char {
+        if Self::get(&self.east, self.words, i, j) {
+            '>'
+        } else if Self::get(&self.south, self.words, i, j) {
+            'v'
+        } else {
+            '.'
+        }
+    }
 
-        if !moved { break; }
+    fn to_grid(&self) -> Result<Grid<char>> {
+        let rows = (0..self.rows).map(|i| (0..self.cols).map(|j| self.tile(i, j)).collect()).collect();
+        Grid::new(rows, Edges::Toroidal)
     }
 
-    steps
+    // a binary PPM, one pixel per cell
+    fn write_frame(&self, path: &Path) -> Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        write!(out, "P6\n{} {}\n255\n", self.cols, self.rows)?;
+        for i in 0..self.rows {
+            let (east, south) = (Self::row(&self.east, self.words, i), Self::row(&self.south, self.words, i));
+            for j in 0..self.cols {
+                let bit = |bits: &[u64]| bits[j / 64] >> (j % 64) & 1 == 1;
+                let pixel: [u8; 3] = if bit(east) {
+                    [230, 120, 40]
+                } else if bit(south) {
+                    [60, 140, 230]
+                } else {
+                    [10, 20, 40]
+                };
+                out.write_all(&pixel)?;
+            }
+        }
+        out.flush()?;
+        Ok(())
+    }
 }
 
-fn parse(path: &Path) -> Result<Grid> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+fn parse(path: &Path) -> Result<Herd> {
+    parse_reader(BufReader::new(File::open(path)?))
+}
 
-    let mut g = Grid::new();
+fn parse_reader(reader: impl BufRead) -> Result<Herd> {
+    let mut lines = Vec::new();
     for line in reader.lines() {
         let line = line?;
-        g.push(
-            line
-                .chars()
-                .collect()
-        );
+        if let Some(c) = line.chars().find(|c| !matches!(c, '.' | '>' | 'v')) {
+            bail!("Unrecognized seabed tile {:?}", c);
+        }
+        if lines.first().is_some_and(|first: &String| first.len() != line.len()) {
+            bail!("row {} is {} wide, the first is {}", lines.len() + 1, line.len(), lines[0].len());
+        }
+        lines.push(line);
+    }
+
+    let mut herd = Herd::new(lines.len(), lines.first().map_or(0, |l| l.len()));
+    for (i, line) in lines.iter().enumerate() {
+        for (j, c) in line.bytes().enumerate() {
+            let bits = match c {
+                b'>' => &mut herd.east,
+                b'v' => &mut herd.south,
+                _ => continue,
+            };
+            bits[i * herd.words + j / 64] |= 1 << (j % 64);
+        }
     }
+    Ok(herd)
+}
 
-    Ok(g)
+// the first step on which no sea cucumber moves, or None once the herd
+// comes back round to an earlier seabed (Brent's cycle finding)
+fn find_landing_time(herd: &mut Herd, frames: Option<&Path>) -> Result<Option<usize>> {
+    let mut steps = 0;
+    let mut seen = herd.clone();
+    let (mut power, mut since) = (1, 0);
+    loop {
+        if let Some(dir) = frames {
+            herd.write_frame(&dir.join(format!("{:06}.ppm", steps)))?;
+        }
+        steps += 1;
+        if herd.step() == 0 {
+            return Ok(Some(steps));
+        }
+        if *herd == seen {
+            return Ok(None);
+        }
+        since += 1;
+        if since == power {
+            seen = herd.clone();
+            power *= 2;
+            since = 0;
+        }
+    }
 }
 
 fn main() -> Result<()> {
-    let grid = parse(&Path::new("input.txt"))?;
-    let p1 = find_landing_time(grid);
+    let args: Vec<String> = std::env::args().collect();
+    let frames = match args.iter().position(|a| a == "--frames") {
+        Some(i) => Some(PathBuf::from(args.get(i + 1).ok_or_else(|| anyhow!("--frames needs a directory"))?)),
+        None => None,
+    };
+    if let Some(dir) = &frames {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut herd = parse(Path::new("input.txt"))?;
+    let check = args.iter().any(|a| a == "--check");
+    let start = if check { Some(herd.to_grid()?) } else { None };
+    let Some(p1) = find_landing_time(&mut herd, frames.as_deref())? else {
+        println!("Part 1: the herd never settles");
+        return Ok(());
+    };
     println!("Part 1 {}", p1);
+
+    // the cell-by-cell engine has to land on the same step and seabed
+    if let Some(start) = start {
+        let mut reference = Automaton::new(start, HerdRules::sea_cucumbers());
+        match reference.first_synchronised(p1 + 1) {
+            Some(steps) if steps == p1 && reference.grid == herd.to_grid()? => println!("the automaton agrees"),
+            Some(steps) => bail!("the automaton settles after {} steps on a different seabed", steps),
+            None => bail!("the automaton hasn't settled after {} steps", p1 + 1),
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "v...>>.vv>
+.vv>>.vv..
+>>.>v>...v
+>>v>>.>.v.
+v>v.vv.v..
+>.>>..v...
+.vv..>.>v.
+v.v..>>v.v
+....v..v.>
+";
+
+    fn parse_text(text: &str) -> Result<Herd> {
+        parse_reader(text.as_bytes())
+    }
+
+    #[test]
+    fn example_herd() {
+        let mut herd = parse_text(EXAMPLE).unwrap();
+        assert_eq!(find_landing_time(&mut herd, None).unwrap(), Some(58));
+        assert_eq!(herd.to_grid().unwrap().row(0).iter().collect::<String>(), "..>>v>vv..");
+    }
+
+    #[test]
+    fn bits_match_the_automaton() {
+        let mut seed = 25u64;
+        for (rows, cols) in [(1, 1), (3, 7), (5, 64), (4, 65), (6, 130)] {
+            let mut herd = Herd::new(rows, cols);
+            for i in 0..rows {
+                for j in 0..cols {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    match seed >> 62 {
+                        0 => herd.east[i * herd.words + j / 64] |= 1 << (j % 64),
+                        1 => herd.south[i * herd.words + j / 64] |= 1 << (j % 64),
+                        _ => {}
+                    }
+                }
+            }
+            let mut reference = Automaton::new(herd.to_grid().unwrap(), HerdRules::sea_cucumbers());
+            for _ in 0..10 {
+                assert_eq!(herd.step(), reference.step(), "{}x{}", rows, cols);
+                assert_eq!(herd.to_grid().unwrap(), reference.grid, "{}x{}", rows, cols);
+            }
+        }
+    }
+
+    #[test]
+    fn herds_that_never_settle() {
+        let mut herd = parse_text(">.\n").unwrap();
+        assert_eq!(find_landing_time(&mut herd, None).unwrap(), None);
+    }
+
+    #[test]
+    fn writes_frames() {
+        let dir = std::env::temp_dir().join(format!("d25_frames_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // jammed from the start, so a single frame
+        let mut herd = parse_text(">v\nv>\n").unwrap();
+        let steps = find_landing_time(&mut herd, Some(&dir)).unwrap().unwrap();
+        let frame = fs::read(dir.join("000000.ppm")).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), steps);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(steps, 1);
+        assert!(frame.starts_with(b"P6\n2 2\n255\n"));
+        assert_eq!(frame.len(), 11 + 2 * 2 * 3);
+        assert_eq!(&frame[11..17], &[230, 120, 40, 60, 140, 230]);
+    }
+
+    #[test]
+    fn rejects_bad_seabeds() {
+        assert_eq!(parse_text("..\n.\n").unwrap_err().to_string(), "row 2 is 1 wide, the first is 2");
+        assert!(parse_text(".x\n").is_err());
+    }
+}
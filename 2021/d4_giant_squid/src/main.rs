@@ -2,131 +2,221 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use anyhow::Result;
-#[derive(Debug, Clone, Default)]
+use anyhow::{bail, Result};
+
+#[derive(Debug, Clone)]
 struct BingoBoard {
-    row_count: [u32; 5],
-    col_count: [u32; 5],
-    grid: [[i32; 5]; 5],
-    reverse_idx_number: HashMap<i32, Vec<(usize, usize)>>,
+    size: usize,
+    cells: Vec<i32>,
+    marked: Vec<bool>,
+    row_count: Vec<usize>,
+    col_count: Vec<usize>,
+    diag_count: [usize; 2],
+    unmarked_sum: i32,
 }
 
 impl BingoBoard {
-    fn create(grid: [[i32; 5]; 5]) -> Self {
-        let mut reverse_idx_number = HashMap::<i32, Vec<(usize, usize)>>::new();
-        // populate reverse idx
-        for (row_nr, row) in grid.iter().enumerate() {
-            for (col_nr, col) in row.iter().enumerate() {
-                if let Some(v) = reverse_idx_number.get_mut(col) {
-                    v.push((row_nr, col_nr));
-                } else {
-                    reverse_idx_number.insert(*col,vec![(row_nr, col_nr)]);
-                }
-            }
-        }
-        BingoBoard {
-            row_count: Default::default(),
-            col_count: Default::default(),
-            grid,
-            reverse_idx_number
+    fn create(rows: Vec<Vec<i32>>) -> Result<Self> {
+        let size = rows.len();
+        if rows.iter().any(|r| r.len() != size) {
+            bail!("board is not {}x{}", size, size);
         }
+        let cells: Vec<i32> = rows.into_iter().flatten().collect();
+        Ok(BingoBoard {
+            size,
+            unmarked_sum: cells.iter().sum(),
+            marked: vec![false; cells.len()],
+            cells,
+            row_count: vec![0; size],
+            col_count: vec![0; size],
+            diag_count: [0; 2],
+        })
     }
 
-    fn is_marked(&self, x: usize, y: usize) -> bool {
-        return self.grid[x][y] < 0
+    // marks one cell and tells if it completed a line
+    fn mark(&mut self, cell: usize, diagonals: bool) -> bool {
+        if self.marked[cell] {
+            return false;
+        }
+        self.marked[cell] = true;
+        self.unmarked_sum -= self.cells[cell];
+
+        let (x, y) = (cell / self.size, cell % self.size);
+        self.row_count[x] += 1;
+        self.col_count[y] += 1;
+        let mut bingo = self.row_count[x] == self.size || self.col_count[y] == self.size;
+        if diagonals {
+            if x == y {
+                self.diag_count[0] += 1;
+                bingo |= self.diag_count[0] == self.size;
+            }
+            if x + y == self.size - 1 {
+                self.diag_count[1] += 1;
+                bingo |= self.diag_count[1] == self.size;
+            }
+        }
+        bingo
     }
+}
 
-    fn is_bingo(&self) -> bool {
-        self.row_count.into_iter().any(|c| c == 5)
-            || self.col_count.into_iter().any(|c| c == 5)
+#[derive(Debug, Clone, Copy)]
+struct Win {
+    board: usize,
+    draw: usize,
+    number: i32,
+    unmarked_sum: i32,
+}
+
+impl Win {
+    fn score(&self) -> i32 {
+        self.number * self.unmarked_sum
     }
+}
 
-    fn mark(&mut self, nr: i32) {
-        if let Some(v) = self.reverse_idx_number.get(&nr)  {
-            if v.len() < 1 {
-                return;
-            }
-            for (x, y) in v.iter().copied() {
-                if self.is_marked(x, y) {
-                    return;
-                }
+struct Tournament {
+    boards: Vec<BingoBoard>,
+    // number -> every (board, cell) holding it, so a draw only touches those cells
+    index: HashMap<i32, Vec<(usize, usize)>>,
+    diagonals: bool,
+}
 
-                self.grid[x][y] = -self.grid[x][y];
-                self.row_count[x] += 1;
-                self.col_count[y] += 1;
+impl Tournament {
+    fn new(boards: Vec<BingoBoard>, diagonals: bool) -> Self {
+        let mut index = HashMap::<i32, Vec<(usize, usize)>>::new();
+        for (board_nr, board) in boards.iter().enumerate() {
+            for (cell, nr) in board.cells.iter().enumerate() {
+                index.entry(*nr).or_default().push((board_nr, cell));
             }
         }
+        Tournament { boards, index, diagonals }
     }
 
-    fn sum_unmarked(&self) -> i32 {
-        let mut sum = 0;
-        for rows in self.grid {
-            for col in rows {
-                if col >= 0 {
-                    sum += col;
+    // every board that wins, in the order it wins
+    fn play(mut self, numbers: &[i32]) -> Vec<Win> {
+        let mut won = vec![false; self.boards.len()];
+        let mut wins = Vec::new();
+        for (draw, &number) in numbers.iter().enumerate() {
+            let Some(cells) = self.index.get(&number) else {
+                continue;
+            };
+            for &(board_nr, cell) in cells {
+                if won[board_nr] {
+                    continue;
+                }
+                let board = &mut self.boards[board_nr];
+                if board.mark(cell, self.diagonals) {
+                    won[board_nr] = true;
+                    wins.push(Win { board: board_nr, draw, number, unmarked_sum: board.unmarked_sum });
                 }
             }
+            if wins.len() == self.boards.len() {
+                break;
+            }
         }
-        sum
+        wins
     }
 }
 
 fn parse(path: &Path) -> Result<(Vec<i32>, Vec<BingoBoard>)> {
-    let mut numbers = Vec::new();
-    let mut boards = Vec::new();
+    parse_reader(BufReader::new(File::open(path)?))
+}
 
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+fn parse_reader(reader: impl BufRead) -> Result<(Vec<i32>, Vec<BingoBoard>)> {
     let mut lines = reader.lines();
-    // parse bingo numbers
-    {
-        let number_line = lines.next().unwrap()?;
-        for number in number_line.split(",") {
-            numbers.push(number.parse()?);
+
+    let mut numbers = Vec::new();
+    if let Some(number_line) = lines.next() {
+        for number in number_line?.split(',') {
+            numbers.push(number.trim().parse()?);
         }
     }
-    loop {
-        if lines.next().is_none() {
-            break;
-        }
 
-        let mut grid: [[i32; 5]; 5] = [[0; 5]; 5];
-        for r in 0..5 {
-            let row = lines.next().unwrap()?;
-            let (a, b, c, d, e) = scan_fmt::scan_fmt!(&row, "{} {} {} {} {}", i32, i32, i32, i32, i32)?;
-            grid[r][0] = a;
-            grid[r][1] = b;
-            grid[r][2] = c;
-            grid[r][3] = d;
-            grid[r][4] = e;
+    let mut boards = Vec::new();
+    let mut rows: Vec<Vec<i32>> = Vec::new();
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            if !rows.is_empty() {
+                boards.push(BingoBoard::create(std::mem::take(&mut rows))?);
+            }
+            continue;
         }
-
-        boards.push(BingoBoard::create(grid));
+        rows.push(line.split_whitespace().map(|n| n.parse()).collect::<Result<_, _>>()?);
+    }
+    if !rows.is_empty() {
+        boards.push(BingoBoard::create(rows)?);
     }
     Ok((numbers, boards))
 }
 
 fn main() -> Result<()> {
-    let (numbers, mut boards) = parse(&Path::new("input.txt"))?;
-    let mut bingo_count = 0;
-    let board_count = boards.len();
-    for number in numbers {
-        for board in &mut boards {
-            if board.is_bingo() {
-                continue;
-            }
+    let diagonals = std::env::args().any(|a| a == "--diagonals");
+    let (numbers, boards) = parse(Path::new("input.txt"))?;
+    let wins = Tournament::new(boards, diagonals).play(&numbers);
 
-            board.mark(number);
+    for win in &wins {
+        println!(
+            "Board {} wins at draw {} on number {} with unmarked sum {}",
+            win.board, win.draw, win.number, win.unmarked_sum
+        );
+    }
+    if let (Some(first), Some(last)) = (wins.first(), wins.last()) {
+        println!("Part 1: {}", first.score());
+        println!("Part 2: {}", last.score());
+    }
+    Ok(())
+}
 
-            if board.is_bingo() {
-                bingo_count += 1;
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-            if bingo_count == board_count {
-                println!("At number: {} Answer: {}", number, number * board.sum_unmarked());
-                return Ok(());
-            }
-        }
+    const EXAMPLE: &str = "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1
+
+22 13 17 11  0
+ 8  2 23  4 24
+21  9 14 16  7
+ 6 10  3 18  5
+ 1 12 20 15 19
+
+ 3 15  0  2 22
+ 9 18 13 17  5
+19  8  7 25 23
+20 11 10 24  4
+14 21 16 12  6
+
+14 21 17 24  4
+10 16 15  9 19
+18  8 23 26 20
+22 11 13  6  5
+ 2  0 12  3  7
+";
+
+    fn parse_text(text: &str) -> Result<(Vec<i32>, Vec<BingoBoard>)> {
+        parse_reader(text.as_bytes())
+    }
+
+    #[test]
+    fn example_tournament() {
+        let (numbers, boards) = parse_text(EXAMPLE).unwrap();
+        let wins = Tournament::new(boards, false).play(&numbers);
+        let order: Vec<(usize, usize, i32, i32)> = wins.iter().map(|w| (w.board, w.draw, w.number, w.unmarked_sum)).collect();
+        assert_eq!(order, vec![(2, 11, 24, 188), (0, 13, 16, 137), (1, 14, 13, 148)]);
+        assert_eq!(wins[0].score(), 4512);
+        assert_eq!(wins[2].score(), 1924);
+    }
+
+    #[test]
+    fn diagonals_only_count_when_asked() {
+        let (numbers, boards) = parse_text("1,5,9,2,3\n\n1 2 3\n4 5 6\n7 8 9\n").unwrap();
+        let win = Tournament::new(boards.clone(), true).play(&numbers)[0];
+        assert_eq!((win.draw, win.number, win.unmarked_sum), (2, 9, 30));
+        let win = Tournament::new(boards, false).play(&numbers)[0];
+        assert_eq!((win.draw, win.number, win.unmarked_sum), (4, 3, 25));
+    }
+
+    #[test]
+    fn rejects_boards_that_are_not_square() {
+        assert_eq!(parse_text("1\n\n1 2\n3\n").unwrap_err().to_string(), "board is not 2x2");
     }
-    Ok(())
 }
@@ -1,16 +1,17 @@
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
 use anyhow::Result;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Point {
-    x: i32,
-    y: i32
+    x: i64,
+    y: i64
 }
 
 impl Point {
-    fn new(x: i32, y: i32) -> Self {
+    fn new(x: i64, y: i64) -> Self {
         Self {
             x,
             y
@@ -18,43 +19,96 @@ impl Point {
     }
 }
 
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+fn cross(ax: i64, ay: i64, bx: i64, by: i64) -> i128 {
+    ax as i128 * by as i128 - ay as i128 * bx as i128
+}
+
+// primitive direction (a, b) and the cross product that tells parallel lines apart
+type LineKey = (i64, i64, i128);
+
+// the lattice points of a segment are start + k * step for k in 0..=steps
 #[derive(Debug, Clone)]
 struct LineSegment {
     p1: Point,
-    p2: Point
+    p2: Point,
+    step: (i64, i64),
+    steps: i64,
 }
 
 impl LineSegment {
     fn new(p1: Point, p2: Point) -> Self {
+        let (dx, dy) = (p2.x - p1.x, p2.y - p1.y);
+        let steps = gcd(dx, dy);
+        let step = if steps == 0 {
+            (1, 0)
+        } else {
+            (dx / steps, dy / steps)
+        };
         Self {
             p1,
-            p2
+            p2,
+            step,
+            steps
         }
     }
 
-    fn is_horizontal(&self) -> bool {
-        return self.p1.x == self.p2.x
+    fn is_axis_aligned(&self) -> bool {
+        self.p1.x == self.p2.x || self.p1.y == self.p2.y
+    }
+
+    fn get_x_min_max(&self) -> (i64, i64) {
+        (self.p1.x.min(self.p2.x), self.p1.x.max(self.p2.x))
     }
 
-    fn is_vertical(&self) -> bool {
-        return self.p1.y == self.p2.y
+    fn get_y_min_max(&self) -> (i64, i64) {
+        (self.p1.y.min(self.p2.y), self.p1.y.max(self.p2.y))
     }
 
-    fn get_y_min_max(&self) -> (i32, i32) {
-        if self.p1.y > self.p2.y {
-            (self.p2.y, self.p1.y)
+    fn line_key(&self) -> LineKey {
+        let (a, b) = if self.step.0 < 0 || (self.step.0 == 0 && self.step.1 < 0) {
+            (-self.step.0, -self.step.1)
         } else {
-            (self.p1.y, self.p2.y)
-        }
+            self.step
+        };
+        (a, b, cross(self.p1.x, self.p1.y, a, b))
     }
 
-    fn get_x_min_max(&self) -> (i32, i32) {
-        if self.p1.x > self.p2.x {
-            (self.p2.x, self.p1.x)
+    // all points of a line with primitive direction (a, b) share x mod a (or x when a == 0),
+    // so this numbers them consecutively along the line
+    fn position_on_line(a: i64, p: Point) -> i64 {
+        if a == 0 {
+            p.y
         } else {
-            (self.p1.x, self.p2.x)
+            p.x.div_euclid(a)
         }
     }
+
+    fn crossing(&self, other: &LineSegment) -> Option<Point> {
+        let denom = cross(self.step.0, self.step.1, other.step.0, other.step.1);
+        if denom == 0 {
+            return None;
+        }
+        let (wx, wy) = (other.p1.x - self.p1.x, other.p1.y - self.p1.y);
+        let k_num = cross(wx, wy, other.step.0, other.step.1);
+        let m_num = cross(wx, wy, self.step.0, self.step.1);
+        if k_num % denom != 0 || m_num % denom != 0 {
+            return None;
+        }
+        let (k, m) = (k_num / denom, m_num / denom);
+        if k < 0 || k > self.steps as i128 || m < 0 || m > other.steps as i128 {
+            return None;
+        }
+        let k = k as i64;
+        Some(Point::new(self.p1.x + k * self.step.0, self.p1.y + k * self.step.1))
+    }
 }
 
 fn parse(path: &Path) -> Result<Vec<LineSegment>> {
@@ -64,7 +118,7 @@ fn parse(path: &Path) -> Result<Vec<LineSegment>> {
     let mut segments = Vec::new();
     for line in reader.lines() {
         let line = line?;
-        let (x1, y1, x2, y2) = scan_fmt::scan_fmt!(&line, "{},{} -> {},{}", i32, i32, i32 ,i32)?;
+        let (x1, y1, x2, y2) = scan_fmt::scan_fmt!(&line, "{},{} -> {},{}", i64, i64, i64, i64)?;
         segments.push(
             LineSegment::new(Point::new(x1, y1), Point::new(x2, y2))
         );
@@ -73,61 +127,152 @@ fn parse(path: &Path) -> Result<Vec<LineSegment>> {
     Ok(segments)
 }
 
-fn count_dangerous(segments: &Vec<LineSegment>) -> i32 {
-    let mut dangerous_cells = 0;
-    let mut grid: Vec<Vec<i32>> = vec![vec![0; 1000]; 1000];
+// ranges of positions covered at least twice, from [start, end] ranges on one line
+fn overlapping_ranges(ranges: &[(i64, i64)]) -> Vec<(i64, i64)> {
+    let mut events: Vec<(i64, i32)> = ranges
+        .iter()
+        .flat_map(|&(s, e)| [(s, 1), (e + 1, -1)])
+        .collect();
+    events.sort();
 
-    for segment in segments {
-        if segment.is_horizontal() {
-            let x = segment.p1.x as usize;
-            let (y_min, y_max) = segment.get_y_min_max();
-            for y in y_min..=y_max {
-                let y = y as usize;
-                grid[x][y] += 1;
-            }
-        } else if segment.is_vertical() {
-            let y = segment.p1.y as usize;
-            let (x_min, x_max) = segment.get_x_min_max();
-            for x in x_min..=x_max {
-                let x = x as usize;
-                grid[x][y] += 1;
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut open_at = 0;
+    for (pos, delta) in events {
+        let was_dangerous = depth >= 2;
+        depth += delta;
+        if !was_dangerous && depth >= 2 {
+            open_at = pos;
+        } else if was_dangerous && depth < 2 && pos > open_at {
+            match result.last_mut() {
+                Some((_, end)) if *end + 1 == open_at => *end = pos - 1,
+                _ => result.push((open_at, pos - 1)),
             }
-        } else {
-            let dx = if segment.p2.x > segment.p1.x {
-                1
-            } else {
-                -1
-            };
-            let dy = if segment.p2.y > segment.p1.y {
-                1
-            } else {
-                -1
-            };
-            let mut x = segment.p1.x;
-            let mut y = segment.p1.y;
-            while x != segment.p2.x && y != segment.p2.y {
-                grid[x as usize][y as usize] += 1;
-                x += dx;
-                y += dy;
-            }
-            grid[segment.p2.x as usize][segment.p2.y as usize] += 1;
         }
     }
+    result
+}
+
+fn in_ranges(ranges: &[(i64, i64)], pos: i64) -> bool {
+    let idx = ranges.partition_point(|&(_, end)| end < pos);
+    idx < ranges.len() && ranges[idx].0 <= pos
+}
 
-    for row in grid {
-        for col in row {
-            if col > 1 {
-                dangerous_cells += 1;
+fn count_dangerous(segments: &[LineSegment]) -> i64 {
+    // collinear overlaps, one sweep per supporting line
+    let mut lines: HashMap<LineKey, Vec<(i64, i64)>> = HashMap::new();
+    for segment in segments {
+        let key = segment.line_key();
+        let s = LineSegment::position_on_line(key.0, segment.p1);
+        let e = LineSegment::position_on_line(key.0, segment.p2);
+        lines.entry(key).or_default().push((s.min(e), s.max(e)));
+    }
+    let overlaps: HashMap<LineKey, Vec<(i64, i64)>> = lines
+        .into_iter()
+        .map(|(key, ranges)| (key, overlapping_ranges(&ranges)))
+        .collect();
+    let mut dangerous_cells: i64 = overlaps
+        .values()
+        .flatten()
+        .map(|(s, e)| e - s + 1)
+        .sum();
+
+    // crossings of non-parallel segments, sweeping by x so only segments
+    // whose x ranges overlap are ever paired
+    let mut order: Vec<&LineSegment> = segments.iter().collect();
+    order.sort_by_key(|s| s.get_x_min_max().0);
+    let mut active: Vec<&LineSegment> = Vec::new();
+    let mut crossings: HashSet<(Point, LineKey, LineKey)> = HashSet::new();
+    for segment in order {
+        let (x_min, _) = segment.get_x_min_max();
+        active.retain(|s| s.get_x_min_max().1 >= x_min);
+        let (y_min, y_max) = segment.get_y_min_max();
+        for other in &active {
+            let (o_min, o_max) = other.get_y_min_max();
+            if o_max < y_min || o_min > y_max {
+                continue;
+            }
+            if let Some(p) = segment.crossing(other) {
+                crossings.insert((p, segment.line_key(), other.line_key()));
             }
         }
+        active.push(segment);
+    }
+
+    // a crossing inside a collinear overlap is already counted
+    let mut crossing_points: HashMap<Point, bool> = HashMap::new();
+    for (p, k1, k2) in crossings {
+        let covered = [k1, k2].iter().any(|k| {
+            in_ranges(&overlaps[k], LineSegment::position_on_line(k.0, p))
+        });
+        *crossing_points.entry(p).or_insert(false) |= covered;
     }
+    dangerous_cells += crossing_points.values().filter(|covered| !**covered).count() as i64;
 
     dangerous_cells
 }
 
 fn main() -> Result<()> {
-    let segments = parse(&Path::new("input.txt"))?;
-    let part_1 = count_dangerous(&segments);
-    println!("Part 2: {}", part_1);
+    let segments = parse(Path::new("input.txt"))?;
+    let axis_aligned: Vec<LineSegment> = segments
+        .iter()
+        .filter(|s| s.is_axis_aligned())
+        .cloned()
+        .collect();
+    println!("Part 1: {}", count_dangerous(&axis_aligned));
+    println!("Part 2: {}", count_dangerous(&segments));
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [(i64, i64, i64, i64); 10] = [
+        (0, 9, 5, 9), (8, 0, 0, 8), (9, 4, 3, 4), (2, 2, 2, 1), (7, 0, 7, 4),
+        (6, 4, 2, 0), (0, 9, 2, 9), (3, 4, 1, 4), (0, 0, 8, 8), (5, 5, 8, 2),
+    ];
+
+    fn segments(coords: &[(i64, i64, i64, i64)]) -> Vec<LineSegment> {
+        coords.iter().map(|&(x1, y1, x2, y2)| LineSegment::new(Point::new(x1, y1), Point::new(x2, y2))).collect()
+    }
+
+    // every lattice point of every segment, counted one by one
+    fn brute_force(segments: &[LineSegment]) -> i64 {
+        let mut seen: HashMap<Point, usize> = HashMap::new();
+        for s in segments {
+            for k in 0..=s.steps {
+                *seen.entry(Point::new(s.p1.x + k * s.step.0, s.p1.y + k * s.step.1)).or_default() += 1;
+            }
+        }
+        seen.values().filter(|n| **n >= 2).count() as i64
+    }
+
+    #[test]
+    fn example_vents() {
+        let segments = segments(&EXAMPLE);
+        let axis_aligned: Vec<LineSegment> = segments.iter().filter(|s| s.is_axis_aligned()).cloned().collect();
+        assert_eq!(count_dangerous(&axis_aligned), 5);
+        assert_eq!(count_dangerous(&segments), 12);
+    }
+
+    #[test]
+    fn any_slope_matches_brute_force() {
+        let mut seed = 5u64;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 33) % n) as i64 - (n / 2) as i64
+        };
+        for _ in 0..200 {
+            let coords: Vec<(i64, i64, i64, i64)> = (0..8).map(|_| (next(13), next(13), next(13), next(13))).collect();
+            let segments = segments(&coords);
+            assert_eq!(count_dangerous(&segments), brute_force(&segments), "{:?}", coords);
+        }
+    }
+
+    #[test]
+    fn overlaps_merge_along_a_line() {
+        assert_eq!(overlapping_ranges(&[(0, 5), (3, 8), (6, 10), (20, 20)]), vec![(3, 8)]);
+        assert_eq!(overlapping_ranges(&[(0, 2), (2, 4), (4, 6)]), vec![(2, 2), (4, 4)]);
+    }
+}
@@ -1,49 +1,158 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use anyhow::Result;
+use anyhow::{bail, Result};
+use num_bigint::BigUint;
+use semiring::{mat_pow, Matrix, Modular, Semiring};
 
-fn parse(path: &Path) -> Result<Vec<i32>> {
+fn parse(path: &Path) -> Result<Vec<usize>> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
 
     let mut r = Vec::new();
 
     let line = reader.lines().next().unwrap()?;
-    for nr in line.split(",") {
+    for nr in line.trim().split(',') {
         r.push(nr.parse()?);
     }
 
     Ok(r)
 }
 
-fn solve(steps: i32, age: &Vec<i32>) -> i128 {
-    let mut fish = 0;
-    let mut buckets = [0; 9];
-    for a in age {
-        buckets[*a as usize] += 1;
+#[derive(Debug, Clone, Copy)]
+struct Lifecycle {
+    reset_timer: usize,
+    newborn_timer: usize,
+}
+
+impl Lifecycle {
+    fn states(&self) -> usize {
+        self.reset_timer.max(self.newborn_timer) + 1
+    }
+
+    // v'[t - 1] = v[t]; a fish at 0 goes back to reset_timer and spawns one at newborn_timer
+    fn transition<T: Semiring>(&self, unit: &T) -> Matrix<T> {
+        let n = self.states();
+        let mut m = vec![vec![unit.zero(); n]; n];
+        for t in 1..n {
+            m[t - 1][t] = unit.one();
+        }
+        m[self.reset_timer][0] = m[self.reset_timer][0].add(&unit.one());
+        m[self.newborn_timer][0] = m[self.newborn_timer][0].add(&unit.one());
+        m
     }
 
-    for i in 0..steps {
-        let new_fish = buckets[0];
-        for j in 0..8 {
-            buckets[j] = buckets[j + 1];
+    fn count<T: Semiring>(&self, ages: &[usize], steps: u64, unit: &T) -> Result<T> {
+        let n = self.states();
+        let mut buckets = vec![unit.zero(); n];
+        for &a in ages {
+            if a >= n {
+                bail!("timer {} is larger than any timer in the lifecycle", a);
+            }
+            buckets[a] = buckets[a].add(&unit.one());
+        }
+
+        let m = mat_pow(self.transition(unit), steps, unit);
+        let mut fish = unit.zero();
+        for row in &m {
+            for (cell, bucket) in row.iter().zip(&buckets) {
+                fish = fish.add(&cell.mul(bucket));
+            }
         }
-        buckets[8] = new_fish;
-        buckets[6] += new_fish;
+        Ok(fish)
+    }
+
+    fn solve(&self, steps: u64, ages: &[usize]) -> Result<BigUint> {
+        self.count(ages, steps, &BigUint::ZERO)
     }
 
-    for i in 0..9 {
-        fish += buckets[i];
+    fn solve_mod(&self, steps: u64, ages: &[usize], p: u64) -> Result<u64> {
+        if p == 0 {
+            bail!("modulus must be positive");
+        }
+        Ok(self.count(ages, steps, &Modular::new(0, p))?.value)
     }
+}
 
-    fish
+fn arg_value(name: &str) -> Result<Option<u64>> {
+    let args: Vec<String> = std::env::args().collect();
+    match args.iter().position(|a| a == name) {
+        Some(i) => match args.get(i + 1) {
+            Some(v) => Ok(Some(v.parse()?)),
+            None => bail!("{} needs a value", name),
+        },
+        None => Ok(None),
+    }
 }
 
 fn main() -> Result<()> {
-    let r = parse(&Path::new("input.txt"))?;
-    let part_1 = solve(80, &r);
-    let part_2 = solve(256, &r);
-    println!("Part 1 {} \n Part 2 {}", part_1, part_2);
+    let r = parse(Path::new("input.txt"))?;
+    let lifecycle = Lifecycle {
+        reset_timer: arg_value("--reset")?.unwrap_or(6) as usize,
+        newborn_timer: arg_value("--newborn")?.unwrap_or(8) as usize,
+    };
+
+    println!("Part 1 {}", lifecycle.solve(80, &r)?);
+    println!("Part 2 {}", lifecycle.solve(256, &r)?);
+
+    if let Some(days) = arg_value("--days")? {
+        match arg_value("--mod")? {
+            Some(p) => println!("After {} days (mod {}): {}", days, p, lifecycle.solve_mod(days, &r, p)?),
+            None => println!("After {} days: {}", days, lifecycle.solve(days, &r)?),
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [usize; 5] = [3, 4, 3, 1, 2];
+    const STANDARD: Lifecycle = Lifecycle { reset_timer: 6, newborn_timer: 8 };
+
+    // one fish at a time, day by day
+    fn simulate(lifecycle: &Lifecycle, ages: &[usize], steps: u64) -> usize {
+        let mut fish = ages.to_vec();
+        for _ in 0..steps {
+            let born = fish.iter().filter(|t| **t == 0).count();
+            for t in fish.iter_mut() {
+                *t = if *t == 0 { lifecycle.reset_timer } else { *t - 1 };
+            }
+            fish.extend(std::iter::repeat_n(lifecycle.newborn_timer, born));
+        }
+        fish.len()
+    }
+
+    #[test]
+    fn example_school() {
+        assert_eq!(STANDARD.solve(18, &EXAMPLE).unwrap(), BigUint::from(26u32));
+        assert_eq!(STANDARD.solve(80, &EXAMPLE).unwrap(), BigUint::from(5934u32));
+        assert_eq!(STANDARD.solve(256, &EXAMPLE).unwrap(), BigUint::from(26984457539u64));
+    }
+
+    #[test]
+    fn other_lifecycles_match_simulation() {
+        for (reset_timer, newborn_timer) in [(2, 4), (4, 2), (3, 3), (0, 1)] {
+            let lifecycle = Lifecycle { reset_timer, newborn_timer };
+            let ages: Vec<usize> = EXAMPLE.iter().map(|a| a % lifecycle.states()).collect();
+            for steps in [0, 1, 7, 20] {
+                assert_eq!(lifecycle.solve(steps, &ages).unwrap(), BigUint::from(simulate(&lifecycle, &ages, steps)));
+            }
+        }
+    }
+
+    #[test]
+    fn modular_count_matches_the_exact_one() {
+        let exact = STANDARD.solve(1000, &EXAMPLE).unwrap();
+        for p in [1, 7, 1_000_000_007] {
+            assert_eq!(BigUint::from(STANDARD.solve_mod(1000, &EXAMPLE, p).unwrap()), &exact % p);
+        }
+        assert!(STANDARD.solve_mod(10, &EXAMPLE, 0).is_err());
+    }
+
+    #[test]
+    fn rejects_timers_past_the_lifecycle() {
+        assert_eq!(STANDARD.solve(1, &[9]).unwrap_err().to_string(), "timer 9 is larger than any timer in the lifecycle");
+    }
+}
@@ -1,51 +1,231 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::ops::RangeInclusive;
 use std::path::Path;
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 
-fn parse(path: &Path) -> Result<Vec<i32>> {
+fn parse(path: &Path) -> Result<Vec<i64>> {
     let file = File::open(path)?;
     let reader = BufReader::new(file);
-    let line = reader.lines().next().unwrap()?;
+    let line = reader.lines().next().ok_or_else(|| anyhow!("empty input"))??;
 
     line
-        .split(",")
-        .map(|s|s.parse().map_err(|o|anyhow!("parse")))
-        .collect::<Result<Vec<i32>>>()
+        .trim()
+        .split(',')
+        .map(|s| s.parse().map_err(|_| anyhow!("parse")))
+        .collect::<Result<Vec<i64>>>()
 }
 
-fn part_1(crabs: &Vec<i32>) -> i32 {
-    let mx = crabs.into_iter().max().copied().unwrap() as usize + 1;
-    let mut scores = vec![0; mx];
+// fuel for moving one crab `distance` steps; must be convex in the distance
+trait CostFunction {
+    fn cost(&self, distance: i64) -> i64;
 
-    for crab in crabs {
-        for i in 0..mx {
-            scores[i] += (*crab - i as i32).abs();
-        }
+    // a position known to be optimal, when there is a closed form for it
+    fn closed_form(&self, _sorted_crabs: &[i64]) -> Option<i64> {
+        None
+    }
+}
+
+struct Linear;
+
+impl CostFunction for Linear {
+    fn cost(&self, distance: i64) -> i64 {
+        distance
+    }
+
+    fn closed_form(&self, sorted_crabs: &[i64]) -> Option<i64> {
+        Some(sorted_crabs[sorted_crabs.len() / 2])
     }
+}
+
+struct Triangular;
+
+impl CostFunction for Triangular {
+    fn cost(&self, distance: i64) -> i64 {
+        distance * (distance + 1) / 2
+    }
+
+    // the optimum lies within half a step of the mean
+    fn closed_form(&self, sorted_crabs: &[i64]) -> Option<i64> {
+        let floor = mean(sorted_crabs).0;
+        (floor - 1..=floor + 1)
+            .map(|p| p.clamp(sorted_crabs[0], sorted_crabs[sorted_crabs.len() - 1]))
+            .min_by_key(|&p| total_fuel(sorted_crabs, self, p))
+    }
+}
+
+struct Quadratic;
+
+impl CostFunction for Quadratic {
+    fn cost(&self, distance: i64) -> i64 {
+        distance * distance
+    }
+
+    // squared distances are least around the mean, so at it rounded
+    fn closed_form(&self, sorted_crabs: &[i64]) -> Option<i64> {
+        Some(mean(sorted_crabs).1)
+    }
+}
+
+struct Convex<F: Fn(i64) -> i64>(F);
+
+impl<F: Fn(i64) -> i64> CostFunction for Convex<F> {
+    fn cost(&self, distance: i64) -> i64 {
+        (self.0)(distance)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Alignment {
+    fuel: i64,
+    positions: RangeInclusive<i64>,
+}
+
+fn total_fuel(crabs: &[i64], cost: &dyn CostFunction, position: i64) -> i64 {
+    crabs.iter().map(|c| cost.cost((c - position).abs())).sum()
+}
 
-    scores.iter().min().copied().unwrap()
+// the mean rounded down and rounded to the nearest integer
+fn mean(crabs: &[i64]) -> (i64, i64) {
+    let (sum, n): (i64, i64) = (crabs.iter().sum(), crabs.len() as i64);
+    (sum.div_euclid(n), (2 * sum + n).div_euclid(2 * n))
 }
 
-fn part_2(crabs: &Vec<i32>) -> i32 {
-    let mx = crabs.into_iter().max().copied().unwrap() as usize + 1;
-    let mut scores = vec![0; mx];
+fn ternary_search(crabs: &[i64], cost: &dyn CostFunction, mut lo: i64, mut hi: i64) -> i64 {
+    while hi - lo > 2 {
+        let m1 = lo + (hi - lo) / 3;
+        let m2 = hi - (hi - lo) / 3;
+        let (f1, f2) = (total_fuel(crabs, cost, m1), total_fuel(crabs, cost, m2));
+        if f1 < f2 {
+            hi = m2 - 1;
+        } else if f1 > f2 {
+            lo = m1 + 1;
+        } else {
+            lo = m1;
+            hi = m2;
+        }
+    }
+    (lo..=hi).min_by_key(|&p| total_fuel(crabs, cost, p)).unwrap()
+}
+
+// the total is convex, so the optimal positions form one range around `best`
+fn plateau(crabs: &[i64], cost: &dyn CostFunction, best: i64, lo: i64, hi: i64) -> RangeInclusive<i64> {
+    let fuel = total_fuel(crabs, cost, best);
+    let (mut l, mut r) = (lo, best);
+    while l < r {
+        let m = l + (r - l) / 2;
+        if total_fuel(crabs, cost, m) == fuel {
+            r = m;
+        } else {
+            l = m + 1;
+        }
+    }
+    let start = l;
 
-    for crab in crabs {
-        for i in 0..mx {
-            let distance = (*crab - i as i32).abs();
-            scores[i] += (distance * (distance + 1)) / 2;
+    let (mut l, mut r) = (best, hi);
+    while l < r {
+        let m = r - (r - l) / 2;
+        if total_fuel(crabs, cost, m) == fuel {
+            l = m;
+        } else {
+            r = m - 1;
         }
     }
+    start..=l
+}
+
+fn align(crabs: &[i64], cost: &dyn CostFunction) -> Result<Alignment> {
+    if crabs.is_empty() {
+        bail!("no crabs to align");
+    }
+    let mut sorted = crabs.to_vec();
+    sorted.sort_unstable();
+    let (lo, hi) = (sorted[0], sorted[sorted.len() - 1]);
+
+    let best = match cost.closed_form(&sorted) {
+        Some(p) => p,
+        None => ternary_search(&sorted, cost, lo, hi),
+    };
 
-    scores.iter().min().copied().unwrap()
+    Ok(Alignment {
+        fuel: total_fuel(&sorted, cost, best),
+        positions: plateau(&sorted, cost, best, lo, hi),
+    })
+}
+
+fn report(name: &str, alignment: &Alignment) {
+    println!(
+        "{} {} at positions {}..={}",
+        name,
+        alignment.fuel,
+        alignment.positions.start(),
+        alignment.positions.end()
+    );
 }
 
 fn main() -> Result<()> {
-    let crabs = parse(&Path::new("input.txt"))?;
-    let rp1 = part_1(&crabs);
-    println!("Part 1 {}", rp1);
-    let rp2 = part_2(&crabs);
-    println!("Part 2 {}", rp2);
+    let crabs = parse(Path::new("input.txt"))?;
+    report("Part 1", &align(&crabs, &Linear)?);
+    report("Part 2", &align(&crabs, &Triangular)?);
+    report("Quadratic", &align(&crabs, &Quadratic)?);
+    report("Cubic", &align(&crabs, &Convex(|d| d * d * d))?);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: [i64; 10] = [16, 1, 2, 0, 4, 2, 7, 1, 2, 14];
+
+    fn brute_force(crabs: &[i64], cost: &dyn CostFunction) -> (i64, Vec<i64>) {
+        let (lo, hi) = (*crabs.iter().min().unwrap(), *crabs.iter().max().unwrap());
+        let fuel = (lo..=hi).map(|p| total_fuel(crabs, cost, p)).min().unwrap();
+        (fuel, (lo..=hi).filter(|&p| total_fuel(crabs, cost, p) == fuel).collect())
+    }
+
+    #[test]
+    fn example() {
+        let linear = align(&EXAMPLE, &Linear).unwrap();
+        assert_eq!((linear.fuel, *linear.positions.start()), (37, 2));
+        let triangular = align(&EXAMPLE, &Triangular).unwrap();
+        assert_eq!((triangular.fuel, triangular.positions), (168, 5..=5));
+    }
+
+    #[test]
+    fn closed_forms() {
+        assert!(Linear.closed_form(&[1, 2]).is_some());
+        assert!(Triangular.closed_form(&[1, 2]).is_some());
+        assert!(Quadratic.closed_form(&[1, 2]).is_some());
+        assert!(Convex(|d| d * d * d).closed_form(&[1, 2]).is_none());
+        assert_eq!(align(&[1, 3], &Linear).unwrap().positions, 1..=3);
+    }
+
+    #[test]
+    fn every_cost_model_matches_brute_force() {
+        let mut seed = 12345u64;
+        for _ in 0..200 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let n = (seed >> 33) % 12 + 1;
+            let crabs: Vec<i64> = (0..n)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    ((seed >> 33) % 40) as i64 - 10
+                })
+                .collect();
+            let cubic = Convex(|d| d * d * d);
+            let models: [&dyn CostFunction; 4] = [&Linear, &Triangular, &Quadratic, &cubic];
+            for cost in models {
+                let (fuel, positions) = brute_force(&crabs, cost);
+                let alignment = align(&crabs, cost).unwrap();
+                assert_eq!(alignment.fuel, fuel, "{:?}", crabs);
+                assert_eq!(alignment.positions.collect::<Vec<_>>(), positions, "{:?}", crabs);
+            }
+        }
+    }
+
+    #[test]
+    fn no_crabs() {
+        assert!(align(&[], &Linear).is_err());
+    }
+}
@@ -1,172 +1,271 @@
-use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use anyhow::Result;
+use anyhow::{bail, Result};
 
-#[derive(Debug, Clone)]
-struct Entry {
-    signal: Vec<String>,
-    display: Vec<String>,
+// segment masks of the digits 0..=9, bit 0 = segment a
+const DIGITS: [u8; 10] = [
+    0b1110111, 0b0100100, 0b1011101, 0b1101101, 0b0101110,
+    0b1101011, 0b1111011, 0b0100101, 0b1111111, 0b1101111,
+];
+
+fn to_mask(pattern: &str) -> Result<u8> {
+    let mut mask = 0;
+    for c in pattern.chars() {
+        if !('a'..='g').contains(&c) {
+            bail!("invalid segment {:?} in {:?}", c, pattern);
+        }
+        mask |= 1 << (c as u8 - b'a');
+    }
+    Ok(mask)
 }
 
-impl Entry {
-    fn create(line: &str) -> Self {
-        let mut spl = line.split(" | ");
-        let signal = spl.next().unwrap().split(" ").map(|x| {
-            let mut chars: Vec<char> = x.chars().collect(); // Collect characters into a Vec<char>
-            chars.sort(); // Sort the characters alphabetically
-            chars.into_iter().collect() // Collect them back into a String
-        }).collect();
-
-        let display = spl.next().unwrap().split(" ").map(|x| {
-            let mut chars: Vec<char> = x.chars().collect(); // Collect characters into a Vec<char>
-            chars.sort(); // Sort the characters alphabetically
-            chars.into_iter().collect() // Collect them back into a String
-        }).collect();
-
-        Self {
-            signal,
-            display
+// wiring[w] is the segment lit by wire w
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Wiring([u8; 7]);
+
+impl Wiring {
+    fn translate(&self, pattern: u8) -> u8 {
+        (0..7)
+            .filter(|w| pattern & (1 << w) != 0)
+            .fold(0, |m, w| m | (1 << self.0[w]))
+    }
+
+    fn digit(&self, pattern: u8) -> Option<usize> {
+        let segments = self.translate(pattern);
+        DIGITS.iter().position(|&d| d == segments)
+    }
+}
+
+impl fmt::Display for Wiring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (w, s) in self.0.iter().enumerate() {
+            if w > 0 {
+                write!(f, " ")?;
+            }
+            write!(f, "{}->{}", (b'a' + w as u8) as char, (b'a' + s) as char)?;
         }
+        Ok(())
     }
 }
 
-fn parse(path: &Path) -> Result<Vec<Entry>> {
-    let mut result = Vec::new();
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum WiringError {
+    Inconsistent,
+    Ambiguous(Vec<Wiring>),
+}
 
-    for line in reader.lines() {
-        let line = line?;
-        result.push(Entry::create(&line));
+impl fmt::Display for WiringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WiringError::Inconsistent => write!(f, "no consistent wiring"),
+            WiringError::Ambiguous(w) => write!(f, "multiple wirings ({} found)", w.len()),
+        }
     }
+}
 
-    Ok(result)
+impl std::error::Error for WiringError {}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    signal: Vec<u8>,
+    display: Vec<u8>,
 }
 
-fn part_1(entries: &Vec<Entry>) -> i32 {
-    let mut count = 0;
-    for entry in entries {
-        count += entry.display
+impl Entry {
+    fn create(line: &str) -> Result<Self> {
+        let Some((signal, display)) = line.split_once(" | ") else {
+            bail!("missing ' | ' in {:?}", line);
+        };
+        Ok(Self {
+            signal: signal.split_whitespace().map(to_mask).collect::<Result<_>>()?,
+            display: display.split_whitespace().map(to_mask).collect::<Result<_>>()?,
+        })
+    }
+
+    fn patterns(&self) -> Vec<u8> {
+        let mut patterns: Vec<u8> = self.signal.iter().chain(&self.display).copied().collect();
+        patterns.sort_unstable();
+        patterns.dedup();
+        patterns
+    }
+
+    // every wiring under which all observed patterns are digits
+    fn wirings(&self) -> Vec<Wiring> {
+        let patterns = self.patterns();
+
+        // a pattern of n wires can only be a digit of n segments: wires inside it
+        // go to those digits' segments, wires outside it to their complements
+        let mut domains = [0x7fu8; 7];
+        for &p in &patterns {
+            let len = p.count_ones();
+            let (mut inside, mut outside) = (0u8, 0u8);
+            for d in DIGITS.iter().filter(|d| d.count_ones() == len) {
+                inside |= d;
+                outside |= !d & 0x7f;
+            }
+            for (w, domain) in domains.iter_mut().enumerate() {
+                *domain &= if p & (1 << w) != 0 { inside } else { outside };
+            }
+        }
+
+        let mut found = Vec::new();
+        let mut wiring = [0u8; 7];
+        search(&patterns, &domains, 0, 0, &mut wiring, &mut found);
+        found
+    }
+
+    fn wiring(&self) -> Result<Wiring, WiringError> {
+        let mut wirings = self.wirings();
+        match wirings.len() {
+            0 => Err(WiringError::Inconsistent),
+            1 => Ok(wirings.pop().unwrap()),
+            _ => Err(WiringError::Ambiguous(wirings)),
+        }
+    }
+
+    // the display value, as long as every consistent wiring agrees on it
+    fn decode(&self) -> Result<u32, WiringError> {
+        let wirings = self.wirings();
+        let values: Vec<u32> = wirings
             .iter()
-            .filter(|x| x.len() == 7 || x.len() == 4 || x.len() == 2 || x.len() == 3)
-            .count() as i32;
+            .map(|w| {
+                self.display
+                    .iter()
+                    .fold(0, |acc, &p| acc * 10 + w.digit(p).unwrap() as u32)
+            })
+            .collect();
+        match values.first() {
+            None => Err(WiringError::Inconsistent),
+            Some(v) if values.iter().all(|x| x == v) => Ok(*v),
+            Some(_) => Err(WiringError::Ambiguous(wirings)),
+        }
     }
-    count
 }
 
-fn all_chars_in_a2(a1: &str, a2: &str) -> bool {
-    let set_a2: HashSet<char> = a2.chars().collect(); // Create a set of characters in A2
-    a1.chars().all(|c| set_a2.contains(&c)) // Check if every character in A1 is in the set
-}
+fn search(patterns: &[u8], domains: &[u8; 7], wire: usize, used: u8, wiring: &mut [u8; 7], found: &mut Vec<Wiring>) {
+    if wire == 7 {
+        let w = Wiring(*wiring);
+        if patterns.iter().all(|&p| w.digit(p).is_some()) {
+            found.push(w);
+        }
+        return;
+    }
 
-fn find_mapping(entry: &Entry) -> HashMap<String, i32> {
-    let mut mapping = HashMap::new();
-    let mut imapping = HashMap::<i32, String>::new();
-
-    // find one
-    {
-        let str = entry.signal.iter().filter(|x| x.len() == 2).next().unwrap().clone();
-        mapping.insert(str.clone(), 1);
-        imapping.insert(1, str);
-    }
-    // find four
-    {
-        let str = entry.signal.iter().filter(|x| x.len() == 4).next().unwrap().clone();
-        mapping.insert(str.clone(), 4);
-        imapping.insert(4, str);
-    }
-    // find seven
-    {
-        let str = entry.signal.iter().filter(|x| x.len() == 3).next().unwrap().clone();
-        mapping.insert(str.clone(), 7);
-        imapping.insert(7, str);
-    }
-    // find eight
-    {
-        let str = entry.signal.iter().filter(|x| x.len() == 7).next().unwrap().clone();
-        mapping.insert(str.clone(), 8);
-        imapping.insert(8, str);
-    }
-
-    // find nine
-    {
-        let four = imapping.get(&4).unwrap();
-        let str = entry.signal.iter().filter(|x| x.len() == 6 && all_chars_in_a2(four, x)).next().unwrap().clone();
-        mapping.insert(str.clone(), 9);
-        imapping.insert(9, str);
-    }
-    // find six
-    {
-        let one = imapping.get(&1).unwrap();
-        let str = entry.signal.iter().filter(|x| x.len() == 6 && !all_chars_in_a2(one, x)).next().unwrap().clone();
-        mapping.insert(str.clone(), 6);
-        imapping.insert(6, str);
-    }
-    // find zero
-    {
-        let nine = imapping.get(&9).unwrap();
-        let six = imapping.get(&6).unwrap();
-        let str = entry.signal.iter().filter(|x| x.len() == 6 && *x != six && *x != nine).next().unwrap().clone();
-        mapping.insert(str.clone(), 0);
-        imapping.insert(0, str);
-    }
-    // find five
-    {
-        let six = imapping.get(&6).unwrap();
-        let str = entry.signal.iter().filter(|x| x.len() == 5 &&  all_chars_in_a2(x, six)).next().unwrap().clone();
-        mapping.insert(str.clone(), 5);
-        imapping.insert(5, str);
-    }
-    // find three
-    {
-        let one = imapping.get(&1).unwrap();
-        let str = entry.signal.iter().filter(|x| x.len() == 5 && all_chars_in_a2(one, x)).next().unwrap().clone();
-        mapping.insert(str.clone(), 3);
-        imapping.insert(3, str);
-    }
-    // find two
-    {
-        let five = imapping.get(&5).unwrap();
-        let three = imapping.get(&3).unwrap();
-        let str = entry.signal.iter().filter(|x| x.len() == 5 && *x != five && *x != three).next().unwrap().clone();
-        mapping.insert(str.clone(), 2);
-        imapping.insert(2, str);
-    }
-
-    assert_eq!(mapping.len(), 10);
-
-    mapping
+    let assigned = (1u8 << wire) - 1;
+    for segment in 0..7 {
+        if domains[wire] & !used & (1 << segment) == 0 {
+            continue;
+        }
+        wiring[wire] = segment;
+        // patterns whose wires are all assigned must already be digits
+        let w = Wiring(*wiring);
+        let wire_mask = assigned | (1 << wire);
+        let ok = patterns
+            .iter()
+            .filter(|&&p| p & !wire_mask == 0)
+            .all(|&p| w.digit(p).is_some());
+        if ok {
+            search(patterns, domains, wire + 1, used | (1 << segment), wiring, found);
+        }
+    }
 }
 
-fn translate_number(mapping: &HashMap<String, i32>, number: &Vec<String>) -> i32 {
-    let mut result = 0;
+fn parse(path: &Path) -> Result<Vec<Entry>> {
+    let mut result = Vec::new();
+    let file = File::open(path)?;
+    let reader = BufReader::new(file);
 
-    for digit in number {
-        let digit = mapping.get(digit).unwrap();
-        result *= 10;
-        result += digit;
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        result.push(Entry::create(&line)?);
     }
 
-    result
+    Ok(result)
+}
+
+fn part_1(entries: &[Entry]) -> usize {
+    entries
+        .iter()
+        .flat_map(|e| &e.display)
+        .filter(|x| matches!(x.count_ones(), 2 | 3 | 4 | 7))
+        .count()
 }
 
-fn part_2(entries: &Vec<Entry>) -> i32 {
+fn part_2(entries: &[Entry]) -> u32 {
     let mut sum = 0;
-    for entry in entries {
-        let mapping = find_mapping(entry);
-        let number = translate_number(&mapping, &entry.display);
-        sum += number;
+    for (idx, entry) in entries.iter().enumerate() {
+        match entry.decode() {
+            Ok(number) => sum += number,
+            Err(e) => println!("Entry {}: {}", idx, e),
+        }
     }
     sum
 }
 
 fn main() -> Result<()> {
-    let entries = parse(&Path::new("input.txt"))?;
+    let entries = parse(Path::new("input.txt"))?;
+    if std::env::args().any(|a| a == "--wirings") {
+        for (idx, entry) in entries.iter().enumerate() {
+            match entry.wiring() {
+                Ok(w) => println!("Entry {}: {}", idx, w),
+                Err(e) => println!("Entry {}: {}", idx, e),
+            }
+        }
+    }
     let p1 = part_1(&entries);
     println!("Part 1: {}", p1);
     let p2 = part_2(&entries);
     println!("Part 2: {}", p2);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "be cfbegad cbdgef fgaecd cgeb fdcge agebfd fecdb fabcd edb | fdgacbe cefdb cefbgd gcbe
+edbfga begcd cbg gc gcadebf fbgde acbgfd abcde gfcbed gfec | fcgedb cgb dgebacf gc
+fgaebd cg bdaec gdafb agbcfd gdcbef bgcad gfac gcb cdgabef | cg cg fdcagb cbg
+fbegcd cbd adcefb dageb afcb bc aefdc ecdab fgdeca fcdbega | efabcd cedba gadfec cb
+aecbfdg fbg gf bafeg dbefa fcge gcbea fcaegb dgceab fcbdga | gecf egdcabf bgf bfgea
+fgeab ca afcebg bdacfeg cfaedg gcfdb baec bfadeg bafgc acf | gebdcfa ecba ca fadegcb
+dbcfg fgd bdegcaf fgec aegbdf ecdfab fbedc dacgb gdcebf gf | cefg dcbef fcge gbcadfe
+bdfegc cbegaf gecbf dfcage bdacg ed bedf ced adcbefg gebcd | ed bcgafe cdgba cbgef
+egadfb cdbfeg cegd fecab cgb gbdefca cg fgcdab egfdb bfceg | gbdfcae bgc cg cgb
+gcafb gcf dcaebfg ecagb gf abcdeg gaef cafbge fdbac fegbdc | fgae cfgab fg bagce";
+
+    fn entries(text: &str) -> Vec<Entry> {
+        text.lines().map(|l| Entry::create(l).unwrap()).collect()
+    }
+
+    #[test]
+    fn example_displays() {
+        let entries = entries(EXAMPLE);
+        assert_eq!(part_1(&entries), 26);
+        assert_eq!(part_2(&entries), 61229);
+        let values: Vec<u32> = entries.iter().map(|e| e.decode().unwrap()).collect();
+        assert_eq!(values, vec![8394, 9781, 1197, 9361, 4873, 8418, 4548, 1625, 8717, 4315]);
+    }
+
+    #[test]
+    fn recovers_the_wiring() {
+        let entry = Entry::create("acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf").unwrap();
+        assert_eq!(entry.decode(), Ok(5353));
+        assert_eq!(entry.wiring().unwrap().to_string(), "a->c b->f c->g d->a e->b f->d g->e");
+    }
+
+    #[test]
+    fn partial_observations() {
+        // every wiring that makes "ab" a 1 still reads it as 1
+        assert_eq!(Entry::create("ab | ab").unwrap().decode(), Ok(1));
+        assert!(matches!(Entry::create("abcde | abcde").unwrap().decode(), Err(WiringError::Ambiguous(_))));
+        assert!(matches!(Entry::create("ab | ab").unwrap().wiring(), Err(WiringError::Ambiguous(_))));
+        assert_eq!(Entry::create("ab cd | ab").unwrap().decode(), Err(WiringError::Inconsistent));
+        assert!(Entry::create("abh | ab").is_err());
+    }
+}
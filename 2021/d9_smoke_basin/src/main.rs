@@ -1,8 +1,8 @@
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::Path;
-use anyhow::Result;
+use anyhow::{bail, Result};
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
 struct GridElement {
@@ -21,7 +21,7 @@ struct Grid {
 impl Grid {
     fn new(g: Vec<Vec<i32>>) -> Self {
         let rows = g.len();
-        let cols = g.get(0).unwrap().len();
+        let cols = g[0].len();
 
         Self {
             g,
@@ -66,7 +66,7 @@ impl Grid {
 fn calculate_risk(basins_low: &Vec<GridElement>) -> i32 {
     let mut risk = 0;
     for ge in basins_low {
-        risk += (1 + ge.value);
+        risk += 1 + ge.value;
     }
     risk
 }
@@ -87,8 +87,10 @@ fn get_basins_low(grid: &Grid) -> Vec<GridElement> {
 }
 
 fn parse(path: &Path) -> Result<Grid> {
-    let file = File::open(path)?;
-    let reader = BufReader::new(file);
+    parse_reader(BufReader::new(File::open(path)?))
+}
+
+fn parse_reader(reader: impl BufRead) -> Result<Grid> {
     let mut grid = Vec::new();
 
     for line in reader.lines() {
@@ -102,47 +104,258 @@ fn parse(path: &Path) -> Result<Grid> {
         grid.push(row);
     }
 
+    if grid.is_empty() {
+        bail!("the heightmap has no rows");
+    }
     Ok(Grid::new(grid))
 }
 
-fn dfs(grid: &Grid, ge: &GridElement, seen: &mut HashSet<GridElement>) -> i32 {
-    seen.insert(ge.clone());
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Label {
+    Wall,
+    Basin(usize),
+    // drains into several basins at once
+    Divide(Vec<usize>),
+}
 
-    let mut count = 1;
-    for n in grid.neighbours(ge.i, ge.j) {
-        if n.value == 9 {
-            continue;
+impl Label {
+    fn basins(&self) -> &[usize] {
+        match self {
+            Label::Wall => &[],
+            Label::Basin(id) => std::slice::from_ref(id),
+            Label::Divide(ids) => ids,
         }
+    }
+}
 
-        if seen.contains(&n) {
-            continue;
-        }
+#[derive(Debug, Clone)]
+struct Basin {
+    low: (usize, usize),
+    size: usize,
+    // height at which water would spill out, and the water it holds up to there
+    spill: i32,
+    depth: i32,
+    volume: i32,
+}
 
-        count += dfs(grid, &n, seen);
+#[derive(Debug, Clone)]
+struct BasinMap {
+    labels: Vec<Vec<Label>>,
+    basins: Vec<Basin>,
+    adjacency: BTreeSet<(usize, usize)>,
+}
+
+impl BasinMap {
+    fn print(&self) {
+        const IDS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+        for row in &self.labels {
+            let line: String = row
+                .iter()
+                .map(|l| match l {
+                    Label::Wall => '#',
+                    Label::Basin(id) => IDS[id % IDS.len()] as char,
+                    Label::Divide(_) => '+',
+                })
+                .collect();
+            println!("{}", line);
+        }
     }
+}
 
-    count
+// plateau id of every cell, and the cells of every plateau
+type Plateaus = (Vec<Vec<usize>>, Vec<Vec<(usize, usize)>>);
+
+// connected cells of equal height, treated as one unit when draining
+fn plateaus(grid: &Grid, wall: Option<i32>) -> Plateaus {
+    let mut plateau_of = vec![vec![usize::MAX; grid.cols]; grid.rows];
+    let mut cells = Vec::new();
+    for i in 0..grid.rows {
+        for j in 0..grid.cols {
+            if plateau_of[i][j] != usize::MAX || Some(grid.g[i][j]) == wall {
+                continue;
+            }
+            let id = cells.len();
+            let mut members = vec![(i, j)];
+            plateau_of[i][j] = id;
+            let mut idx = 0;
+            while idx < members.len() {
+                let (ci, cj) = members[idx];
+                idx += 1;
+                for n in grid.neighbours(ci, cj) {
+                    if n.value == grid.g[i][j] && plateau_of[n.i][n.j] == usize::MAX {
+                        plateau_of[n.i][n.j] = id;
+                        members.push((n.i, n.j));
+                    }
+                }
+            }
+            cells.push(members);
+        }
+    }
+    (plateau_of, cells)
 }
 
-fn get_basins_sizes(grid: &Grid, basins_low: &Vec<GridElement>) -> Vec<i32> {
-    let mut seen_set = HashSet::new();
-    let mut res = Vec::new();
-    for bl in basins_low {
-       res.push(dfs(grid, bl, &mut seen_set));
+// every plateau drains along its steepest way down; a plateau with no way down
+// starts a basin, one whose steepest ways reach several basins is a divide
+fn label_basins(grid: &Grid, wall: Option<i32>) -> BasinMap {
+    let (plateau_of, cells) = plateaus(grid, wall);
+    let height = |p: usize| grid.g[cells[p][0].0][cells[p][0].1];
+
+    let mut order: Vec<usize> = (0..cells.len()).collect();
+    order.sort_by_key(|&p| height(p));
+
+    let mut plateau_label = vec![Label::Wall; cells.len()];
+    let mut basins = Vec::new();
+    for p in order {
+        let lower: Vec<usize> = cells[p]
+            .iter()
+            .flat_map(|&(i, j)| grid.neighbours(i, j))
+            .map(|n| plateau_of[n.i][n.j])
+            .filter(|&q| q != usize::MAX && height(q) < height(p))
+            .collect();
+
+        plateau_label[p] = match lower.iter().map(|&q| height(q)).min() {
+            None => {
+                basins.push(Basin { low: cells[p][0], size: 0, spill: 0, depth: 0, volume: 0 });
+                Label::Basin(basins.len() - 1)
+            }
+            Some(lowest) => {
+                let ids: BTreeSet<usize> = lower
+                    .iter()
+                    .filter(|&&q| height(q) == lowest)
+                    .flat_map(|&q| plateau_label[q].basins().to_vec())
+                    .collect();
+                if ids.len() == 1 {
+                    Label::Basin(*ids.first().unwrap())
+                } else {
+                    Label::Divide(ids.into_iter().collect())
+                }
+            }
+        };
     }
 
-    res.sort();
-    res
+    let mut labels = vec![vec![Label::Wall; grid.cols]; grid.rows];
+    for (p, members) in cells.iter().enumerate() {
+        for &(i, j) in members {
+            labels[i][j] = plateau_label[p].clone();
+        }
+    }
+
+    // lowest neighbouring height outside each basin, and basins touching around any cell
+    let mut spill = vec![i32::MAX; basins.len()];
+    let mut adjacency = BTreeSet::new();
+    for i in 0..grid.rows {
+        for j in 0..grid.cols {
+            let mut around: BTreeSet<usize> = labels[i][j].basins().iter().copied().collect();
+            for n in grid.neighbours(i, j) {
+                around.extend(labels[n.i][n.j].basins());
+                if let Label::Basin(id) = labels[i][j] {
+                    if labels[n.i][n.j] != labels[i][j] {
+                        spill[id] = spill[id].min(n.value);
+                    }
+                }
+            }
+            let around: Vec<usize> = around.into_iter().collect();
+            for (k, &a) in around.iter().enumerate() {
+                for &b in &around[k + 1..] {
+                    adjacency.insert((a, b));
+                }
+            }
+        }
+    }
+
+    for (id, basin) in basins.iter_mut().enumerate() {
+        let heights: Vec<i32> = labels
+            .iter()
+            .flatten()
+            .zip(grid.g.iter().flatten())
+            .filter(|(l, _)| **l == Label::Basin(id))
+            .map(|(_, h)| *h)
+            .collect();
+        let lowest = grid.g[basin.low.0][basin.low.1];
+        // a basin that never meets another cell fills up to its own rim
+        basin.spill = if spill[id] == i32::MAX {
+            heights.iter().copied().max().unwrap_or(lowest)
+        } else {
+            spill[id]
+        };
+        basin.size = heights.len();
+        basin.depth = basin.spill - lowest;
+        basin.volume = heights.iter().map(|h| (basin.spill - h).max(0)).sum();
+    }
+
+    BasinMap { labels, basins, adjacency }
 }
 
 fn main() -> Result<()> {
-    let grid = parse(&Path::new("input.txt"))?;
+    let grid = parse(Path::new("input.txt"))?;
     let basins_low = get_basins_low(&grid);
     println!("Part 1 {}", calculate_risk(&basins_low));
 
-    let basins_sizes = get_basins_sizes(&grid, &basins_low);
-    let p2 = basins_sizes.iter().rev().take(3).fold(1, |acc, e| acc * e);
+    let wall = if std::env::args().any(|a| a == "--no-walls") { None } else { Some(9) };
+    let map = label_basins(&grid, wall);
+    let mut basins_sizes: Vec<usize> = map.basins.iter().map(|b| b.size).collect();
+    basins_sizes.sort();
+    let p2: usize = basins_sizes.iter().rev().take(3).product();
     println!("Part 2 {}", p2);
 
+    if std::env::args().any(|a| a == "--map") {
+        map.print();
+        for (id, b) in map.basins.iter().enumerate() {
+            println!(
+                "basin {} low {:?} size {} depth {} volume {}",
+                id, b.low, b.size, b.depth, b.volume
+            );
+        }
+        for (a, b) in &map.adjacency {
+            println!("{} - {}", a, b);
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "2199943210
+3987894921
+9856789892
+8767896789
+9899965678";
+
+    fn grid(text: &str) -> Grid {
+        Grid::new(text.lines().map(|l| l.chars().map(|c| c.to_digit(10).unwrap() as i32).collect()).collect())
+    }
+
+    #[test]
+    fn example_basins() {
+        let grid = grid(EXAMPLE);
+        assert_eq!(calculate_risk(&get_basins_low(&grid)), 15);
+        let map = label_basins(&grid, Some(9));
+        let mut sizes: Vec<(usize, (usize, usize))> = map.basins.iter().map(|b| (b.size, b.low)).collect();
+        sizes.sort();
+        assert_eq!(sizes, vec![(3, (0, 1)), (9, (0, 9)), (9, (4, 6)), (14, (2, 2))]);
+    }
+
+    #[test]
+    fn divides_drain_both_ways() {
+        let map = label_basins(&grid("121"), None);
+        assert_eq!(map.labels[0], vec![Label::Basin(0), Label::Divide(vec![0, 1]), Label::Basin(1)]);
+        assert_eq!(map.adjacency, BTreeSet::from([(0, 1)]));
+        assert!(map.basins.iter().all(|b| b.size == 1));
+    }
+
+    #[test]
+    fn basins_fill_up_to_their_spill() {
+        let map = label_basins(&grid("9999\n9129\n9999"), Some(9));
+        let basin = &map.basins[0];
+        assert_eq!((map.basins.len(), basin.size, basin.spill, basin.depth, basin.volume), (1, 2, 9, 8, 15));
+    }
+
+    #[test]
+    fn rejects_empty_heightmap() {
+        assert_eq!(parse_reader("".as_bytes()).unwrap_err().to_string(), "the heightmap has no rows");
+        assert_eq!(parse_reader("21\n12\n".as_bytes()).unwrap().rows, 2);
+    }
+}
@@ -0,0 +1,337 @@
+// Reactor reboot: a list of steps turns axis-aligned boxes of cubes on or
+// off, in any number of dimensions, and we want the cubes left on. Three
+// interchangeable ways to count them live here.
+
+use std::str::FromStr;
+use anyhow::{anyhow, bail};
+
+// inclusive bounds, one pair per dimension
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cuboid {
+    pub ranges: Vec<(i64, i64)>,
+}
+
+impl Cuboid {
+    pub fn new(ranges: Vec<(i64, i64)>) -> Self {
+        Cuboid { ranges }
+    }
+
+    // the cube from -radius to radius in every dimension
+    pub fn centred(dims: usize, radius: i64) -> Self {
+        Cuboid { ranges: vec![(-radius, radius); dims] }
+    }
+
+    pub fn dims(&self) -> usize {
+        self.ranges.len()
+    }
+
+    pub fn volume(&self) -> i128 {
+        self.ranges.iter().map(|(lo, hi)| (hi - lo + 1) as i128).product()
+    }
+
+    // only called once `check_dims` has passed
+    pub(crate) fn intersect(&self, other: &Cuboid) -> Option<Cuboid> {
+        debug_assert_eq!(self.dims(), other.dims(), "cuboids of different dimensions");
+        let mut ranges = Vec::with_capacity(self.dims());
+        for ((a0, a1), (b0, b1)) in self.ranges.iter().zip(&other.ranges) {
+            let (lo, hi) = (*a0.max(b0), *a1.min(b1));
+            if lo > hi {
+                return None;
+            }
+            ranges.push((lo, hi));
+        }
+        Some(Cuboid { ranges })
+    }
+
+    // disjoint cuboids covering self but not other: in each dimension in
+    // turn, the slabs before and after the intersection are cut off
+    pub(crate) fn difference(&self, other: &Cuboid) -> Vec<Cuboid> {
+        let Some(intersection) = self.intersect(other) else {
+            return vec![self.clone()];
+        };
+
+        let mut diffs = Vec::new();
+        let mut rest = self.clone();
+        for (d, &(lo, hi)) in intersection.ranges.iter().enumerate() {
+            let (r0, r1) = rest.ranges[d];
+            if r0 < lo {
+                let mut slab = rest.clone();
+                slab.ranges[d] = (r0, lo - 1);
+                diffs.push(slab);
+            }
+            if hi < r1 {
+                let mut slab = rest.clone();
+                slab.ranges[d] = (hi + 1, r1);
+                diffs.push(slab);
+            }
+            rest.ranges[d] = (lo, hi);
+        }
+        diffs
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    On,
+    Off,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Step {
+    pub state: State,
+    pub cuboid: Cuboid,
+}
+
+impl FromStr for Step {
+    type Err = anyhow::Error;
+
+    // "on x=-20..26,y=-36..17,z=-47..7", with any number of axes
+    fn from_str(s: &str) -> anyhow::Result<Step> {
+        let (state, ranges) = s.trim().split_once(' ').ok_or_else(|| anyhow!("not a step: {:?}", s))?;
+        let state = match state {
+            "on" => State::On,
+            "off" => State::Off,
+            _ => bail!("invalid step type {:?}", state),
+        };
+        let mut bounds = Vec::new();
+        for range in ranges.split(',') {
+            let (_, range) = range.split_once('=').ok_or_else(|| anyhow!("invalid range {:?}", range))?;
+            let (lo, hi) = range.split_once("..").ok_or_else(|| anyhow!("invalid range {:?}", range))?;
+            let (lo, hi): (i64, i64) = (lo.parse()?, hi.parse()?);
+            if lo > hi {
+                bail!("empty range {}..{}", lo, hi);
+            }
+            bounds.push((lo, hi));
+        }
+        Ok(Step { state, cuboid: Cuboid::new(bounds) })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    // keeps the lit region as disjoint cuboids, carving every step out of them
+    Splitting,
+    // inclusion-exclusion: every step cancels its overlap with each
+    // signed cuboid seen so far
+    SignedIntersection,
+    // one axis at a time, between consecutive step boundaries, the last
+    // step covering a slab decides it
+    Compression,
+}
+
+impl Strategy {
+    pub fn all() -> [Strategy; 3] {
+        [Strategy::Splitting, Strategy::SignedIntersection, Strategy::Compression]
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Strategy> {
+        match s {
+            "split" | "splitting" => Ok(Strategy::Splitting),
+            "signed" => Ok(Strategy::SignedIntersection),
+            "compress" | "compression" => Ok(Strategy::Compression),
+            _ => bail!("unknown strategy {:?}, expected split, signed or compress", s),
+        }
+    }
+}
+
+fn splitting(steps: &[Step]) -> i128 {
+    let mut on: Vec<Cuboid> = Vec::new();
+    for step in steps {
+        on = on.iter().flat_map(|c| c.difference(&step.cuboid)).collect();
+        if step.state == State::On {
+            on.push(step.cuboid.clone());
+        }
+    }
+    on.iter().map(Cuboid::volume).sum()
+}
+
+fn signed_intersection(steps: &[Step]) -> i128 {
+    let mut terms: Vec<(Cuboid, i128)> = Vec::new();
+    for step in steps {
+        let mut new_terms: Vec<(Cuboid, i128)> = terms
+            .iter()
+            .filter_map(|(c, sign)| step.cuboid.intersect(c).map(|i| (i, -sign)))
+            .collect();
+        if step.state == State::On {
+            new_terms.push((step.cuboid.clone(), 1));
+        }
+        terms.extend(new_terms);
+    }
+    terms.iter().map(|(c, sign)| c.volume() * sign).sum()
+}
+
+fn compression(steps: &[&Step], dim: usize) -> i128 {
+    if !steps.iter().any(|s| s.state == State::On) {
+        return 0;
+    }
+    let mut bounds: Vec<i64> = steps.iter().flat_map(|s| [s.cuboid.ranges[dim].0, s.cuboid.ranges[dim].1 + 1]).collect();
+    bounds.sort();
+    bounds.dedup();
+
+    let last = dim + 1 == steps[0].cuboid.dims();
+    let mut total = 0;
+    for w in bounds.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        let covering: Vec<&Step> = steps
+            .iter()
+            .filter(|s| s.cuboid.ranges[dim].0 <= a && b - 1 <= s.cuboid.ranges[dim].1)
+            .copied()
+            .collect();
+        let width = (b - a) as i128;
+        if last {
+            if covering.last().is_some_and(|s| s.state == State::On) {
+                total += width;
+            }
+        } else {
+            total += width * compression(&covering, dim + 1);
+        }
+    }
+    total
+}
+
+// one step per non-blank line, all with the same number of axes
+pub fn parse_steps(text: &str) -> anyhow::Result<Vec<Step>> {
+    let mut steps = Vec::new();
+    for (n, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+        let step: Step = line.parse().map_err(|e| anyhow!("line {}: {}", n + 1, e))?;
+        steps.push(step);
+    }
+    check_dims(&steps, None)?;
+    Ok(steps)
+}
+
+// every step (and the clip) spans the same number of axes
+fn check_dims(steps: &[Step], clip: Option<&Cuboid>) -> anyhow::Result<()> {
+    let Some(dims) = clip.map(Cuboid::dims).or(steps.first().map(|s| s.cuboid.dims())) else {
+        return Ok(());
+    };
+    if let Some((n, step)) = steps.iter().enumerate().find(|(_, s)| s.cuboid.dims() != dims) {
+        bail!("step {} has {} axes, expected {}", n + 1, step.cuboid.dims(), dims);
+    }
+    Ok(())
+}
+
+// the cubes left on after all steps, counting only those inside `clip`
+pub fn reboot(steps: &[Step], strategy: Strategy, clip: Option<&Cuboid>) -> anyhow::Result<i128> {
+    check_dims(steps, clip)?;
+    Ok(count(steps, strategy, clip))
+}
+
+fn count(steps: &[Step], strategy: Strategy, clip: Option<&Cuboid>) -> i128 {
+    let steps: Vec<Step> = match clip {
+        Some(clip) => steps
+            .iter()
+            .filter_map(|s| s.cuboid.intersect(clip).map(|cuboid| Step { state: s.state, cuboid }))
+            .collect(),
+        None => steps.to_vec(),
+    };
+    if steps.is_empty() {
+        return 0;
+    }
+    match strategy {
+        Strategy::Splitting => splitting(&steps),
+        Strategy::SignedIntersection => signed_intersection(&steps),
+        Strategy::Compression => compression(&steps.iter().collect::<Vec<_>>(), 0),
+    }
+}
+
+// runs every strategy; the count if they all agree
+pub fn cross_check(steps: &[Step], clip: Option<&Cuboid>) -> anyhow::Result<i128> {
+    check_dims(steps, clip)?;
+    let counts: Vec<(Strategy, i128)> = Strategy::all().into_iter().map(|s| (s, count(steps, s, clip))).collect();
+    if counts.iter().any(|(_, c)| *c != counts[0].1) {
+        let counts: Vec<String> = counts.iter().map(|(s, c)| format!("{:?} {}", s, c)).collect();
+        bail!("strategies disagree: {}", counts.join(", "));
+    }
+    Ok(counts[0].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL: &str = "on x=10..12,y=10..12,z=10..12
+on x=11..13,y=11..13,z=11..13
+off x=9..11,y=9..11,z=9..11
+on x=10..10,y=10..10,z=10..10";
+
+    #[test]
+    fn small_example() {
+        let steps = parse_steps(SMALL).unwrap();
+        assert_eq!(cross_check(&steps, None).unwrap(), 39);
+        assert_eq!(cross_check(&steps, Some(&Cuboid::centred(3, 10))).unwrap(), 1);
+    }
+
+    // every cube of a small box, one at a time
+    fn brute_force(steps: &[Step], space: &Cuboid) -> i128 {
+        let mut count = 0;
+        let mut point: Vec<i64> = space.ranges.iter().map(|r| r.0).collect();
+        'points: loop {
+            let last = steps.iter().rev().find(|s| s.cuboid.ranges.iter().zip(&point).all(|((lo, hi), p)| lo <= p && p <= hi));
+            if last.is_some_and(|s| s.state == State::On) {
+                count += 1;
+            }
+            for (d, p) in point.iter_mut().enumerate() {
+                if *p < space.ranges[d].1 {
+                    *p += 1;
+                    continue 'points;
+                }
+                *p = space.ranges[d].0;
+            }
+            return count;
+        }
+    }
+
+    #[test]
+    fn strategies_match_brute_force_in_any_dimension() {
+        let mut seed = 7u64;
+        let mut next = |n: i64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            ((seed >> 33) as i64).rem_euclid(n)
+        };
+        for dims in 1..=4 {
+            for _ in 0..20 {
+                let steps: Vec<Step> = (0..next(8) + 1)
+                    .map(|_| {
+                        let ranges = (0..dims)
+                            .map(|_| {
+                                let lo = next(8) - 4;
+                                (lo, lo + next(5))
+                            })
+                            .collect();
+                        Step { state: if next(3) == 0 { State::Off } else { State::On }, cuboid: Cuboid::new(ranges) }
+                    })
+                    .collect();
+                let space = Cuboid::centred(dims, 8);
+                assert_eq!(cross_check(&steps, None).unwrap(), brute_force(&steps, &space), "{:?}", steps);
+                let clip = Cuboid::centred(dims, 2);
+                assert_eq!(cross_check(&steps, Some(&clip)).unwrap(), brute_force(&steps, &clip), "{:?}", steps);
+            }
+        }
+    }
+
+    #[test]
+    fn mixed_dimensions_are_errors() {
+        assert!(parse_steps("on x=1..2,y=1..2\non x=1..2").is_err());
+        let steps = vec![
+            Step { state: State::On, cuboid: Cuboid::new(vec![(0, 1), (0, 1)]) },
+            Step { state: State::On, cuboid: Cuboid::new(vec![(0, 1)]) },
+        ];
+        for strategy in Strategy::all() {
+            assert!(reboot(&steps, strategy, None).is_err());
+        }
+        assert!(cross_check(&steps, None).is_err());
+        assert!(reboot(&steps[..1], Strategy::Splitting, Some(&Cuboid::centred(3, 50))).is_err());
+    }
+
+    #[test]
+    fn bad_steps() {
+        assert!("toggle x=1..2".parse::<Step>().is_err());
+        assert!("on x=2..1".parse::<Step>().is_err());
+        assert!("on x=a..1".parse::<Step>().is_err());
+    }
+}
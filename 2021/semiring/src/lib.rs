@@ -0,0 +1,160 @@
+// Counting with matrix powers: a semiring to count in (exact big integers
+// or residues mod m) and the few matrix operations the puzzles need.
+
+use num_bigint::BigUint;
+
+// values carry what they need to make their zero and one, so a residue
+// knows its modulus
+pub trait Semiring: Clone {
+    fn zero(&self) -> Self;
+    fn one(&self) -> Self;
+    fn add(&self, other: &Self) -> Self;
+    fn mul(&self, other: &Self) -> Self;
+    fn is_zero(&self) -> bool;
+}
+
+impl Semiring for BigUint {
+    fn zero(&self) -> Self {
+        BigUint::ZERO
+    }
+
+    fn one(&self) -> Self {
+        BigUint::from(1u32)
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == BigUint::ZERO
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modular {
+    pub value: u64,
+    pub m: u64,
+}
+
+impl Modular {
+    // panics on a zero modulus; callers check user input first
+    pub fn new(value: u64, m: u64) -> Self {
+        Modular { value: value % m, m }
+    }
+}
+
+impl Semiring for Modular {
+    fn zero(&self) -> Self {
+        Modular::new(0, self.m)
+    }
+
+    fn one(&self) -> Self {
+        Modular::new(1, self.m)
+    }
+
+    fn add(&self, other: &Self) -> Self {
+        Modular { value: ((self.value as u128 + other.value as u128) % self.m as u128) as u64, m: self.m }
+    }
+
+    fn mul(&self, other: &Self) -> Self {
+        Modular { value: ((self.value as u128 * other.value as u128) % self.m as u128) as u64, m: self.m }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.value == 0
+    }
+}
+
+pub type Matrix<T> = Vec<Vec<T>>;
+
+pub fn identity<T: Semiring>(n: usize, unit: &T) -> Matrix<T> {
+    (0..n).map(|i| (0..n).map(|j| if i == j { unit.one() } else { unit.zero() }).collect()).collect()
+}
+
+pub fn mat_mul<T: Semiring>(a: &Matrix<T>, b: &Matrix<T>, unit: &T) -> Matrix<T> {
+    let n = a.len();
+    let mut r = vec![vec![unit.zero(); n]; n];
+    for i in 0..n {
+        for k in 0..n {
+            // transition matrices are mostly zeros
+            if a[i][k].is_zero() {
+                continue;
+            }
+            for j in 0..n {
+                r[i][j] = r[i][j].add(&a[i][k].mul(&b[k][j]));
+            }
+        }
+    }
+    r
+}
+
+pub fn mat_vec<T: Semiring>(a: &Matrix<T>, v: &[T], unit: &T) -> Vec<T> {
+    a.iter()
+        .map(|row| row.iter().zip(v).fold(unit.zero(), |acc, (x, y)| acc.add(&x.mul(y))))
+        .collect()
+}
+
+pub fn mat_pow<T: Semiring>(mut m: Matrix<T>, mut exp: u64, unit: &T) -> Matrix<T> {
+    let mut r = identity(m.len(), unit);
+    while exp > 0 {
+        if exp & 1 == 1 {
+            r = mat_mul(&r, &m, unit);
+        }
+        exp >>= 1;
+        if exp > 0 {
+            m = mat_mul(&m, &m, unit);
+        }
+    }
+    r
+}
+
+// m^exp v, without ever forming m^exp
+pub fn mat_pow_vec<T: Semiring>(mut m: Matrix<T>, mut exp: u64, mut v: Vec<T>, unit: &T) -> Vec<T> {
+    while exp > 0 {
+        if exp & 1 == 1 {
+            v = mat_vec(&m, &v, unit);
+        }
+        exp >>= 1;
+        if exp > 0 {
+            m = mat_mul(&m, &m, unit);
+        }
+    }
+    v
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fibonacci<T: Semiring>(n: u64, unit: &T) -> T {
+        let m = vec![vec![unit.one(), unit.one()], vec![unit.one(), unit.zero()]];
+        mat_pow(m, n, unit)[0][1].clone()
+    }
+
+    #[test]
+    fn exact_and_modular_agree() {
+        assert_eq!(fibonacci(90, &BigUint::ZERO), BigUint::from(2880067194370816120u64));
+        assert_eq!(fibonacci(90, &Modular::new(0, 1_000_000_007)).value, 2880067194370816120 % 1_000_000_007);
+        assert_eq!(fibonacci(0, &BigUint::ZERO), BigUint::ZERO);
+    }
+
+    #[test]
+    fn power_times_vector() {
+        let unit = Modular::new(0, 97);
+        let m = vec![vec![Modular::new(2, 97), Modular::new(1, 97)], vec![Modular::new(0, 97), Modular::new(3, 97)]];
+        let v = vec![Modular::new(5, 97), Modular::new(7, 97)];
+        for exp in 0..20 {
+            assert_eq!(mat_pow_vec(m.clone(), exp, v.clone(), &unit), mat_vec(&mat_pow(m.clone(), exp, &unit), &v, &unit));
+        }
+    }
+
+    #[test]
+    fn modulus_one() {
+        assert_eq!(Modular::new(5, 1).one().value, 0);
+    }
+}
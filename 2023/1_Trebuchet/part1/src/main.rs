@@ -1,81 +1,161 @@
-use std::fs::File;
-use std::io::{self, BufRead};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
 use std::path::Path;
+use aho_corasick::{AhoCorasick, MatchKind};
+use anyhow::{anyhow, bail, Result};
 
-fn chars_to_digit(s: &str) -> Vec<(usize, u32)> {
-    let replacements = vec![("one", 1), ("two", 2), ("three", 3), ("four", 4), ("five", 5),
+const ENGLISH: [(&str, u32); 9] = [("one", 1), ("two", 2), ("three", 3), ("four", 4), ("five", 5),
     ("six", 6), ("seven", 7), ("eight", 8), ("nine", 9)];
 
-    let mut result = Vec::<(usize, u32)>::new();
-    for (pat, digit) in replacements {
-        for (idx, _) in s.match_indices(pat) {
-            result.push((idx, digit));
+// the tokens that stand for a digit, searched for all at once
+struct Vocabulary {
+    automaton: AhoCorasick,
+    values: Vec<u32>,
+}
+
+impl Vocabulary {
+    fn new(tokens: &[(String, u32)]) -> Result<Vocabulary> {
+        if let Some((token, value)) = tokens.iter().find(|(t, v)| t.is_empty() || *v > 9) {
+            bail!("{:?} can't stand for {}, tokens must be non-empty and digits 0-9", token, value);
         }
+        // standard semantics, so overlapping matches like "eightwo" all show up
+        let automaton = AhoCorasick::builder()
+            .match_kind(MatchKind::Standard)
+            .build(tokens.iter().map(|(t, _)| t))?;
+        Ok(Vocabulary { automaton, values: tokens.iter().map(|(_, v)| *v).collect() })
+    }
+
+    fn numerals() -> Vec<(String, u32)> {
+        (0..10).map(|d| (d.to_string(), d)).collect()
     }
 
-    result
+    fn english() -> Vec<(String, u32)> {
+        ENGLISH.iter().map(|(w, d)| (w.to_string(), *d)).collect()
+    }
+
+    // one "token digit" pair per line, e.g. "drei 3"
+    fn load(path: &Path) -> Result<Vec<(String, u32)>> {
+        let mut tokens = Vec::new();
+        for line in fs::read_to_string(path)?.lines().filter(|l| !l.trim().is_empty()) {
+            let (token, digit) = line.trim().rsplit_once(char::is_whitespace).ok_or_else(|| anyhow!("expected \"token digit\", got {:?}", line))?;
+            tokens.push((token.trim().to_string(), digit.parse()?));
+        }
+        Ok(tokens)
+    }
 }
 
-fn to_indices_and_digits(s: &str) -> Vec<(usize, u32)> {
-    s.char_indices().flat_map(|(idx, c)| { 
-        c.to_digit(10).map(|digit| (idx, digit))
-    })
-    .collect()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Found {
+    digit: u32,
+    // byte range of the token in the line
+    start: usize,
+    end: usize,
 }
 
+// the token starting first and the one starting last; at the same start
+// the longer token wins
+fn extract(line: &str, vocabulary: &Vocabulary) -> Option<(Found, Found)> {
+    let mut found = vocabulary.automaton.find_overlapping_iter(line).map(|m| Found {
+        digit: vocabulary.values[m.pattern().as_usize()],
+        start: m.start(),
+        end: m.end(),
+    });
+    let f = found.next()?;
+    Some(found.fold((f, f), |(first, last), f| {
+        let first = if (f.start, first.end) < (first.start, f.end) { f } else { first };
+        let last = if (last.start, last.end) < (f.start, f.end) { f } else { last };
+        (first, last)
+    }))
+}
+
+fn calibration_value(line: &str, vocabulary: &Vocabulary) -> u32 {
+    extract(line, vocabulary).map_or(0, |(first, last)| first.digit * 10 + last.digit)
+}
 
-fn part1() -> std::io::Result<()> {
-    let path = Path::new("input.txt");
+fn parse(path: &Path) -> Result<Vec<String>> {
     let file = File::open(path)?;
-    let reader = io::BufReader::new(file);
-    
-    let sum: u32 = reader.lines()
-    .collect::<Result<Vec<String>,_>>()?
-    .iter()
-    .map(|s| to_indices_and_digits(s.as_str()))
-    .map(|v| {
-        let min = v.iter().min_by_key(|(idx, _)| idx).map(|(_, digit)| *digit);
-        let max = v.iter().max_by_key(|(idx, _)| idx).map(|(_, digit)| *digit);
-        match (min, max) {
-            (Some(d1), Some(d2)) => d1 * 10 + d2,
-            (_, _) => 0
+    let reader = BufReader::new(file);
+    Ok(reader.lines().collect::<Result<_, _>>()?)
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let words = match args.iter().position(|a| a == "--words") {
+        Some(i) => Vocabulary::load(Path::new(args.get(i + 1).ok_or_else(|| anyhow!("--words needs a file"))?))?,
+        None => Vocabulary::english(),
+    };
+
+    let lines = parse(Path::new("input.txt"))?;
+    let numerals = Vocabulary::new(&Vocabulary::numerals())?;
+    let spelled = Vocabulary::new(&[Vocabulary::numerals(), words].concat())?;
+
+    if args.iter().any(|a| a == "--positions") {
+        for line in &lines {
+            match extract(line, &spelled) {
+                Some((first, last)) => println!(
+                    "{}: {} at {}..{}, {} at {}..{}",
+                    line, first.digit, first.start, first.end, last.digit, last.start, last.end
+                ),
+                None => println!("{}: no digits", line),
+            }
         }
-    })
-    .sum();
+    }
 
-    println!("Sum {}", sum);
+    let part1: u32 = lines.iter().map(|l| calibration_value(l, &numerals)).sum();
+    let part2: u32 = lines.iter().map(|l| calibration_value(l, &spelled)).sum();
+    println!("Part 1: {}", part1);
+    println!("Part 2: {}", part2);
     Ok(())
 }
 
-fn part2() -> std::io::Result<()> {
-    let path = Path::new("input.txt");
-    let file = File::open(path)?;
-    let reader = io::BufReader::new(file);
-    
-    let sum: u32 = reader.lines()
-    .collect::<Result<Vec<String>,_>>()?
-    .iter()
-    .map(|s| (s, to_indices_and_digits(s.as_str()).into_iter()))
-    .map(|(s, iter)| {
-        let v2 = chars_to_digit(s.as_str());
-        iter.chain(v2.into_iter())
-    })
-    .map(|v| {
-        let min = v.clone().min_by_key(|(idx, _)| *idx).map(|(_, digit)| digit);
-        let max = v.max_by_key(|(idx, _)| *idx).map(|(_, digit)| digit);
-        match (min, max) {
-            (Some(d1), Some(d2)) => d1 * 10 + d2,
-            (_, _) => 0
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sum(text: &str, vocabulary: &Vocabulary) -> u32 {
+        text.lines().map(|l| calibration_value(l, vocabulary)).sum()
+    }
+
+    fn spelled() -> Vocabulary {
+        Vocabulary::new(&[Vocabulary::numerals(), Vocabulary::english()].concat()).unwrap()
+    }
+
+    #[test]
+    fn examples() {
+        let numerals = Vocabulary::new(&Vocabulary::numerals()).unwrap();
+        assert_eq!(sum("1abc2\npqr3stu8vwx\na1b2c3d4e5f\ntreb7uchet", &numerals), 142);
+        let text = "two1nine\neightwothree\nabcone2threexyz\nxtwone3four\n4nineeightseven2\nzoneight234\n7pqrstsixteen";
+        assert_eq!(sum(text, &spelled()), 281);
+    }
+
+    #[test]
+    fn overlapping_words() {
+        let spelled = spelled();
+        for (line, value) in [("twone", 21), ("eightwo", 82), ("oneight", 18), ("sevenine", 79), ("eighthree", 83), ("nineight", 98)] {
+            assert_eq!(calibration_value(line, &spelled), value, "{}", line);
         }
-    })
-    .sum();
+        let (first, last) = extract("xtwonex", &spelled).unwrap();
+        assert_eq!((first, last), (Found { digit: 2, start: 1, end: 4 }, Found { digit: 1, start: 3, end: 6 }));
+        assert_eq!(extract("abc", &spelled), None);
+        assert_eq!(calibration_value("abc", &spelled), 0);
+    }
 
-    println!("Sum {}", sum);
-    Ok(())
-}
+    #[test]
+    fn longer_token_wins_at_the_same_start() {
+        let vocabulary = Vocabulary::new(&[("thirty".to_string(), 3), ("thirtyone".to_string(), 1)]).unwrap();
+        assert_eq!(calibration_value("thirtyone", &vocabulary), 11);
+    }
 
-fn main() -> std::io::Result<()> {
-    part1()?;
-    part2()?;
-    Ok(())
+    #[test]
+    fn loaded_vocabularies() {
+        let path = std::env::temp_dir().join(format!("trebuchet_words_{}.txt", std::process::id()));
+        fs::write(&path, "eins 1\nzwei 2\n\ndrei 3\n").unwrap();
+        let words = Vocabulary::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(words[2], ("drei".to_string(), 3));
+        let german = Vocabulary::new(&[Vocabulary::numerals(), words].concat()).unwrap();
+        assert_eq!(calibration_value("xdreinszweix", &german), 32);
+        assert!(Vocabulary::new(&[("ten".to_string(), 10)]).is_err());
+        assert!(Vocabulary::new(&[(String::new(), 1)]).is_err());
+    }
 }
@@ -0,0 +1,448 @@
+use anyhow::{bail, Context, Result};
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+
+const DEFAULT_ENDPOINT: &str = "https://adventofcode.com";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Right,
+    Wrong,
+    TooHigh,
+    TooLow,
+    Wait(u64),
+    AlreadySolved,
+    Unknown,
+}
+
+impl Verdict {
+    // `text` is the response page as given by `article_text`
+    fn from_text(text: &str) -> Verdict {
+        if text.contains("That's the right answer") {
+            Verdict::Right
+        } else if text.contains("answer too recently") {
+            Verdict::Wait(parse_wait_seconds(text).unwrap_or(60))
+        } else if text.contains("your answer is too high") {
+            Verdict::TooHigh
+        } else if text.contains("your answer is too low") {
+            Verdict::TooLow
+        } else if text.contains("That's not the right answer") {
+            Verdict::Wrong
+        } else if text.contains("solving the right level") {
+            Verdict::AlreadySolved
+        } else {
+            Verdict::Unknown
+        }
+    }
+
+    // only verdicts about the answer itself are worth remembering
+    fn is_final(&self) -> bool {
+        matches!(self, Verdict::Right | Verdict::Wrong | Verdict::TooHigh | Verdict::TooLow)
+    }
+
+    fn tag(&self) -> &'static str {
+        match self {
+            Verdict::Right => "right",
+            Verdict::Wrong => "wrong",
+            Verdict::TooHigh => "too_high",
+            Verdict::TooLow => "too_low",
+            Verdict::Wait(_) => "wait",
+            Verdict::AlreadySolved => "already_solved",
+            Verdict::Unknown => "unknown",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Verdict> {
+        match tag {
+            "right" => Some(Verdict::Right),
+            "wrong" => Some(Verdict::Wrong),
+            "too_high" => Some(Verdict::TooHigh),
+            "too_low" => Some(Verdict::TooLow),
+            _ => None,
+        }
+    }
+}
+
+fn article_text(body: &str) -> String {
+    let article = match (body.find("<article"), body.find("</article>")) {
+        (Some(start), Some(end)) if start < end => &body[start..end],
+        _ => body,
+    };
+
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in article.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// "You have 1m 5s left to wait." / "You have 38s left to wait."
+fn parse_wait_seconds(text: &str) -> Option<u64> {
+    let start = text.find("You have ")? + "You have ".len();
+    let end = start + text[start..].find(" left to wait")?;
+    let mut seconds = 0;
+    for token in text[start..end].split_whitespace() {
+        let (nr, scale) = [("h", 3600), ("m", 60), ("s", 1)]
+            .iter()
+            .find_map(|(unit, scale)| token.strip_suffix(unit).map(|nr| (nr, scale)))?;
+        seconds += nr.parse::<u64>().ok()? * scale;
+    }
+    Some(seconds)
+}
+
+#[derive(Debug, Clone)]
+struct Attempt {
+    year: u32,
+    day: u32,
+    part: u32,
+    answer: String,
+    verdict: Verdict,
+}
+
+struct AttemptLog {
+    path: PathBuf,
+    attempts: Vec<Attempt>,
+}
+
+impl AttemptLog {
+    fn load(path: PathBuf) -> Result<Self> {
+        let mut attempts = Vec::new();
+        if path.exists() {
+            let reader = BufReader::new(fs::File::open(&path)?);
+            for line in reader.lines() {
+                let line = line?;
+                let fields: Vec<&str> = line.splitn(5, '\t').collect();
+                if fields.len() != 5 {
+                    continue;
+                }
+                let Some(verdict) = Verdict::from_tag(fields[3]) else {
+                    continue;
+                };
+                attempts.push(Attempt {
+                    year: fields[0].parse()?,
+                    day: fields[1].parse()?,
+                    part: fields[2].parse()?,
+                    answer: fields[4].to_string(),
+                    verdict,
+                });
+            }
+        }
+        Ok(AttemptLog { path, attempts })
+    }
+
+    fn record(&mut self, attempt: Attempt) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(
+            file,
+            "{}\t{}\t{}\t{}\t{}",
+            attempt.year,
+            attempt.day,
+            attempt.part,
+            attempt.verdict.tag(),
+            attempt.answer
+        )?;
+        self.attempts.push(attempt);
+        Ok(())
+    }
+
+    fn for_puzzle(&self, year: u32, day: u32, part: u32) -> impl Iterator<Item = &Attempt> {
+        self.attempts
+            .iter()
+            .filter(move |a| a.year == year && a.day == day && a.part == part)
+    }
+
+    // the tightest (lower, upper) exclusive bounds learned from "too low"/"too high"
+    fn bounds(&self, year: u32, day: u32, part: u32) -> (Option<i128>, Option<i128>) {
+        let mut low = None;
+        let mut high = None;
+        for attempt in self.for_puzzle(year, day, part) {
+            let Ok(value) = attempt.answer.parse::<i128>() else {
+                continue;
+            };
+            match attempt.verdict {
+                Verdict::TooLow => low = low.max(Some(value)),
+                Verdict::TooHigh => high = Some(high.map_or(value, |h: i128| h.min(value))),
+                _ => {}
+            }
+        }
+        (low, high)
+    }
+
+    // a verdict we can give without asking the server, if any
+    fn known_verdict(&self, year: u32, day: u32, part: u32, answer: &str) -> Option<Verdict> {
+        let attempts: Vec<&Attempt> = self.for_puzzle(year, day, part).collect();
+        if let Some(a) = attempts.iter().find(|a| a.answer == answer) {
+            return Some(a.verdict);
+        }
+        if attempts.iter().any(|a| a.verdict == Verdict::Right) {
+            return Some(Verdict::AlreadySolved);
+        }
+
+        let value = answer.parse::<i128>().ok()?;
+        match self.bounds(year, day, part) {
+            (Some(low), _) if value <= low => Some(Verdict::TooLow),
+            (_, Some(high)) if value >= high => Some(Verdict::TooHigh),
+            _ => None,
+        }
+    }
+}
+
+struct Config {
+    endpoint: String,
+    session: Option<String>,
+    cache: PathBuf,
+}
+
+impl Config {
+    fn from_env() -> Result<Self> {
+        Self::from_vars(|name| std::env::var(name).ok())
+    }
+
+    // `var` looks up AOC_ENDPOINT, AOC_SESSION, AOC_CACHE and HOME
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let endpoint = var("AOC_ENDPOINT").unwrap_or_else(|| DEFAULT_ENDPOINT.to_string());
+        let session = var("AOC_SESSION");
+        let cache = match var("AOC_CACHE") {
+            Some(p) => PathBuf::from(p),
+            None => {
+                let home = var("HOME").context("HOME not set, use AOC_CACHE")?;
+                PathBuf::from(home).join(".cache/aoc/attempts.tsv")
+            }
+        };
+        Ok(Config { endpoint: endpoint.trim_end_matches('/').to_string(), session, cache })
+    }
+}
+
+fn post_answer(config: &Config, year: u32, day: u32, part: u32, answer: &str) -> Result<String> {
+    let session = config
+        .session
+        .as_ref()
+        .context("AOC_SESSION must hold the adventofcode.com session cookie")?;
+    let url = format!("{}/{}/day/{}/answer", config.endpoint, year, day);
+    let response = ureq::post(&url)
+        .set("Cookie", &format!("session={}", session))
+        .set("User-Agent", "github.com/BogdanArdelean/aoc submit")
+        .send_form(&[("level", &part.to_string()), ("answer", answer)])
+        .with_context(|| format!("POST {}", url))?;
+    Ok(response.into_string()?)
+}
+
+fn submit(config: &Config, year: u32, day: u32, part: u32, answer: &str) -> Result<Verdict> {
+    let mut log = AttemptLog::load(config.cache.clone())?;
+    if let Some(verdict) = log.known_verdict(year, day, part, answer) {
+        println!("Not sent, known from {}: {}", config.cache.display(), verdict.tag());
+        return Ok(verdict);
+    }
+
+    let body = post_answer(config, year, day, part, answer)?;
+    let text = article_text(&body);
+    let verdict = Verdict::from_text(&text);
+    println!("{}", text);
+    if verdict.is_final() {
+        log.record(Attempt { year, day, part, answer: answer.to_string(), verdict })?;
+    }
+    Ok(verdict)
+}
+
+fn bounds_hint(bounds: (Option<i128>, Option<i128>)) -> Option<String> {
+    match bounds {
+        (None, None) => None,
+        (low, high) => Some(format!(
+            "Hint: answer in ({}, {})",
+            low.map_or("-inf".to_string(), |v| v.to_string()),
+            high.map_or("+inf".to_string(), |v| v.to_string())
+        )),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: aoc submit <year> <day> <part> [answer]");
+    eprintln!("  the answer is read from stdin when omitted");
+    eprintln!("  env: AOC_SESSION (cookie), AOC_ENDPOINT (default {}), AOC_CACHE", DEFAULT_ENDPOINT);
+    std::process::exit(2);
+}
+
+fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.len() < 4 || args[0] != "submit" {
+        usage();
+    }
+
+    let year: u32 = args[1].parse().context("year")?;
+    let day: u32 = args[2].parse().context("day")?;
+    let part: u32 = args[3].parse().context("part")?;
+    if !(1..=25).contains(&day) || !(1..=2).contains(&part) {
+        bail!("day must be 1..=25 and part 1 or 2");
+    }
+
+    let answer = match args.get(4) {
+        Some(a) => a.trim().to_string(),
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input)?;
+            input.lines().rev().find(|l| !l.trim().is_empty()).unwrap_or("").trim().to_string()
+        }
+    };
+    if answer.is_empty() {
+        bail!("empty answer");
+    }
+
+    let config = Config::from_env()?;
+    let verdict = submit(&config, year, day, part, &answer)?;
+    if let Some(hint) = bounds_hint(AttemptLog::load(config.cache.clone())?.bounds(year, day, part)) {
+        println!("{}", hint);
+    }
+    match verdict {
+        Verdict::Right => Ok(()),
+        Verdict::Wait(s) => {
+            eprintln!("rate limited, retry in {}s", s);
+            std::process::exit(1);
+        }
+        v => {
+            eprintln!("answer {} was {}", answer, v.tag());
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    const RIGHT: &str = "<main><article><p>That's the right answer!  You are one gold star closer to saving Christmas.</p></article></main>";
+    const TOO_HIGH: &str = "<article><p>That's not the right answer; your answer is too high.  Please wait one minute before trying again.</p></article>";
+    const TOO_LOW: &str = "<article><p>That's not the right answer; your answer is too low.  Please wait one minute before trying again.</p></article>";
+    const WRONG: &str = "<article><p>That's not the right answer.  If you're stuck, make sure you're using the full input data.</p></article>";
+    const WAIT: &str = "<article><p>You gave an answer too recently; you have to wait after submitting an answer before trying again.  You have 1m 5s left to wait. </p></article>";
+
+    // a loopback server answering each request with the next canned page;
+    // the form bodies it received are kept for inspection
+    struct Stub {
+        endpoint: String,
+        received: Arc<Mutex<Vec<String>>>,
+    }
+
+    fn stub(pages: &[&'static str]) -> Stub {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = received.clone();
+        let pages = pages.to_vec();
+        std::thread::spawn(move || {
+            for page in pages {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                log.lock().unwrap().push(String::from_utf8(body).unwrap());
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    page.len(),
+                    page
+                )
+                .unwrap();
+            }
+        });
+        Stub { endpoint, received }
+    }
+
+    fn config(stub: &Stub, test: &str) -> Config {
+        let dir = std::env::temp_dir().join(format!("aoc-test-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = dir.join("attempts.tsv").to_string_lossy().into_owned();
+        let endpoint = stub.endpoint.clone();
+        Config::from_vars(|name| match name {
+            "AOC_ENDPOINT" => Some(endpoint.clone()),
+            "AOC_SESSION" => Some("test-session".to_string()),
+            "AOC_CACHE" => Some(cache.clone()),
+            _ => None,
+        })
+        .unwrap()
+    }
+
+    fn sent(stub: &Stub) -> Vec<String> {
+        stub.received.lock().unwrap().clone()
+    }
+
+    #[test]
+    fn correct_answer() {
+        let stub = stub(&[RIGHT]);
+        let config = config(&stub, "correct");
+        assert_eq!(submit(&config, 2021, 7, 1, "42").unwrap(), Verdict::Right);
+        assert_eq!(sent(&stub), ["level=1&answer=42"]);
+
+        // once solved, any other answer is settled from the cache
+        assert_eq!(submit(&config, 2021, 7, 1, "43").unwrap(), Verdict::AlreadySolved);
+        assert_eq!(sent(&stub).len(), 1);
+    }
+
+    #[test]
+    fn too_high_and_too_low_narrow_the_bounds() {
+        let stub = stub(&[TOO_HIGH, TOO_LOW]);
+        let config = config(&stub, "bounds");
+        assert_eq!(submit(&config, 2021, 7, 2, "100").unwrap(), Verdict::TooHigh);
+        assert_eq!(submit(&config, 2021, 7, 2, "10").unwrap(), Verdict::TooLow);
+
+        let log = AttemptLog::load(config.cache.clone()).unwrap();
+        assert_eq!(log.bounds(2021, 7, 2), (Some(10), Some(100)));
+        assert_eq!(bounds_hint(log.bounds(2021, 7, 2)).unwrap(), "Hint: answer in (10, 100)");
+        assert_eq!(bounds_hint(log.bounds(2021, 7, 1)), None);
+
+        // answers outside the bounds never reach the server
+        assert_eq!(submit(&config, 2021, 7, 2, "150").unwrap(), Verdict::TooHigh);
+        assert_eq!(submit(&config, 2021, 7, 2, "3").unwrap(), Verdict::TooLow);
+        assert_eq!(sent(&stub).len(), 2);
+    }
+
+    #[test]
+    fn rate_limit_is_not_cached() {
+        let stub = stub(&[WAIT]);
+        let config = config(&stub, "wait");
+        assert_eq!(submit(&config, 2021, 8, 1, "7").unwrap(), Verdict::Wait(65));
+        assert!(AttemptLog::load(config.cache.clone()).unwrap().attempts.is_empty());
+    }
+
+    #[test]
+    fn duplicate_answer_is_replayed_from_cache() {
+        let stub = stub(&[WRONG]);
+        let config = config(&stub, "duplicate");
+        assert_eq!(submit(&config, 2021, 9, 1, "abc").unwrap(), Verdict::Wrong);
+        assert_eq!(submit(&config, 2021, 9, 1, "abc").unwrap(), Verdict::Wrong);
+        assert_eq!(sent(&stub).len(), 1);
+    }
+
+    #[test]
+    fn wait_times() {
+        assert_eq!(parse_wait_seconds("You have 38s left to wait."), Some(38));
+        assert_eq!(parse_wait_seconds("You have 1h 2m 3s left to wait."), Some(3723));
+        assert_eq!(parse_wait_seconds("You have 5é left to wait."), None);
+        assert_eq!(parse_wait_seconds("You have é left to wait."), None);
+    }
+}